@@ -16,6 +16,7 @@ use axum::{
     Router,
 };
 
+use html_ops::visitor::sanitizer_visitor::SanitizerConfig;
 use repository::RichTextRepositoryLike;
 
 use log::{error, info};
@@ -32,12 +33,23 @@ pub enum ServerError {
 #[derive(Clone)]
 pub struct Server {
     db: Arc<Mutex<dyn RichTextRepositoryLike<RichText = String>>>,
+    /// Applied to every rich text before it is stored.
+    sanitizer: Arc<SanitizerConfig>,
 }
 
 impl Server {
     pub fn new(db: impl RichTextRepositoryLike<RichText = String> + 'static) -> Self {
         let db = Arc::new(Mutex::new(db));
-        Self { db }
+        Self {
+            db,
+            sanitizer: Arc::new(SanitizerConfig::default()),
+        }
+    }
+
+    /// Replaces the default sanitizer configuration.
+    pub fn with_sanitizer(mut self, sanitizer: SanitizerConfig) -> Self {
+        self.sanitizer = Arc::new(sanitizer);
+        self
     }
 
     pub async fn run(&self, port: u16) -> Result<(), ServerError> {
@@ -67,9 +79,7 @@ impl Server {
                 "/rich-text",
                 get(routes::rich_text::get::handle_list_rich_text),
             )
-            .with_state(Self {
-                db: self.db.clone(),
-            }).layer(cors);
+            .with_state(self.clone()).layer(cors);
 
         // Run it with hyper on localhost:3001
         let addr: SocketAddr = SocketAddr::from(([127, 0, 0, 1], port));
@@ -1,11 +1,16 @@
 use std::collections::HashMap;
 
+use html_ops::rich_text::Provenance;
 use uuid::Uuid;
 
 use super::RichTextRepositoryLike;
 
 // Implement RichTextRepository for a HashMap
-pub struct HashMapRepository(HashMap<Uuid, String>);
+pub struct HashMapRepository {
+    rich_texts: HashMap<Uuid, String>,
+    translations: HashMap<(Uuid, String), String>,
+    provenance: HashMap<Uuid, Provenance>,
+}
 
 impl Default for HashMapRepository {
     fn default() -> Self {
@@ -15,7 +20,11 @@ impl Default for HashMapRepository {
 
 impl HashMapRepository {
     pub fn new() -> Self {
-        Self(HashMap::new())
+        Self {
+            rich_texts: HashMap::new(),
+            translations: HashMap::new(),
+            provenance: HashMap::new(),
+        }
     }
 }
 
@@ -23,21 +32,42 @@ impl RichTextRepositoryLike for HashMapRepository {
     type RichText = String;
 
     fn upsert(&mut self, id: &Uuid, rich_text: String) -> bool {
-        self.0.insert(*id, rich_text).is_some()
+        self.rich_texts.insert(*id, rich_text).is_some()
     }
 
     fn delete(&mut self, id: &Uuid) -> bool {
-        self.0.remove(id).is_some()
+        self.translations
+            .retain(|(translated_id, _), _| translated_id != id);
+        self.provenance.remove(id);
+        self.rich_texts.remove(id).is_some()
     }
 
     fn get(&mut self, id: &Uuid) -> Option<String> {
-        self.0.get(id).cloned()
+        self.rich_texts.get(id).cloned()
     }
 
     fn list(&mut self) -> Vec<(Uuid, String)> {
-        self.0
+        self.rich_texts
             .iter()
             .map(|entry| (*entry.0, entry.1.clone()))
             .collect()
     }
+
+    fn get_translation(&mut self, id: &Uuid, locale: &str) -> Option<String> {
+        self.translations.get(&(*id, locale.to_string())).cloned()
+    }
+
+    fn upsert_translation(&mut self, id: &Uuid, locale: &str, rich_text: String) -> bool {
+        self.translations
+            .insert((*id, locale.to_string()), rich_text)
+            .is_some()
+    }
+
+    fn get_provenance(&mut self, id: &Uuid) -> Option<Provenance> {
+        self.provenance.get(id).cloned()
+    }
+
+    fn upsert_provenance(&mut self, id: &Uuid, provenance: Provenance) -> bool {
+        self.provenance.insert(*id, provenance).is_some()
+    }
 }
@@ -1,4 +1,5 @@
 pub mod hashmap;
+use html_ops::rich_text::Provenance;
 use uuid::Uuid;
 
 use crate::rich_text::RichTextLike;
@@ -12,4 +13,9 @@ pub trait RichTextRepositoryLike: Send + Sync {
     fn upsert(&mut self, id: &Uuid, rich_text: Self::RichText) -> bool;
     fn delete(&mut self, id: &Uuid) -> bool;
     fn list(&mut self) -> Vec<(Uuid, Self::RichText)>;
+    fn get_translation(&mut self, id: &Uuid, locale: &str) -> Option<Self::RichText>;
+    fn upsert_translation(&mut self, id: &Uuid, locale: &str, rich_text: Self::RichText) -> bool;
+    /// Where the rich text was extracted from, if known.
+    fn get_provenance(&mut self, id: &Uuid) -> Option<Provenance>;
+    fn upsert_provenance(&mut self, id: &Uuid, provenance: Provenance) -> bool;
 }
@@ -3,6 +3,7 @@ pub mod post {
     use crate::Server;
 
     use axum::{extract::State, Json};
+    use html_ops::{rich_text::Provenance, visitor::sanitizer_visitor::sanitize_html};
     use log::info;
     use serde::{Deserialize, Serialize};
     use uuid::Uuid;
@@ -11,6 +12,13 @@ pub mod post {
     pub struct RichTextRequest {
         pub id: Uuid,
         pub rich_text: String,
+        /// Stores the rich text as the translation for this locale rather than as the
+        /// source.
+        #[serde(default)]
+        pub locale: Option<String>,
+        /// Where the source rich text was extracted from; ignored for translations.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub provenance: Option<Provenance>,
     }
 
     #[derive(Deserialize, Serialize, Debug)]
@@ -27,8 +35,18 @@ pub mod post {
             payload.id
         );
 
+        let rich_text = sanitize_html(&payload.rich_text, &state.sanitizer);
+
         let mut db = state.db.lock().unwrap();
-        let success = db.upsert(&payload.id, payload.rich_text);
+        let success = match payload.locale {
+            Some(locale) => db.upsert_translation(&payload.id, &locale, rich_text),
+            None => {
+                if let Some(provenance) = payload.provenance {
+                    db.upsert_provenance(&payload.id, provenance);
+                }
+                db.upsert(&payload.id, rich_text)
+            }
+        };
 
         Json(RichTextResponse { success })
     }
@@ -42,8 +60,10 @@ pub mod get {
         extract::{Path, Query, State},
         Json,
     };
+    use html_ops::rich_text::Provenance;
     use log::{info, warn};
     use serde::{Deserialize, Serialize};
+    use std::path::PathBuf;
     use uuid::Uuid;
 
     #[derive(Deserialize, Serialize, Debug)]
@@ -51,41 +71,82 @@ pub mod get {
         pub id: Uuid,
     }
 
+    #[derive(Deserialize, Serialize, Debug)]
+    pub struct LocaleQuery {
+        pub locale: Option<String>,
+    }
+
     #[derive(Deserialize, Serialize, Debug)]
     pub struct RichTextResponse {
         rich_text: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        provenance: Option<Provenance>,
     }
 
     #[axum::debug_handler]
     pub async fn handle_get_rich_text(
         State(state): State<Server>,
         Path(query): Path<RichTextQuery>,
+        Query(locale): Query<LocaleQuery>,
     ) -> Json<RichTextResponse> {
         info!("Handling GET request for rich text with id: {}", query.id);
-        let rich_text = state.db.lock().unwrap().get(&query.id);
+        let mut db = state.db.lock().unwrap();
+        // Fall back to the source when there is no translation for the locale
+        let rich_text = locale
+            .locale
+            .and_then(|locale| db.get_translation(&query.id, &locale))
+            .or_else(|| db.get(&query.id));
 
         match rich_text {
             Some(rich_text) => {
                 info!("Rich text found for id: {}", query.id);
-                Json(RichTextResponse { rich_text })
+                Json(RichTextResponse {
+                    rich_text,
+                    provenance: db.get_provenance(&query.id),
+                })
             }
             None => {
                 warn!("Rich text not found for id: {}", query.id);
                 Json(RichTextResponse {
                     rich_text: "".to_string(),
+                    provenance: None,
                 })
             }
         }
     }
 
+    #[derive(Deserialize, Serialize, Debug)]
+    pub struct ListQuery {
+        /// Only lists the rich texts extracted from this file, in page order.
+        pub file: Option<PathBuf>,
+    }
+
     #[derive(Deserialize, Serialize, Debug)]
     pub struct ListRichTextResponse {
         rich_texts: Vec<(Uuid, String)>,
     }
     #[axum::debug_handler]
-    pub async fn handle_list_rich_text(State(state): State<Server>) -> Json<ListRichTextResponse> {
+    pub async fn handle_list_rich_text(
+        State(state): State<Server>,
+        Query(query): Query<ListQuery>,
+    ) -> Json<ListRichTextResponse> {
         info!("Handling GET request for list of rich text");
-        let rich_texts = state.db.lock().unwrap().list();
+        let mut db = state.db.lock().unwrap();
+        let mut rich_texts = db.list();
+        if let Some(file) = query.file {
+            let mut ordered: Vec<_> = rich_texts
+                .into_iter()
+                .filter_map(|(id, rich_text)| {
+                    let provenance = db.get_provenance(&id).filter(|p| p.file == file)?;
+                    Some((provenance.order, id, rich_text))
+                })
+                .collect();
+            ordered.sort_by_key(|(order, _, _)| *order);
+            rich_texts = ordered
+                .into_iter()
+                .map(|(_, id, rich_text)| (id, rich_text))
+                .collect();
+        }
         Json(ListRichTextResponse { rich_texts })
     }
 }
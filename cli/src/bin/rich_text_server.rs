@@ -3,6 +3,7 @@ use std::path::PathBuf;
 
 use clap::{Parser, Subcommand};
 use color_eyre::eyre;
+use html_ops::{rich_text::RichTextMarkup, visitor::sanitizer_visitor::SanitizerConfig};
 use log::info;
 use rich_text_api::repository::hashmap::HashMapRepository;
 
@@ -13,7 +14,9 @@ async fn main() -> eyre::Result<()> {
     dotenv::dotenv().ok();
     env_logger::init();
 
-    let server = rich_text_api::Server::new(HashMapRepository::new());
+    let mut sanitizer = SanitizerConfig::default();
+    sanitizer.allow_rich_text(&RichTextMarkup::default());
+    let server = rich_text_api::Server::new(HashMapRepository::new()).with_sanitizer(sanitizer);
     server.run(3001).await?;
 
     Ok(())
@@ -1,25 +1,27 @@
 use html_ops::{
-    process_html_file,
+    process_html_file, process_markdown_file,
+    rich_text::RichTextBlock,
     visitor::{orphan_visitor::OrphanVisitor, rich_text_wrapper_visitor::RichTextWrapperVisitor},
-    walk::{process_html_files, process_html_files_async},
+    walk::{process_html_files_parallel, process_markdown_files},
 };
 use log::info;
 use reqwest;
 use rich_text_api::routes::rich_text::post::{RichTextRequest, RichTextResponse as PostResponse};
-use std::{collections::HashMap, fs, io, path::Path};
-use uuid::Uuid;
+use std::{fs, io, path::Path, sync::Mutex};
 
 const SYNC_WITH_DB: bool = true;
 
 async fn sync_with_database(
     client: &reqwest::Client,
     base_url: &str,
-    text_map: &HashMap<Uuid, String>,
+    blocks: &[RichTextBlock],
 ) -> color_eyre::Result<()> {
-    for (id, html) in text_map {
+    for block in blocks {
         let post_request = RichTextRequest {
-            id: *id,
-            rich_text: html.to_string(),
+            id: block.id,
+            rich_text: block.html.clone(),
+            locale: None,
+            provenance: Some(block.provenance.clone()),
         };
 
         let post_response: PostResponse = client
@@ -35,6 +37,14 @@ async fn sync_with_database(
     Ok(())
 }
 
+fn write_output(output_path: &Path, html: &str) -> io::Result<()> {
+    match output_path.parent() {
+        Some(parent) => fs::create_dir_all(parent)?,
+        None => return Err(io::Error::new(io::ErrorKind::Other, "Invalid output path")),
+    }
+    fs::write(output_path, html)
+}
+
 #[tokio::main]
 async fn main() -> color_eyre::Result<()> {
     color_eyre::install()?;
@@ -48,24 +58,35 @@ async fn main() -> color_eyre::Result<()> {
     let client = reqwest::Client::new();
     let base_url = "http://127.0.0.1:3001";
 
-    let mut repository: HashMap<Uuid, String> = HashMap::new();
+    let repository: Mutex<Vec<RichTextBlock>> = Mutex::new(Vec::new());
 
-    process_html_files(src_dir, |path, relative_path| {
-        let (html, visitor) = process_html_file(path, OrphanVisitor::new())?;
+    process_html_files_parallel(src_dir, |path, relative_path| {
+        let visitor = OrphanVisitor::new().with_file(relative_path);
+        let (html, visitor) = process_html_file(path, visitor)?;
         let output_path = dst_dir.join(relative_path);
 
-        match output_path.parent() {
-            Some(parent) => fs::create_dir_all(parent)?,
-            None => return Err(io::Error::new(io::ErrorKind::Other, "Invalid output path")),
-        }
+        repository
+            .lock()
+            .expect("repository lock poisoned")
+            .extend_from_slice(visitor.blocks());
+
+        write_output(&output_path, &html)?;
+        info!("Successfully processed file: {:?}", path);
+
+        Ok(())
+    })?;
+
+    let mut repository = repository.into_inner().expect("repository lock poisoned");
 
-        let text_map = visitor.html_map();
+    // Pages authored in Markdown are seeded the same way, and written out as HTML
+    process_markdown_files(src_dir, |path, relative_path| {
+        let visitor = OrphanVisitor::new().with_file(relative_path);
+        let (html, visitor) = process_markdown_file(path, visitor)?;
+        let output_path = dst_dir.join(relative_path).with_extension("html");
 
-        for (id, html) in text_map {
-            repository.insert(*id, html.clone());
-        }
+        repository.extend_from_slice(visitor.blocks());
 
-        fs::write(&output_path, html)?;
+        write_output(&output_path, &html)?;
         info!("Successfully processed file: {:?}", path);
 
         Ok(())
@@ -42,15 +42,129 @@
 //!
 //! Usage:
 //! ```
-//! cargo run -- process-html --src-dir <SOURCE_DIRECTORY> --dst-dir <DESTINATION_DIRECTORY> --api-endpoint <API_ENDPOINT>
+//! cargo run -- process-html --src-dir <SOURCE_DIRECTORY> --dst-dir <DESTINATION_DIRECTORY> [--api-endpoint <API_ENDPOINT>] [--output-format markdown]
+//! ```
+//!
+//! The extracted rich-text blocks are only posted to the API when `--api-endpoint` is given.
+//! With `--output-format markdown`, each page is written as a `.md` file instead, with the
+//! rich-text boundaries kept as HTML comments. With `--sanitize`, scripts, event handlers,
+//! unsafe URLs and other markup outside the default allowlist are removed before extraction.
+//! With `--glossary <FILE>`, the first occurrence of each term of a JSON glossary
+//! (`[{"term", "synonyms", "id"}]`) is linked as `<a data-glossary="id">`. With
+//! `--footnotes`, footnote references and notes are linked both ways and renumbered, and
+//! each note becomes a rich-text block tied to the block citing it. With `--base-path`,
+//! `--cdn-origin`, `--pretty-urls` or `--asset-manifest`, internal links are rewritten
+//! before extraction, and links to files missing from the source directory are logged.
+//! With `--preserve-nbsp`, non-breaking spaces in the extracted text are kept.
+//!
+//! ### export-translations
+//!
+//! Exports the rich-text blocks of processed HTML files to an XLIFF 2.0 (`.xlf`) or
+//! gettext (`.po`) file, picked from the output extension.
+//!
+//! Usage:
+//! ```
+//! cargo run -- export-translations --src-dir <PROCESSED_DIRECTORY> --output <FILE> --target-lang <LOCALE>
+//! ```
+//!
+//! ### import-translations
+//!
+//! Validates a translated file against the processed HTML files, then writes the localized
+//! pages to `<DESTINATION_DIRECTORY>/<LOCALE>` and optionally upserts the translations.
+//!
+//! Usage:
+//! ```
+//! cargo run -- import-translations --src-dir <PROCESSED_DIRECTORY> --input <FILE> --dst-dir <DESTINATION_DIRECTORY> [--api-endpoint <API_ENDPOINT>]
+//! ```
+//!
+//! ### lint-accessibility
+//!
+//! Reports accessibility problems in HTML files, as text or JSON, and fails if any of them
+//! is an error.
+//!
+//! Usage:
+//! ```
+//! cargo run -- lint-accessibility --src-dir <DIRECTORY> [--format json]
+//! ```
+//!
+//! ### readability-report
+//!
+//! Writes word and sentence counts, reading time and readability grades of every page and
+//! rich-text block of processed HTML files, as JSON or CSV picked from the output
+//! extension. Blocks above `--target-grade` are flagged.
+//!
+//! Usage:
+//! ```
+//! cargo run -- readability-report --src-dir <PROCESSED_DIRECTORY> --output <FILE> [--target-grade 6]
+//! ```
+//!
+//! ### find-duplicates
+//!
+//! Writes the groups of rich-text blocks that are exact or near-duplicates of each other
+//! across processed HTML files to a JSON file. With `--share-ids`, the files are rewritten
+//! in place so that every exact duplicate, and with `--include-near` every near-duplicate,
+//! records the id of its first copy in a `data-shared-id` attribute. Blocks keep their own
+//! ids, so that ids stay unique within each page.
+//!
+//! Usage:
+//! ```
+//! cargo run -- find-duplicates --src-dir <PROCESSED_DIRECTORY> --output <FILE> [--threshold 0.8] [--share-ids]
+//! ```
+//!
+//! ### media-manifest
+//!
+//! Writes every `img`, `picture` source, `video`, `audio` and `iframe` of the HTML files to
+//! a JSON manifest, with the resolved path, alt text, caption, dimensions and size on disk
+//! of each, followed by the missing files, oversized images and duplicated assets.
+//!
+//! Usage:
+//! ```
+//! cargo run -- media-manifest --src-dir <DIRECTORY> --output <FILE> [--max-image-kb 500]
+//! ```
+//!
+//! ### diff
+//!
+//! Compares two HTML files, or the HTML files of two directories by relative path, and
+//! writes the inserted, deleted, moved and modified nodes as JSON, or the new documents
+//! annotated with `<ins>` and `<del>`, picked from the output extension.
+//!
+//! Usage:
+//! ```
+//! cargo run -- diff --old assets/input --new assets/output --output <FILE.json | DIRECTORY>
 //! ```
 
-use std::path::PathBuf;
+use std::{collections::{BTreeMap, HashMap}, fs, path::PathBuf};
 
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use color_eyre::eyre;
+use html_ops::{
+    diff::{diff_dirs_with_markup, DocumentDiff},
+    process_html_file,
+    rc_dom::RcDom,
+    rich_text::{RichTextMarkup, RICH_TEXT_ID_ATTRIBUTE, RICH_TEXT_TAG},
+    translation::{Catalog, TranslationFormat},
+    visitor::{
+        accessibility_visitor::{lint_directory_with_markup, Severity},
+        duplicate_visitor::{shared_ids, DuplicateFinder, DuplicateOptions, SharedIdVisitor},
+        footnote_visitor::FootnoteVisitor,
+        glossary_visitor::{Glossary, GlossaryVisitor},
+        link_rewrite_visitor::{LinkRewriteRules, LinkRewriteVisitor},
+        media_visitor::{MediaIssue, MediaManifest, MediaOptions},
+        orphan_visitor::OrphanVisitor,
+        readability_visitor::{ReadabilityOptions, ReadabilityReport},
+        sanitizer_visitor::{SanitizerConfig, SanitizerVisitor},
+        style_class_visitor::{to_stylesheet, StyleClassVisitor},
+        word_cleanup_visitor::WordCleanupVisitor,
+        translation_visitor::TranslationVisitor,
+        NodeVisitor,
+    },
+    walk::process_html_files,
+    whitespace::WhitespaceOptions,
+};
 use log::info;
-use rich_text_api::repository::hashmap::HashMapRepository;
+use rich_text_api::{
+    repository::hashmap::HashMapRepository, routes::rich_text::post::RichTextRequest,
+};
 
 #[derive(Parser)]
 #[command(version, about, long_about = None)]
@@ -58,6 +172,79 @@ use rich_text_api::repository::hashmap::HashMapRepository;
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+    #[command(flatten)]
+    rich_text: RichTextArgs,
+}
+
+/// How rich-text blocks are marked up, for every command that writes or reads them.
+#[derive(Args)]
+struct RichTextArgs {
+    /// The tag of rich-text elements
+    #[arg(long, global = true, default_value = RICH_TEXT_TAG)]
+    rich_text_tag: String,
+    /// The attribute holding the id of a rich-text block, such as data-rt-id
+    #[arg(long, global = true, default_value = RICH_TEXT_ID_ATTRIBUTE)]
+    rich_text_id_attribute: String,
+    /// An attribute added to every new rich-text element, as NAME=VALUE; may be repeated
+    #[arg(long = "rich-text-attribute", global = true, value_parser = parse_attribute)]
+    rich_text_attributes: Vec<(String, String)>,
+}
+
+impl RichTextArgs {
+    fn markup(&self) -> RichTextMarkup {
+        self.rich_text_attributes.iter().fold(
+            RichTextMarkup::new(&self.rich_text_tag, &self.rich_text_id_attribute),
+            |markup, (name, value)| markup.with_attribute(name, value),
+        )
+    }
+}
+
+/// How internal links are rewritten by `process-html`.
+#[derive(Args)]
+struct LinkArgs {
+    /// The path prefix the site is served under, such as /docs
+    #[arg(long)]
+    base_path: Option<String>,
+    /// The origin assets are served from, such as https://cdn.example.com
+    #[arg(long)]
+    cdn_origin: Option<String>,
+    /// Rewrite links to page.html as page and to dir/index.html as dir/
+    #[arg(long)]
+    pretty_urls: bool,
+    /// A JSON object mapping asset paths to their fingerprinted names
+    #[arg(long)]
+    asset_manifest: Option<PathBuf>,
+}
+
+impl LinkArgs {
+    /// The rules to rewrite links with, or `None` when no rule is given.
+    fn rules(&self) -> std::io::Result<Option<LinkRewriteRules>> {
+        if self.base_path.is_none()
+            && self.cdn_origin.is_none()
+            && !self.pretty_urls
+            && self.asset_manifest.is_none()
+        {
+            return Ok(None);
+        }
+        let asset_manifest = match &self.asset_manifest {
+            Some(path) => LinkRewriteRules::load_asset_manifest(path)?,
+            None => HashMap::new(),
+        };
+        Ok(Some(LinkRewriteRules {
+            base_path: self.base_path.clone(),
+            cdn_origin: self.cdn_origin.clone(),
+            pretty_urls: self.pretty_urls,
+            asset_manifest,
+        }))
+    }
+}
+
+fn parse_attribute(attribute: &str) -> Result<(String, String), String> {
+    let (name, value) = attribute.split_once('=').unwrap_or((attribute, ""));
+    if name.is_empty() {
+        return Err(format!("expected NAME=VALUE, got {:?}", attribute));
+    }
+    Ok((name.to_string(), value.to_string()))
 }
 
 #[derive(Subcommand)]
@@ -75,9 +262,35 @@ enum Commands {
         /// The destination directory for processed HTML files
         #[arg(short, long)]
         dst_dir: PathBuf,
-        /// The API endpoint to use for the rich text database
+        /// The rich text API endpoint to post the extracted blocks to
         #[arg(short, long)]
-        api_endpoint: String,
+        api_endpoint: Option<String>,
+        /// The format of the processed files
+        #[arg(short, long, value_enum, default_value_t = OutputFormat::Html)]
+        output_format: OutputFormat,
+        /// Clean up HTML saved from Microsoft Word before anything else
+        #[arg(long)]
+        word: bool,
+        /// Remove markup outside the sanitizer allowlist before extracting rich text
+        #[arg(long)]
+        sanitize: bool,
+        /// A JSON glossary whose terms are linked on their first occurrence in each page
+        #[arg(short, long)]
+        glossary: Option<PathBuf>,
+        /// Link footnotes to their references, renumber them, and extract each note as a
+        /// rich-text block
+        #[arg(long)]
+        footnotes: bool,
+        /// Remove font and mso-* declarations from inline styles, move styles made of
+        /// repeated declarations into utility classes, and write the stylesheet of those
+        /// classes to this file
+        #[arg(long)]
+        extract_styles: Option<PathBuf>,
+        /// Keep non-breaking spaces in the extracted rich text instead of collapsing them
+        #[arg(long)]
+        preserve_nbsp: bool,
+        #[command(flatten)]
+        links: LinkArgs,
     },
     SeedDatabase {
         /// The source directory containing HTML files to process
@@ -90,6 +303,116 @@ enum Commands {
         #[arg(short, long)]
         api_endpoint: String,
     },
+    /// Exports the rich-text blocks of processed HTML files for translation
+    ExportTranslations {
+        /// The directory containing processed HTML files
+        #[arg(short, long)]
+        src_dir: PathBuf,
+        /// The XLIFF (.xlf) or PO (.po) file to write
+        #[arg(short, long)]
+        output: PathBuf,
+        /// The language of the source content
+        #[arg(long, default_value = "en")]
+        source_lang: String,
+        /// The language to translate into
+        #[arg(short, long)]
+        target_lang: String,
+    },
+    /// Imports a translated XLIFF or PO file and writes the localized HTML files
+    ImportTranslations {
+        /// The directory containing the processed HTML files that were exported
+        #[arg(short, long)]
+        src_dir: PathBuf,
+        /// The translated XLIFF (.xlf) or PO (.po) file
+        #[arg(short, long)]
+        input: PathBuf,
+        /// The destination directory, under which a directory per locale is written
+        #[arg(short, long)]
+        dst_dir: PathBuf,
+        /// The rich text API endpoint to upsert the translations to
+        #[arg(short, long)]
+        api_endpoint: Option<String>,
+    },
+    /// Reports accessibility problems in HTML files
+    LintAccessibility {
+        /// The directory containing the HTML files to check
+        #[arg(short, long)]
+        src_dir: PathBuf,
+        /// The format of the report
+        #[arg(short, long, value_enum, default_value_t = ReportFormat::Text)]
+        format: ReportFormat,
+    },
+    /// Reports the readability of the rich-text blocks of processed HTML files
+    ReadabilityReport {
+        /// The directory containing processed HTML files
+        #[arg(short, long)]
+        src_dir: PathBuf,
+        /// The JSON (.json) or CSV (.csv) file to write
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Flag blocks with a Flesch-Kincaid grade above this
+        #[arg(short, long)]
+        target_grade: Option<f64>,
+        /// Blocks with fewer words are never flagged
+        #[arg(long, default_value_t = 10)]
+        min_words: usize,
+    },
+    /// Finds duplicated rich-text blocks across processed HTML files
+    FindDuplicates {
+        /// The directory containing processed HTML files
+        #[arg(short, long)]
+        src_dir: PathBuf,
+        /// The JSON file to write the duplicate groups to
+        #[arg(short, long)]
+        output: PathBuf,
+        /// The similarity above which blocks are near-duplicates
+        #[arg(short, long, default_value_t = 0.8)]
+        threshold: f64,
+        /// Rewrite the files so that exact duplicates record the id of their first copy in
+        /// `data-shared-id`
+        #[arg(long)]
+        share_ids: bool,
+        /// With `--share-ids`, mark near-duplicates too
+        #[arg(long, requires = "share_ids")]
+        include_near: bool,
+    },
+    /// Writes a manifest of the images, videos, audio and iframes of HTML files
+    MediaManifest {
+        /// The directory containing the HTML files and their assets
+        #[arg(short, long)]
+        src_dir: PathBuf,
+        /// The JSON file to write the manifest to
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Report images larger than this, in kilobytes
+        #[arg(short, long, default_value_t = 500)]
+        max_image_kb: u64,
+    },
+    /// Reports the structural changes between two HTML files or directories
+    Diff {
+        /// The original file or directory
+        #[arg(long)]
+        old: PathBuf,
+        /// The changed file or directory
+        #[arg(long)]
+        new: PathBuf,
+        /// A JSON (.json) file for the changes, or else the annotated HTML file, or the
+        /// directory of annotated files when comparing directories
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    Html,
+    Markdown,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ReportFormat {
+    Text,
+    Json,
 }
 
 #[tokio::main]
@@ -99,21 +422,125 @@ async fn main() -> eyre::Result<()> {
     env_logger::init();
 
     let cli = Cli::parse();
+    let markup = cli.rich_text.markup();
 
     // You can check for the existence of subcommands, and if found use their
     // matches just as you would the top level cmd
     match &cli.command {
         Commands::RichTextServer { port } => {
-            let server = rich_text_api::Server::new(HashMapRepository::new());
+            let mut sanitizer = SanitizerConfig::default();
+            sanitizer.allow_rich_text(&markup);
+            let server =
+                rich_text_api::Server::new(HashMapRepository::new()).with_sanitizer(sanitizer);
             server.run(*port).await?;
         }
         Commands::ProcessHtml {
             src_dir,
             dst_dir,
             api_endpoint,
+            output_format,
+            word,
+            sanitize,
+            glossary,
+            footnotes,
+            extract_styles,
+            preserve_nbsp,
+            links,
         } => {
             info!("Processing HTML files in directory: {:?}", src_dir);
-            
+            let mut html_map = HashMap::new();
+            let mut provenance = HashMap::new();
+            let mut sanitizer = SanitizerConfig::default();
+            sanitizer.allow_rich_text(&markup);
+            let glossary = glossary.as_ref().map(Glossary::load).transpose()?;
+            let mut style_classes = BTreeMap::new();
+            let link_rules = links.rules()?;
+
+            process_html_files(src_dir, |path, relative_path| {
+                let mut dom = RcDom::from_file(path)?;
+                if *word {
+                    (dom.document, _) = WordCleanupVisitor::new().traverse(dom.document);
+                }
+                if *sanitize {
+                    (dom.document, _) = SanitizerVisitor::new(&sanitizer).traverse(dom.document);
+                }
+                if let Some(glossary) = glossary.as_ref() {
+                    (dom.document, _) = GlossaryVisitor::new(glossary).traverse(dom.document);
+                }
+                if extract_styles.is_some() {
+                    let mut visitor = StyleClassVisitor::new();
+                    (dom.document, _) = visitor.traverse(dom.document);
+                    visitor.extract_classes();
+                    style_classes.extend(visitor.classes().clone());
+                }
+                if let Some(rules) = link_rules.as_ref() {
+                    let mut visitor = LinkRewriteVisitor::new(rules, src_dir, relative_path);
+                    (dom.document, _) = visitor.traverse(dom.document);
+                }
+                let mut visitor = OrphanVisitor::new()
+                    .with_rich_text_markup(markup.clone())
+                    .with_whitespace_options(WhitespaceOptions {
+                        preserve_nbsp: *preserve_nbsp,
+                    })
+                    .with_file(relative_path);
+                let (mut document, _) = visitor.traverse(dom.document);
+                html_map.extend(visitor.html_map().clone());
+                provenance.extend(
+                    visitor
+                        .blocks()
+                        .iter()
+                        .map(|block| (block.id, block.provenance.clone())),
+                );
+                if *footnotes {
+                    let mut visitor = FootnoteVisitor::new().with_rich_text_markup(markup.clone());
+                    (document, _) = visitor.traverse(document);
+                    visitor.link_notes();
+                    html_map.extend(visitor.html_map().clone());
+                }
+
+                let (output_path, contents) = match output_format {
+                    OutputFormat::Html => (dst_dir.join(relative_path), document.to_html_string()),
+                    OutputFormat::Markdown => (
+                        dst_dir.join(relative_path).with_extension("md"),
+                        document.to_markdown_with_markup(&markup),
+                    ),
+                };
+                if let Some(parent) = output_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(&output_path, contents)?;
+                info!("Successfully processed file: {:?}", path);
+                Ok(())
+            })?;
+
+            if let Some(extract_styles) = extract_styles {
+                fs::write(extract_styles, to_stylesheet(&style_classes))?;
+                info!(
+                    "Wrote {} utility classes to {:?}",
+                    style_classes.len(),
+                    extract_styles
+                );
+            }
+
+            if let Some(api_endpoint) = api_endpoint {
+                let client = reqwest::Client::new();
+                let count = html_map.len();
+                for (id, rich_text) in html_map {
+                    let request = RichTextRequest {
+                        id,
+                        rich_text,
+                        locale: None,
+                        provenance: provenance.remove(&id),
+                    };
+                    client
+                        .post(api_endpoint)
+                        .json(&request)
+                        .send()
+                        .await?
+                        .error_for_status()?;
+                }
+                info!("Posted {} rich-text blocks", count);
+            }
         }
         Commands::SeedDatabase {
             src_dir,
@@ -122,6 +549,214 @@ async fn main() -> eyre::Result<()> {
         } => {
            
         }
+        Commands::ExportTranslations {
+            src_dir,
+            output,
+            source_lang,
+            target_lang,
+        } => {
+            let catalog = Catalog::from_dir_with_markup(src_dir, &markup)?;
+            let contents = match TranslationFormat::from_path(output)? {
+                TranslationFormat::Xliff => catalog.to_xliff(source_lang, target_lang),
+                TranslationFormat::Po => catalog.to_po(target_lang),
+            };
+            fs::write(output, contents)?;
+            info!(
+                "Exported {} translation units to {:?}",
+                catalog.units().len(),
+                output
+            );
+        }
+        Commands::ImportTranslations {
+            src_dir,
+            input,
+            dst_dir,
+            api_endpoint,
+        } => {
+            let catalog = Catalog::from_dir_with_markup(src_dir, &markup)?;
+            let translations = catalog.import_file(input)?;
+            let locale_dir = dst_dir.join(&translations.locale);
+
+            process_html_files(src_dir, |path, relative_path| {
+                let visitor =
+                    TranslationVisitor::new(&translations).with_rich_text_markup(markup.clone());
+                let (html, visitor) = process_html_file(path, visitor)?;
+                let output_path = locale_dir.join(relative_path);
+                if let Some(parent) = output_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::write(&output_path, html)?;
+                info!(
+                    "Translated {} blocks of {:?} ({} untranslated)",
+                    visitor.translated(),
+                    relative_path,
+                    visitor.untranslated().len()
+                );
+                Ok(())
+            })?;
+
+            if let Some(api_endpoint) = api_endpoint {
+                let client = reqwest::Client::new();
+                for (id, rich_text) in translations.units.iter() {
+                    let request = RichTextRequest {
+                        id: *id,
+                        rich_text: rich_text.clone(),
+                        locale: Some(translations.locale.clone()),
+                        provenance: None,
+                    };
+                    client
+                        .post(api_endpoint)
+                        .json(&request)
+                        .send()
+                        .await?
+                        .error_for_status()?;
+                }
+                info!(
+                    "Upserted {} {} translations",
+                    translations.units.len(),
+                    translations.locale
+                );
+            }
+        }
+        Commands::LintAccessibility { src_dir, format } => {
+            let diagnostics = lint_directory_with_markup(src_dir, &markup)?;
+            match format {
+                ReportFormat::Text => {
+                    for diagnostic in diagnostics.iter() {
+                        println!("{}", diagnostic);
+                    }
+                }
+                ReportFormat::Json => println!("{}", serde_json::to_string_pretty(&diagnostics)?),
+            }
+
+            let errors = diagnostics
+                .iter()
+                .filter(|diagnostic| diagnostic.severity == Severity::Error)
+                .count();
+            if errors > 0 {
+                eyre::bail!(
+                    "{} accessibility errors and {} warnings",
+                    errors,
+                    diagnostics.len() - errors
+                );
+            }
+        }
+        Commands::ReadabilityReport {
+            src_dir,
+            output,
+            target_grade,
+            min_words,
+        } => {
+            let options = ReadabilityOptions {
+                target_grade: *target_grade,
+                min_words: *min_words,
+                rich_text: markup,
+                ..Default::default()
+            };
+            let report = ReadabilityReport::from_dir(src_dir, &options)?;
+            let contents = match output.extension().and_then(|ext| ext.to_str()) {
+                Some("csv") => report.to_csv(),
+                Some("json") => serde_json::to_string_pretty(&report)?,
+                _ => eyre::bail!("unsupported report file {:?}", output),
+            };
+            fs::write(output, contents)?;
+
+            let flagged = report.blocks.iter().filter(|block| block.above_target).count();
+            info!(
+                "Wrote readability of {} pages to {:?}, {} blocks above the target grade",
+                report.pages.len(),
+                output,
+                flagged
+            );
+        }
+        Commands::FindDuplicates {
+            src_dir,
+            output,
+            threshold,
+            share_ids,
+            include_near,
+        } => {
+            let options = DuplicateOptions {
+                threshold: *threshold,
+                rich_text: markup.clone(),
+                ..Default::default()
+            };
+            let groups = DuplicateFinder::from_dir(src_dir, options)?.groups();
+            fs::write(output, serde_json::to_string_pretty(&groups)?)?;
+            info!("Wrote {} duplicate groups to {:?}", groups.len(), output);
+
+            if *share_ids {
+                let ids = shared_ids(&groups, *include_near);
+                process_html_files(src_dir, |path, _| {
+                    let dom = RcDom::from_file(path)?;
+                    let mut visitor =
+                        SharedIdVisitor::new(&ids).with_rich_text_markup(markup.clone());
+                    let (document, _) = visitor.traverse(dom.document);
+                    if visitor.marked() > 0 {
+                        fs::write(path, document.to_html_string())?;
+                        info!("Marked {} duplicates in {:?}", visitor.marked(), path);
+                    }
+                    Ok(())
+                })?;
+            }
+        }
+        Commands::MediaManifest {
+            src_dir,
+            output,
+            max_image_kb,
+        } => {
+            let options = MediaOptions {
+                max_image_size: max_image_kb * 1024,
+            };
+            let manifest = MediaManifest::from_dir(src_dir, &options)?;
+            fs::write(output, serde_json::to_string_pretty(&manifest)?)?;
+
+            let missing = manifest
+                .issues
+                .iter()
+                .filter(|issue| matches!(issue, MediaIssue::Missing { .. }))
+                .count();
+            info!(
+                "Wrote {} media entries to {:?}, {} missing, {} other issues",
+                manifest.entries.len(),
+                output,
+                missing,
+                manifest.issues.len() - missing
+            );
+        }
+        Commands::Diff { old, new, output } => {
+            let as_json = output.extension().and_then(|ext| ext.to_str()) == Some("json");
+            if old.is_dir() && new.is_dir() {
+                let diffs = diff_dirs_with_markup(old, new, &markup)?;
+                if as_json {
+                    fs::write(output, serde_json::to_string_pretty(&diffs)?)?;
+                } else {
+                    for file_diff in diffs.iter() {
+                        if let Some(diff) = file_diff.diff.as_ref() {
+                            let output_path = output.join(&file_diff.file);
+                            if let Some(parent) = output_path.parent() {
+                                fs::create_dir_all(parent)?;
+                            }
+                            fs::write(output_path, diff.annotated().to_html_string())?;
+                        }
+                    }
+                }
+                info!("{} files differ between {:?} and {:?}", diffs.len(), old, new);
+            } else if old.is_file() && new.is_file() {
+                let old_dom = RcDom::from_file(old)?;
+                let new_dom = RcDom::from_file(new)?;
+                let diff = DocumentDiff::with_markup(&old_dom.document, &new_dom.document, &markup);
+                let contents = if as_json {
+                    serde_json::to_string_pretty(&diff)?
+                } else {
+                    diff.annotated().to_html_string()
+                };
+                fs::write(output, contents)?;
+                info!("{} changes between {:?} and {:?}", diff.changes.len(), old, new);
+            } else {
+                eyre::bail!("{:?} and {:?} must both be files or both be directories", old, new);
+            }
+        }
     }
 
     Ok(())
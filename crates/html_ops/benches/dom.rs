@@ -0,0 +1,128 @@
+//! Parse + traverse + serialize throughput of `RcDom` against `ArenaDom`, walking the
+//! nodes directly and running a [`NodeVisitor`] over them.
+//!
+//! Runs over every `.html` file under `assets/` (or the directory given as the first
+//! argument) and prints MB/s for each DOM:
+//!
+//! ```sh
+//! cargo bench -p html_ops --bench dom -- [DIR]
+//! ```
+
+use html_ops::{
+    arena_dom::{ArenaData, ArenaDom},
+    rc_dom::{NodeData, RcDom},
+    visitor::{outline_visitor::OutlineVisitor, NodeVisitor},
+    walk::process_html_files,
+};
+use std::{
+    env, fs,
+    hint::black_box,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+const MIN_DURATION: Duration = Duration::from_secs(2);
+
+fn rc_dom(html: &str) -> (usize, String) {
+    let dom = RcDom::from_str(html);
+    let mut text = 0;
+    let mut stack = vec![dom.document.clone()];
+    while let Some(node) = stack.pop() {
+        if let NodeData::Text { ref contents } = node.data {
+            text += contents.borrow().len();
+        }
+        stack.extend(node.children.borrow().iter().cloned());
+    }
+    (text, dom.document.inner_html())
+}
+
+fn arena_dom(html: &str) -> (usize, String) {
+    let dom = ArenaDom::from_str(html);
+    let text = dom
+        .descendants(dom.document())
+        .map(|id| match dom[id].data {
+            ArenaData::Text { ref contents } => contents.len(),
+            _ => 0,
+        })
+        .sum();
+    (text, dom.to_html_string())
+}
+
+fn rc_dom_visitor(html: &str) -> (usize, String) {
+    let dom = RcDom::from_str(html);
+    let mut visitor = OutlineVisitor::new();
+    let (document, _) = visitor.traverse(dom.document.clone());
+    (visitor.outline().len(), document.inner_html())
+}
+
+fn arena_dom_visitor(html: &str) -> (usize, String) {
+    let mut dom = ArenaDom::from_str(html);
+    let mut visitor = OutlineVisitor::new();
+    dom.traverse(&mut visitor);
+    (visitor.outline().len(), dom.to_html_string())
+}
+
+/// Runs `f` over the whole corpus until `MIN_DURATION` has passed; returns MB/s.
+fn throughput(corpus: &[String], f: fn(&str) -> (usize, String)) -> f64 {
+    let bytes: usize = corpus.iter().map(String::len).sum();
+    let start = Instant::now();
+    let mut rounds = 0;
+    while start.elapsed() < MIN_DURATION {
+        for html in corpus {
+            black_box(f(black_box(html)));
+        }
+        rounds += 1;
+    }
+    (bytes * rounds) as f64 / start.elapsed().as_secs_f64() / 1e6
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`; anything else is the corpus directory.
+    let dir = env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with("--"))
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../assets"));
+
+    let mut corpus = Vec::new();
+    process_html_files(&dir, |path, _| {
+        corpus.push(fs::read_to_string(path)?);
+        Ok(())
+    })
+    .expect("failed to read corpus");
+    assert!(!corpus.is_empty(), "no .html files under {}", dir.display());
+
+    for html in &corpus {
+        assert_eq!(rc_dom(html), arena_dom(html), "the DOMs disagree");
+        assert_eq!(
+            rc_dom_visitor(html),
+            arena_dom_visitor(html),
+            "the visited DOMs disagree"
+        );
+    }
+
+    let bytes: usize = corpus.iter().map(String::len).sum();
+    println!(
+        "{} files, {} KB from {}",
+        corpus.len(),
+        bytes / 1000,
+        dir.display()
+    );
+    for (label, rc_fn, arena_fn) in [
+        (
+            "walk",
+            rc_dom as fn(&str) -> (usize, String),
+            arena_dom as fn(&str) -> _,
+        ),
+        ("visitor", rc_dom_visitor, arena_dom_visitor),
+    ] {
+        let rc = throughput(&corpus, rc_fn);
+        let arena = throughput(&corpus, arena_fn);
+        println!("{label}:");
+        println!("  RcDom:    {rc:8.2} MB/s");
+        println!(
+            "  ArenaDom: {arena:8.2} MB/s ({:+.1}%)",
+            (arena / rc - 1.0) * 100.0
+        );
+    }
+}
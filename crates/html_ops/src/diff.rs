@@ -0,0 +1,923 @@
+//! Structural diff of two documents.
+//!
+//! The children of each pair of matched nodes are aligned with a longest common
+//! subsequence over their keys: the id of rich-text elements, the `id` attribute of other
+//! elements, or else the element name. Keyed nodes found at another position, under the
+//! same or another parent, are reported as moved, and matched text nodes are compared
+//! word by word.
+//!
+//! Besides the list of changes, a diff holds a copy of the new document annotated with
+//! `<ins>` and `<del>` elements, for review in a browser.
+
+use crate::{
+    rc_dom::{
+        builder::{self, element, ElementBuilder},
+        Handle, Node, NodeData, RcDom,
+    },
+    rich_text::RichTextMarkup,
+    util::{attribute, node_path, parent},
+    walk::process_html_files,
+    whitespace::{normalize_text, WhitespaceOptions},
+};
+use html5ever::Attribute;
+use serde::Serialize;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    io,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+/// The attribute marking moved and modified elements in the annotated document.
+pub const DIFF_ATTRIBUTE: &str = "data-diff";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Inserted,
+    Deleted,
+    Moved,
+    Modified,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AttributeChange {
+    pub name: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EditOp {
+    Equal,
+    Insert,
+    Delete,
+}
+
+/// A run of words kept, inserted or deleted in a text.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WordEdit {
+    pub op: EditOp,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Change {
+    pub kind: ChangeKind,
+    /// The path of the element in the new document, or in the old one for deletions. Text
+    /// changes have the path of their parent.
+    pub path: String,
+    /// The id of the rich-text element changed or containing the change.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rich_text: Option<String>,
+    /// The HTML of a deleted node.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub before: Option<String>,
+    /// The HTML of an inserted node.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attributes: Vec<AttributeChange>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<WordEdit>,
+}
+
+impl Change {
+    fn new(kind: ChangeKind, path: String, rich_text: Option<String>) -> Self {
+        Change {
+            kind,
+            path,
+            rich_text,
+            before: None,
+            after: None,
+            attributes: Vec::new(),
+            words: Vec::new(),
+        }
+    }
+}
+
+/// The changes between two documents.
+#[derive(Debug, Serialize)]
+pub struct DocumentDiff {
+    pub changes: Vec<Change>,
+    #[serde(skip)]
+    annotated: Handle,
+}
+
+impl DocumentDiff {
+    pub fn new(old: &Handle, new: &Handle) -> Self {
+        Self::with_markup(old, new, &RichTextMarkup::default())
+    }
+
+    /// Like [`DocumentDiff::new`], for documents whose rich-text blocks use other markup.
+    pub fn with_markup(old: &Handle, new: &Handle, markup: &RichTextMarkup) -> Self {
+        let mut differ = Differ {
+            markup,
+            old_keyed: keyed_nodes(markup, old),
+            new_keyed: keyed_nodes(markup, new),
+            changes: Vec::new(),
+            moved: HashSet::new(),
+            moved_away: Vec::new(),
+        };
+        let annotated = match new.data {
+            NodeData::Document => {
+                let document = Node::new(NodeData::Document);
+                let children = differ.diff_children(old, new, None);
+                builder::set_children(&document, children);
+                document
+            }
+            _ => {
+                let mut nodes = differ.diff_node(old, new, None);
+                assert_eq!(nodes.len(), 1, "elements are annotated in place");
+                nodes.remove(0)
+            }
+        };
+        differ.resolve_moved_away();
+        DocumentDiff {
+            changes: differ.changes,
+            annotated,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// A copy of the new document, with inserted nodes and words in `<ins>`, deleted ones
+    /// in `<del>`, and moved and modified elements marked with a `data-diff` attribute.
+    pub fn annotated(&self) -> &Handle {
+        &self.annotated
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileStatus {
+    Added,
+    Removed,
+    Modified,
+}
+
+/// The changes of a file between two directories.
+#[derive(Debug, Serialize)]
+pub struct FileDiff {
+    pub file: PathBuf,
+    pub status: FileStatus,
+    #[serde(flatten)]
+    pub diff: Option<DocumentDiff>,
+}
+
+/// Compares the HTML files of two directories by relative path. Unchanged files are left
+/// out.
+pub fn diff_dirs<P: AsRef<Path>, Q: AsRef<Path>>(
+    old_dir: P,
+    new_dir: Q,
+) -> io::Result<Vec<FileDiff>> {
+    diff_dirs_with_markup(old_dir, new_dir, &RichTextMarkup::default())
+}
+
+/// Like [`diff_dirs`], for documents whose rich-text blocks use other markup.
+pub fn diff_dirs_with_markup<P: AsRef<Path>, Q: AsRef<Path>>(
+    old_dir: P,
+    new_dir: Q,
+    markup: &RichTextMarkup,
+) -> io::Result<Vec<FileDiff>> {
+    let (old_dir, new_dir) = (old_dir.as_ref(), new_dir.as_ref());
+    let mut old_files = BTreeSet::new();
+    process_html_files(old_dir, |_, relative_path| {
+        old_files.insert(relative_path.to_path_buf());
+        Ok(())
+    })?;
+    let mut new_files = BTreeSet::new();
+    process_html_files(new_dir, |_, relative_path| {
+        new_files.insert(relative_path.to_path_buf());
+        Ok(())
+    })?;
+
+    let mut diffs = Vec::new();
+    for file in old_files.union(&new_files) {
+        let (status, diff) = match (old_files.contains(file), new_files.contains(file)) {
+            (true, true) => {
+                let old = RcDom::from_file(old_dir.join(file))?;
+                let new = RcDom::from_file(new_dir.join(file))?;
+                let diff = DocumentDiff::with_markup(&old.document, &new.document, markup);
+                if diff.is_empty() {
+                    continue;
+                }
+                (FileStatus::Modified, Some(diff))
+            }
+            (false, true) => (FileStatus::Added, None),
+            _ => (FileStatus::Removed, None),
+        };
+        diffs.push(FileDiff {
+            file: file.clone(),
+            status,
+            diff,
+        });
+    }
+    Ok(diffs)
+}
+
+/// How a node is matched with the nodes of the other document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Key {
+    /// Only matches the node with the same id, wherever it is.
+    Id(String),
+    /// Matches any sibling at the same position of the alignment.
+    Name(String),
+}
+
+struct Differ<'a> {
+    markup: &'a RichTextMarkup,
+    old_keyed: HashMap<String, Handle>,
+    new_keyed: HashMap<String, Handle>,
+    changes: Vec<Change>,
+    /// The keys of the nodes reported as moved.
+    moved: HashSet<String>,
+    /// Deletions of keyed nodes found elsewhere in the new document, kept until it is known
+    /// whether they were reported as moved: the key, the index the change belongs at, the
+    /// change and its `<del>` in the annotated document.
+    moved_away: Vec<(String, usize, Change, Handle)>,
+}
+
+impl Differ<'_> {
+    /// Compares two matched nodes, returning the annotated copy of the new one.
+    fn diff_node(&mut self, old: &Handle, new: &Handle, rich_text: Option<&str>) -> Vec<Handle> {
+        match (&old.data, &new.data) {
+            (NodeData::Text { contents: before }, NodeData::Text { contents: after }) => {
+                self.diff_text(&before.borrow(), &after.borrow(), new, rich_text)
+            }
+            (
+                NodeData::Element {
+                    attrs: old_attrs, ..
+                },
+                NodeData::Element {
+                    name,
+                    attrs: new_attrs,
+                    ..
+                },
+            ) => {
+                let own_id = self.markup.id_of(new);
+                let rich_text = own_id.as_deref().or(rich_text);
+
+                let attributes = diff_attributes(&old_attrs.borrow(), &new_attrs.borrow());
+                let mut attrs = new_attrs.borrow().clone();
+                if !attributes.is_empty() {
+                    let mut change = Change::new(
+                        ChangeKind::Modified,
+                        node_path(new),
+                        rich_text.map(str::to_string),
+                    );
+                    change.attributes = attributes;
+                    self.changes.push(change);
+                    attrs.push(builder::attribute(DIFF_ATTRIBUTE, "modified"));
+                }
+
+                let children = self.diff_children(old, new, rich_text);
+                vec![ElementBuilder::new(name.clone())
+                    .attrs(attrs)
+                    .child(children)
+                    .build()]
+            }
+            _ => vec![new.deep_clone()],
+        }
+    }
+
+    /// Aligns the children of two matched nodes, returning the annotated children.
+    fn diff_children(
+        &mut self,
+        old: &Handle,
+        new: &Handle,
+        rich_text: Option<&str>,
+    ) -> Vec<Handle> {
+        let old_children: Vec<Handle> = old
+            .children
+            .borrow()
+            .iter()
+            .filter(|child| is_significant(child))
+            .cloned()
+            .collect();
+        let new_all = new.children.borrow().clone();
+        let new_children: Vec<(usize, Handle)> = new_all
+            .iter()
+            .cloned()
+            .enumerate()
+            .filter(|(_, child)| is_significant(child))
+            .collect();
+
+        let old_keys: Vec<Key> = old_children
+            .iter()
+            .map(|child| key(self.markup, child))
+            .collect();
+        let new_keys: Vec<Key> = new_children
+            .iter()
+            .map(|(_, child)| key(self.markup, child))
+            .collect();
+        let ops = align(&old_keys, &new_keys, |a, b| a == b);
+
+        let mut annotated = Vec::new();
+        let mut next_child = 0;
+        for op in ops {
+            match op {
+                Op::Equal(i, j) => {
+                    let (index, ref child) = new_children[j];
+                    copy_between(&new_all, &mut next_child, index, &mut annotated);
+                    next_child = index + 1;
+                    annotated.extend(self.diff_node(&old_children[i], child, rich_text));
+                }
+                Op::Delete(i) => {
+                    let child = &old_children[i];
+                    let mut change = Change::new(
+                        ChangeKind::Deleted,
+                        change_path(child),
+                        rich_text_of(self.markup, child, rich_text),
+                    );
+                    change.before = Some(child.outer_html());
+                    let deleted = wrap("del", None, vec![child.deep_clone()]);
+                    annotated.push(deleted.clone());
+                    match key(self.markup, child) {
+                        // Reported where it was moved to, if it is reached
+                        Key::Id(id) if self.new_keyed.contains_key(&id) => {
+                            let index = self.changes.len();
+                            self.moved_away.push((id, index, change, deleted));
+                        }
+                        _ => self.changes.push(change),
+                    }
+                }
+                Op::Insert(j) => {
+                    let (index, ref child) = new_children[j];
+                    copy_between(&new_all, &mut next_child, index, &mut annotated);
+                    next_child = index + 1;
+                    let moved_from = match key(self.markup, child) {
+                        Key::Id(id) => self.old_keyed.get(&id).cloned(),
+                        Key::Name(_) => None,
+                    };
+                    match moved_from {
+                        Some(original) => {
+                            annotated.push(self.diff_moved(&original, child, rich_text));
+                        }
+                        None => {
+                            let mut change = Change::new(
+                                ChangeKind::Inserted,
+                                change_path(child),
+                                rich_text_of(self.markup, child, rich_text),
+                            );
+                            change.after = Some(child.outer_html());
+                            self.changes.push(change);
+                            let copy = self.copy_inserted(child, rich_text);
+                            annotated.push(wrap("ins", None, vec![copy]));
+                        }
+                    }
+                }
+            }
+        }
+        copy_between(&new_all, &mut next_child, new_all.len(), &mut annotated);
+        annotated
+    }
+
+    /// Reports a keyed node as moved, returning its annotated copy.
+    fn diff_moved(&mut self, original: &Handle, new: &Handle, rich_text: Option<&str>) -> Handle {
+        if let Key::Id(id) = key(self.markup, new) {
+            self.moved.insert(id);
+        }
+        self.changes.push(Change::new(
+            ChangeKind::Moved,
+            change_path(new),
+            rich_text_of(self.markup, new, rich_text),
+        ));
+        let nodes = self.diff_node(original, new, rich_text);
+        wrap("ins", Some("moved"), nodes)
+    }
+
+    /// Copies an inserted node, reporting the keyed nodes moved into it.
+    fn copy_inserted(&mut self, node: &Handle, rich_text: Option<&str>) -> Handle {
+        if let Key::Id(id) = key(self.markup, node) {
+            if let Some(original) = self.old_keyed.get(&id).cloned() {
+                return self.diff_moved(&original, node, rich_text);
+            }
+        }
+        let NodeData::Element {
+            ref name,
+            ref attrs,
+            ..
+        } = node.data
+        else {
+            return node.deep_clone();
+        };
+        let own_id = self.markup.id_of(node);
+        let rich_text = own_id.as_deref().or(rich_text);
+        let children: Vec<Handle> = node
+            .children
+            .borrow()
+            .iter()
+            .map(|child| self.copy_inserted(child, rich_text))
+            .collect();
+        ElementBuilder::new(name.clone())
+            .attrs(attrs.borrow().clone())
+            .child(children)
+            .build()
+    }
+
+    /// Drops the deletions of keyed nodes reported as moved, from the changes and the
+    /// annotated document, and reports the others as deleted.
+    fn resolve_moved_away(&mut self) {
+        for (id, index, change, deleted) in std::mem::take(&mut self.moved_away).into_iter().rev() {
+            if self.moved.contains(&id) {
+                detach(&deleted);
+            } else {
+                self.changes.insert(index, change);
+            }
+        }
+    }
+
+    /// Compares two texts word by word, ignoring differences in whitespace.
+    fn diff_text(
+        &mut self,
+        before: &str,
+        after: &str,
+        new: &Handle,
+        rich_text: Option<&str>,
+    ) -> Vec<Handle> {
+        let options = WhitespaceOptions::default();
+        if normalize_text(before, &options) == normalize_text(after, &options) {
+            return vec![builder::text(after)];
+        }
+
+        let old_tokens = tokenize(before);
+        let new_tokens = tokenize(after);
+        let same = |a: &&str, b: &&str| a == b || (a.trim().is_empty() && b.trim().is_empty());
+        let mut words: Vec<WordEdit> = Vec::new();
+        for op in align(&old_tokens, &new_tokens, same) {
+            let (op, text) = match op {
+                Op::Equal(_, j) => (EditOp::Equal, new_tokens[j]),
+                Op::Insert(j) => (EditOp::Insert, new_tokens[j]),
+                Op::Delete(i) => (EditOp::Delete, old_tokens[i]),
+            };
+            match words.last_mut() {
+                Some(last) if last.op == op => last.text.push_str(text),
+                _ => words.push(WordEdit {
+                    op,
+                    text: text.to_string(),
+                }),
+            }
+        }
+
+        let annotated = words
+            .iter()
+            .map(|edit| match edit.op {
+                EditOp::Equal => builder::text(&edit.text),
+                EditOp::Insert => wrap("ins", None, vec![builder::text(&edit.text)]),
+                EditOp::Delete => wrap("del", None, vec![builder::text(&edit.text)]),
+            })
+            .collect();
+        let mut change = Change::new(
+            ChangeKind::Modified,
+            change_path(new),
+            rich_text.map(str::to_string),
+        );
+        change.words = words;
+        self.changes.push(change);
+        annotated
+    }
+}
+
+/// Copies the children from `next` up to `end`, which are the whitespace skipped by the
+/// alignment.
+fn copy_between(children: &[Handle], next: &mut usize, end: usize, copies: &mut Vec<Handle>) {
+    while *next < end {
+        copies.push(children[*next].deep_clone());
+        *next += 1;
+    }
+}
+
+enum Op {
+    Equal(usize, usize),
+    Delete(usize),
+    Insert(usize),
+}
+
+/// Aligns two sequences along a shortest edit script, with the deletions of each run of
+/// changes before its insertions.
+///
+/// The script is found with the linear-space variant of Myers' algorithm: the middle snake
+/// of the two sequences splits them in two halves, which are aligned in turn. This takes
+/// O((n + m) D) time for D edits and O(n + m) space.
+fn align<T>(old: &[T], new: &[T], same: impl Fn(&T, &T) -> bool) -> Vec<Op> {
+    let mut script = Vec::with_capacity(old.len().max(new.len()));
+    let max_d = (old.len() + new.len()).div_ceil(2) + 1;
+    let mut forward = vec![0; 2 * max_d + 1];
+    let mut backward = vec![0; 2 * max_d + 1];
+    let aligner = Aligner {
+        old,
+        new,
+        same: &same,
+        offset: max_d as isize,
+    };
+    aligner.conquer(
+        0..old.len(),
+        0..new.len(),
+        &mut forward,
+        &mut backward,
+        &mut script,
+    );
+
+    let mut ops = Vec::with_capacity(script.len());
+    let mut inserts = Vec::new();
+    for op in script {
+        match op {
+            Op::Delete(_) => ops.push(op),
+            Op::Insert(_) => inserts.push(op),
+            Op::Equal(..) => {
+                ops.append(&mut inserts);
+                ops.push(op);
+            }
+        }
+    }
+    ops.append(&mut inserts);
+    ops
+}
+
+struct Aligner<'a, T, F> {
+    old: &'a [T],
+    new: &'a [T],
+    same: &'a F,
+    /// Added to a diagonal `k = x - y` to index the furthest-reaching paths.
+    offset: isize,
+}
+
+impl<T, F: Fn(&T, &T) -> bool> Aligner<'_, T, F> {
+    fn conquer(
+        &self,
+        mut old: Range<usize>,
+        mut new: Range<usize>,
+        forward: &mut [usize],
+        backward: &mut [usize],
+        ops: &mut Vec<Op>,
+    ) {
+        while !old.is_empty()
+            && !new.is_empty()
+            && (self.same)(&self.old[old.start], &self.new[new.start])
+        {
+            ops.push(Op::Equal(old.start, new.start));
+            old.start += 1;
+            new.start += 1;
+        }
+        let mut suffix = 0;
+        while !old.is_empty()
+            && !new.is_empty()
+            && (self.same)(&self.old[old.end - 1], &self.new[new.end - 1])
+        {
+            old.end -= 1;
+            new.end -= 1;
+            suffix += 1;
+        }
+
+        if old.is_empty() {
+            ops.extend(new.clone().map(Op::Insert));
+        } else if new.is_empty() {
+            ops.extend(old.clone().map(Op::Delete));
+        } else {
+            let (x, y) = self.middle_snake(old.clone(), new.clone(), forward, backward);
+            self.conquer(old.start..x, new.start..y, forward, backward, ops);
+            self.conquer(x..old.end, y..new.end, forward, backward, ops);
+        }
+        ops.extend((0..suffix).map(|i| Op::Equal(old.end + i, new.end + i)));
+    }
+
+    /// Finds where the middle snake of an optimal path through the two ranges starts,
+    /// searching from both ends at once until the paths overlap. Both ranges are non-empty
+    /// and differ in their first and last elements.
+    fn middle_snake(
+        &self,
+        old: Range<usize>,
+        new: Range<usize>,
+        forward: &mut [usize],
+        backward: &mut [usize],
+    ) -> (usize, usize) {
+        let (n, m) = (old.len() as isize, new.len() as isize);
+        let delta = n - m;
+        let odd = delta % 2 != 0;
+        let at = |k: isize| (k + self.offset) as usize;
+        forward[at(1)] = 0;
+        backward[at(1)] = 0;
+        for d in 0..=(n + m + 1) / 2 {
+            for k in (-d..=d).step_by(2) {
+                let mut x = if k == -d || (k != d && forward[at(k - 1)] < forward[at(k + 1)]) {
+                    forward[at(k + 1)] as isize
+                } else {
+                    forward[at(k - 1)] as isize + 1
+                };
+                let (start_x, start_y) = (x, x - k);
+                let mut y = start_y;
+                while x < n
+                    && y < m
+                    && (self.same)(
+                        &self.old[old.start + x as usize],
+                        &self.new[new.start + y as usize],
+                    )
+                {
+                    x += 1;
+                    y += 1;
+                }
+                forward[at(k)] = x as usize;
+                if odd && (k - delta).abs() < d && x + backward[at(delta - k)] as isize >= n {
+                    return (old.start + start_x as usize, new.start + start_y as usize);
+                }
+            }
+            for k in (-d..=d).step_by(2) {
+                let mut x = if k == -d || (k != d && backward[at(k - 1)] < backward[at(k + 1)]) {
+                    backward[at(k + 1)] as isize
+                } else {
+                    backward[at(k - 1)] as isize + 1
+                };
+                let mut y = x - k;
+                while x < n
+                    && y < m
+                    && (self.same)(
+                        &self.old[old.end - 1 - x as usize],
+                        &self.new[new.end - 1 - y as usize],
+                    )
+                {
+                    x += 1;
+                    y += 1;
+                }
+                backward[at(k)] = x as usize;
+                if !odd && (k - delta).abs() <= d && x + forward[at(delta - k)] as isize >= n {
+                    return (old.end - x as usize, new.end - y as usize);
+                }
+            }
+        }
+        unreachable!("the paths from both ends meet within (n + m + 1) / 2 steps")
+    }
+}
+
+fn diff_attributes(old: &[Attribute], new: &[Attribute]) -> Vec<AttributeChange> {
+    let value = |attrs: &[Attribute], name: &str| {
+        attrs
+            .iter()
+            .find(|attr| attr.name.local.as_ref() == name)
+            .map(|attr| attr.value.to_string())
+    };
+    let mut names: Vec<String> = old
+        .iter()
+        .chain(new.iter())
+        .map(|attr| attr.name.local.to_string())
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let (before, after) = (value(old, &name), value(new, &name));
+            (before != after).then_some(AttributeChange {
+                name,
+                before,
+                after,
+            })
+        })
+        .collect()
+}
+
+/// Splits text into runs of whitespace and runs of other characters.
+fn tokenize(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = 0;
+    let mut in_space = None;
+    for (index, c) in text.char_indices() {
+        let is_space = c.is_whitespace();
+        if in_space.is_some_and(|in_space| in_space != is_space) {
+            tokens.push(&text[start..index]);
+            start = index;
+        }
+        in_space = Some(is_space);
+    }
+    if start < text.len() {
+        tokens.push(&text[start..]);
+    }
+    tokens
+}
+
+fn key(markup: &RichTextMarkup, node: &Handle) -> Key {
+    match node.data {
+        NodeData::Element { ref name, .. } => {
+            match markup.id_of(node).or_else(|| attribute(node, "id")) {
+                Some(id) if !id.is_empty() => Key::Id(format!("{}#{}", name.local, id)),
+                _ => Key::Name(name.local.to_string()),
+            }
+        }
+        NodeData::Text { .. } => Key::Name("#text".to_string()),
+        NodeData::Comment { .. } => Key::Name("#comment".to_string()),
+        _ => Key::Name("#other".to_string()),
+    }
+}
+
+/// The nodes with an id, by key.
+fn keyed_nodes(markup: &RichTextMarkup, root: &Handle) -> HashMap<String, Handle> {
+    let mut nodes = HashMap::new();
+    let mut stack = vec![root.clone()];
+    while let Some(node) = stack.pop() {
+        if let Key::Id(id) = key(markup, &node) {
+            nodes.entry(id).or_insert_with(|| node.clone());
+        }
+        stack.extend(node.children.borrow().iter().cloned());
+    }
+    nodes
+}
+
+/// Whether the node takes part in the alignment; whitespace-only text does not.
+fn is_significant(node: &Handle) -> bool {
+    match node.data {
+        NodeData::Text { ref contents } => !contents.borrow().trim().is_empty(),
+        _ => true,
+    }
+}
+
+fn change_path(node: &Handle) -> String {
+    match node.data {
+        NodeData::Element { .. } => node_path(node),
+        _ => parent(node)
+            .map(|parent| node_path(&parent))
+            .unwrap_or_default(),
+    }
+}
+
+/// The id of an inserted or deleted rich-text element, or of the one containing it.
+fn rich_text_of(markup: &RichTextMarkup, node: &Handle, enclosing: Option<&str>) -> Option<String> {
+    match node.data {
+        NodeData::Element { ref name, .. } if markup.is_rich_text(name) => markup.id_of(node),
+        _ => enclosing.map(str::to_string),
+    }
+}
+
+/// Removes a node from its parent.
+fn detach(node: &Handle) {
+    let parent = node.parent.take();
+    if let Some(parent) = parent.and_then(|parent| parent.upgrade()) {
+        parent
+            .children
+            .borrow_mut()
+            .retain(|child| !std::ptr::eq(&**child, &**node));
+    }
+}
+
+/// Wraps nodes in an `<ins>` or `<del>` element, marked with a `data-diff` attribute.
+fn wrap(name: &str, marker: Option<&str>, children: Vec<Handle>) -> Handle {
+    element(name)
+        .attr_opt(DIFF_ATTRIBUTE, marker)
+        .child(children)
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diff(old: &str, new: &str) -> DocumentDiff {
+        let old = RcDom::from_fragment_str(old);
+        let new = RcDom::from_fragment_str(new);
+        DocumentDiff::new(&old.fragment_root(), &new.fragment_root())
+    }
+
+    #[test]
+    fn test_text_and_attribute_changes() {
+        let diff = diff(
+            "<p class=\"a\"><rich-text id=\"r1\">Take one tablet  daily.</rich-text></p>",
+            "<p class=\"b\"><rich-text id=\"r1\">Take two tablets daily.</rich-text></p>",
+        );
+        assert_eq!(diff.changes.len(), 2);
+        assert_eq!(
+            diff.changes[0].attributes,
+            [AttributeChange {
+                name: "class".to_string(),
+                before: Some("a".to_string()),
+                after: Some("b".to_string()),
+            }]
+        );
+        let text = &diff.changes[1];
+        assert_eq!(text.kind, ChangeKind::Modified);
+        assert_eq!(text.rich_text.as_deref(), Some("r1"));
+        assert_eq!(text.path, "html > p > rich-text");
+        assert_eq!(
+            diff.annotated().inner_html(),
+            "<p class=\"b\" data-diff=\"modified\"><rich-text id=\"r1\">Take \
+             <del>one</del><ins>two</ins> <del>tablet</del><ins>tablets</ins> daily.</rich-text></p>"
+        );
+    }
+
+    #[test]
+    fn test_insert_delete_and_move() {
+        let diff = diff(
+            "<p><rich-text id=\"a\">A</rich-text> <rich-text id=\"b\">B</rich-text></p>\
+             <ul><li>x</li><li>y</li></ul>",
+            "<p><rich-text id=\"b\">B</rich-text> <rich-text id=\"a\">A!</rich-text></p>\
+             <ul><li>x</li></ul><p>New</p>",
+        );
+        let kinds: Vec<_> = diff
+            .changes
+            .iter()
+            .map(|change| (change.kind, change.rich_text.as_deref()))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                (ChangeKind::Moved, Some("a")),
+                (ChangeKind::Modified, Some("a")),
+                (ChangeKind::Deleted, None),
+                (ChangeKind::Inserted, None),
+            ]
+        );
+        assert_eq!(diff.changes[2].path, "html > ul > li:nth-of-type(2)");
+        assert_eq!(diff.changes[2].before.as_deref(), Some("<li>y</li>"));
+        assert_eq!(diff.changes[3].after.as_deref(), Some("<p>New</p>"));
+        assert_eq!(
+            diff.annotated().inner_html(),
+            "<p><rich-text id=\"b\">B</rich-text> <ins data-diff=\"moved\"><rich-text id=\"a\">\
+             <del>A</del><ins>A!</ins></rich-text></ins></p><ul><li>x</li><del><li>y</li></del></ul>\
+             <ins><p>New</p></ins>"
+        );
+        let json = serde_json::to_value(&diff).unwrap();
+        assert_eq!(
+            json["changes"][0],
+            serde_json::json!({ "kind": "moved", "path": "html > p:nth-of-type(1) > rich-text:nth-of-type(2)", "rich_text": "a" })
+        );
+    }
+
+    #[test]
+    fn test_move_into_inserted_container() {
+        let diff = diff(
+            "<p><rich-text id=\"a\">A</rich-text></p><p>keep</p>",
+            "<p>keep</p><section><rich-text id=\"a\">A</rich-text></section>",
+        );
+        let moved: Vec<_> = diff
+            .changes
+            .iter()
+            .filter(|change| change.rich_text.as_deref() == Some("a"))
+            .map(|change| (change.kind, change.path.as_str()))
+            .collect();
+        assert_eq!(moved, [(ChangeKind::Moved, "html > section > rich-text")]);
+        assert!(diff.annotated().inner_html().ends_with(
+            "<ins><section><ins data-diff=\"moved\"><rich-text id=\"a\">A</rich-text></ins>\
+             </section></ins>"
+        ));
+        assert!(!diff.annotated().inner_html().contains("<del><rich-text"));
+    }
+
+    #[test]
+    fn test_inserted_text_is_escaped() {
+        let diff = diff("<p>a</p>", "<p>a</p>1 &lt; 2");
+        assert_eq!(diff.changes[0].kind, ChangeKind::Inserted);
+        assert_eq!(diff.changes[0].after.as_deref(), Some("1 &lt; 2"));
+    }
+
+    #[test]
+    fn test_align_finds_a_longest_common_subsequence() {
+        fn lcs(a: &[u8], b: &[u8]) -> usize {
+            let mut lengths = vec![vec![0; b.len() + 1]; a.len() + 1];
+            for i in 0..a.len() {
+                for j in 0..b.len() {
+                    lengths[i + 1][j + 1] = if a[i] == b[j] {
+                        lengths[i][j] + 1
+                    } else {
+                        lengths[i][j + 1].max(lengths[i + 1][j])
+                    };
+                }
+            }
+            lengths[a.len()][b.len()]
+        }
+
+        let mut seed = 7u32;
+        let mut sequence = |len: usize| -> Vec<u8> {
+            (0..len)
+                .map(|_| {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                    b"abc"[(seed >> 16) as usize % 3]
+                })
+                .collect()
+        };
+        for round in 0..200 {
+            let old = sequence(round % 13);
+            let new = sequence(round % 7 + round % 5);
+            let (mut i, mut j, mut equal) = (0, 0, 0);
+            for op in align(&old, &new, |a, b| a == b) {
+                match op {
+                    Op::Equal(a, b) => {
+                        assert_eq!((a, b), (i, j));
+                        assert_eq!(old[a], new[b]);
+                        (i, j, equal) = (i + 1, j + 1, equal + 1);
+                    }
+                    Op::Delete(a) => {
+                        assert_eq!(a, i);
+                        i += 1;
+                    }
+                    Op::Insert(b) => {
+                        assert_eq!(b, j);
+                        j += 1;
+                    }
+                }
+            }
+            assert_eq!((i, j), (old.len(), new.len()));
+            assert_eq!(equal, lcs(&old, &new), "{:?} {:?}", old, new);
+        }
+    }
+}
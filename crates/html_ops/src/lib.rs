@@ -1,6 +1,14 @@
+pub mod arena_dom;
+pub mod diff;
+pub mod markdown;
 pub mod rc_dom;
+pub mod rich_text;
+pub mod snapshot;
+pub mod translation;
+mod util;
 pub mod visitor;
 pub mod walk;
+pub mod whitespace;
 
 use crate::{rc_dom::RcDom, visitor::NodeVisitor};
 use std::path::Path;
@@ -18,3 +26,17 @@ pub fn process_html_file<P: AsRef<Path>, V: NodeVisitor>(
 
     Ok((updated_html, visitor))
 }
+
+/// Like [`process_html_file`], for a Markdown file. Rich-text blocks written by a previous
+/// Markdown export keep their ids.
+pub fn process_markdown_file<P: AsRef<Path>, V: NodeVisitor>(
+    file_path: P,
+    mut visitor: V,
+) -> Result<(String, V), std::io::Error> {
+    let dom = RcDom::from_markdown_file(file_path)?;
+
+    let (node_handle, _) = visitor.traverse(dom.document);
+    let updated_html = node_handle.to_html_string();
+
+    Ok((updated_html, visitor))
+}
@@ -0,0 +1,769 @@
+//! Conversion of documents to CommonMark, with GFM tables and strikethrough.
+//!
+//! Markup without a Markdown equivalent is kept as inline HTML, and rich-text elements
+//! are written as `<!-- rich-text id="…" -->` … `<!-- /rich-text -->` comment pairs, with
+//! the tag and id attribute of their [`RichTextMarkup`], so that their boundaries survive
+//! the round trip. [`RcDom::from_markdown`] parses Markdown
+//! back into a document, restoring those comment pairs as rich-text elements.
+
+use crate::{
+    rc_dom::{builder::set_children, Handle, NodeData, RcDom},
+    rich_text::RichTextMarkup,
+    util::{attribute, element_name, heading_level},
+    whitespace::{collapse_whitespace, WhitespaceOptions},
+};
+use pulldown_cmark::{Options, Parser};
+use std::{io, path::Path};
+
+/// Non-breaking spaces are kept, as Markdown has no other way to write them.
+const KEEP_NBSP: WhitespaceOptions = WhitespaceOptions {
+    preserve_nbsp: true,
+};
+
+/// Elements rendered as Markdown blocks, or kept as HTML blocks when unsupported.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "details",
+    "dialog",
+    "dd",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hgroup",
+    "hr",
+    "html",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "table",
+    "ul",
+];
+
+/// Block elements whose content is rendered in place of the element.
+const TRANSPARENT_BLOCKS: &[&str] = &[
+    "article",
+    "aside",
+    "body",
+    "div",
+    "figcaption",
+    "figure",
+    "footer",
+    "header",
+    "hgroup",
+    "html",
+    "main",
+    "nav",
+    "section",
+];
+
+impl Handle {
+    /// Renders this node and its descendants as Markdown.
+    ///
+    /// Attributes of block elements are dropped; inline elements with attributes Markdown
+    /// cannot express are kept as HTML.
+    pub fn to_markdown(&self) -> String {
+        self.to_markdown_with_markup(&RichTextMarkup::default())
+    }
+
+    /// Like [`Handle::to_markdown`], for rich-text blocks marked up as `markup`.
+    pub fn to_markdown_with_markup(&self, markup: &RichTextMarkup) -> String {
+        let markdown = match self.data {
+            NodeData::Document => render_blocks(markup, &self.children.borrow(), false),
+            _ => render_blocks(markup, std::slice::from_ref(self), false),
+        };
+        format!("{}\n", markdown.trim_end())
+    }
+}
+
+/// Whether the element only has attributes from `allowed`.
+fn has_only_attributes(node: &Handle, allowed: &[&str]) -> bool {
+    match node.data {
+        NodeData::Element { ref attrs, .. } => attrs
+            .borrow()
+            .iter()
+            .all(|attr| allowed.contains(&attr.name.local.as_ref())),
+        _ => true,
+    }
+}
+
+fn element_children(node: &Handle) -> Vec<Handle> {
+    node.children
+        .borrow()
+        .iter()
+        .filter(|child| element_name(child).is_some())
+        .cloned()
+        .collect()
+}
+
+fn is_block(markup: &RichTextMarkup, node: &Handle) -> bool {
+    match element_name(node) {
+        _ if markup.is_rich_text_node(node) => node
+            .children
+            .borrow()
+            .iter()
+            .any(|node| is_block(markup, node)),
+        Some(name) => BLOCK_ELEMENTS.contains(&name),
+        None => false,
+    }
+}
+
+fn rich_text_open(markup: &RichTextMarkup, node: &Handle) -> String {
+    format!(
+        "<!-- {} {}=\"{}\" -->",
+        markup.tag,
+        markup.id_attribute,
+        markup.id_of(node).unwrap_or_default()
+    )
+}
+
+fn rich_text_close(markup: &RichTextMarkup) -> String {
+    format!("<!-- /{} -->", markup.tag)
+}
+
+/// Renders a sequence of sibling nodes, grouping runs of inline content into paragraphs.
+/// Tight content, such as that of a list item, puts blocks on consecutive lines.
+fn render_blocks(markup: &RichTextMarkup, nodes: &[Handle], tight: bool) -> String {
+    let mut blocks = Vec::new();
+    let mut inline_run = Vec::new();
+
+    let flush = |run: &mut Vec<Handle>, blocks: &mut Vec<String>| {
+        let paragraph = render_paragraph(markup, run);
+        if !paragraph.is_empty() {
+            blocks.push(paragraph);
+        }
+        run.clear();
+    };
+
+    for node in nodes {
+        if is_block(markup, node) {
+            flush(&mut inline_run, &mut blocks);
+            let block = render_block(markup, node);
+            if !block.trim().is_empty() {
+                blocks.push(block);
+            }
+        } else {
+            inline_run.push(node.clone());
+        }
+    }
+    flush(&mut inline_run, &mut blocks);
+
+    blocks.join(if tight { "\n" } else { "\n\n" })
+}
+
+fn render_block(markup: &RichTextMarkup, node: &Handle) -> String {
+    let name = element_name(node).unwrap_or_default();
+    let children = node.children.borrow();
+
+    match name {
+        _ if markup.is_rich_text_node(node) => format!(
+            "{}\n\n{}\n\n{}",
+            rich_text_open(markup, node),
+            render_blocks(markup, &children, false),
+            rich_text_close(markup)
+        ),
+        "head" => String::new(),
+        _ if heading_level(name).is_some()
+            && !children.iter().any(|node| is_block(markup, node)) =>
+        {
+            let level = heading_level(name).unwrap_or(1);
+            let text = render_inline(markup, &children);
+            format!("{} {}", "#".repeat(level as usize), text.trim())
+        }
+        "p" if !children.iter().any(|node| is_block(markup, node)) => {
+            render_paragraph(markup, &children)
+        }
+        "hr" => "---".to_string(),
+        "blockquote" => render_blocks(markup, &children, false)
+            .lines()
+            .map(|line| format!("> {}", line).trim_end().to_string())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        "pre" => render_code_block(node),
+        "ul" | "ol" => render_list(markup, node, name == "ol").unwrap_or_else(|| node.outer_html()),
+        "table" => render_table(markup, node).unwrap_or_else(|| node.outer_html()),
+        name if TRANSPARENT_BLOCKS.contains(&name) => render_blocks(markup, &children, false),
+        _ => node.outer_html(),
+    }
+}
+
+/// Renders inline content as a paragraph. A paragraph that is a single rich-text element
+/// has its boundaries on lines of their own, as a line starting with a comment would be
+/// read as an HTML block.
+fn render_paragraph(markup: &RichTextMarkup, nodes: &[Handle]) -> String {
+    let content = nodes
+        .iter()
+        .filter(|node| !is_whitespace(node))
+        .collect::<Vec<_>>();
+    match content.as_slice() {
+        [only] if markup.is_rich_text_node(only) => {
+            let paragraph = render_inline(markup, &only.children.borrow());
+            let paragraph = paragraph.trim();
+            if paragraph.is_empty() {
+                return String::new();
+            }
+            format!(
+                "{}\n\n{}\n\n{}",
+                rich_text_open(markup, only),
+                escape_block_start(paragraph),
+                rich_text_close(markup)
+            )
+        }
+        _ => escape_block_start(render_inline(markup, nodes).trim()),
+    }
+}
+
+fn is_whitespace(node: &Handle) -> bool {
+    matches!(node.data, NodeData::Text { ref contents } if contents.borrow().trim().is_empty())
+}
+
+fn render_code_block(pre: &Handle) -> String {
+    let elements = element_children(pre);
+    let (code, language) = match elements.as_slice() {
+        [code] if element_name(code) == Some("code") => {
+            let language = attribute(code, "class").and_then(|class| {
+                class.split_whitespace().find_map(|class| {
+                    class
+                        .strip_prefix("language-")
+                        .or_else(|| class.strip_prefix("lang-"))
+                        .map(str::to_string)
+                })
+            });
+            (code.clone(), language)
+        }
+        _ => (pre.clone(), None),
+    };
+
+    let text = code.text_content();
+    let text = text.strip_suffix('\n').unwrap_or(&text);
+    let fence = "`".repeat(longest_run(text, '`').max(2) + 1);
+    format!(
+        "{}{}\n{}\n{}",
+        fence,
+        language.unwrap_or_default(),
+        text,
+        fence
+    )
+}
+
+/// Renders a list, or returns `None` if its structure has no Markdown equivalent.
+fn render_list(markup: &RichTextMarkup, list: &Handle, ordered: bool) -> Option<String> {
+    let mut elements = element_children(list);
+
+    // A single rich-text element around every item, as `OrphanVisitor` produces, is
+    // written around the whole list.
+    let rich_text = match elements.as_slice() {
+        [only] if markup.is_rich_text_node(only) => Some(only.clone()),
+        _ => None,
+    };
+    if let Some(ref rich_text) = rich_text {
+        elements = element_children(rich_text);
+    }
+    if elements.iter().any(|item| element_name(item) != Some("li")) {
+        return None;
+    }
+
+    let start = attribute(list, "start")
+        .and_then(|start| start.parse::<usize>().ok())
+        .unwrap_or(1);
+    let items = elements
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let marker = if ordered {
+                format!("{}. ", start + i)
+            } else {
+                "- ".to_string()
+            };
+            let children = item.children.borrow();
+            let tight = !children
+                .iter()
+                .any(|child| element_name(child) == Some("p"));
+            let content = render_blocks(markup, &children, tight);
+            let indent = " ".repeat(marker.len());
+            let mut lines = content.lines();
+            let first = lines.next().unwrap_or_default();
+            let mut rendered = format!("{}{}", marker, first).trim_end().to_string();
+            for line in lines {
+                rendered.push('\n');
+                if !line.is_empty() {
+                    rendered.push_str(&indent);
+                    rendered.push_str(line);
+                }
+            }
+            rendered
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Some(match rich_text {
+        Some(rich_text) => format!(
+            "{}\n\n{}\n\n{}",
+            rich_text_open(markup, &rich_text),
+            items,
+            rich_text_close(markup)
+        ),
+        None => items,
+    })
+}
+
+/// Renders a GFM table, or returns `None` if it has captions, spanning cells or block
+/// content in its cells.
+fn render_table(markup: &RichTextMarkup, table: &Handle) -> Option<String> {
+    let mut rows = Vec::new();
+    for child in element_children(table) {
+        match element_name(&child) {
+            Some("thead" | "tbody" | "tfoot") => rows.extend(element_children(&child)),
+            Some("tr") => rows.push(child),
+            Some("colgroup") => {}
+            _ => return None,
+        }
+    }
+
+    let mut cells: Vec<Vec<Handle>> = Vec::new();
+    for row in rows.iter() {
+        let row_cells = element_children(row);
+        for cell in row_cells.iter() {
+            let spans = ["colspan", "rowspan"]
+                .iter()
+                .filter_map(|span| attribute(cell, span))
+                .any(|span| span.trim() != "1");
+            if !matches!(element_name(cell), Some("td" | "th"))
+                || spans
+                || cell
+                    .children
+                    .borrow()
+                    .iter()
+                    .any(|node| is_block(markup, node))
+            {
+                return None;
+            }
+        }
+        cells.push(row_cells);
+    }
+
+    let columns = cells.iter().map(Vec::len).max().filter(|&n| n > 0)?;
+    let render_row = |row: &[Handle]| {
+        let mut rendered: Vec<String> = row
+            .iter()
+            .map(|cell| {
+                render_inline(markup, &cell.children.borrow())
+                    .trim()
+                    .replace('|', "\\|")
+            })
+            .collect();
+        rendered.resize(columns, String::new());
+        format!("| {} |", rendered.join(" | "))
+    };
+
+    let header = &cells[0];
+    let delimiters: Vec<&str> = (0..columns)
+        .map(|i| {
+            let align = header.get(i).and_then(|cell| {
+                attribute(cell, "align").or_else(|| {
+                    attribute(cell, "style").and_then(|style| {
+                        style.split(';').find_map(|declaration| {
+                            let (property, value) = declaration.split_once(':')?;
+                            (property.trim() == "text-align").then(|| value.trim().to_string())
+                        })
+                    })
+                })
+            });
+            match align.as_deref() {
+                Some("left") => ":---",
+                Some("center") => ":---:",
+                Some("right") => "---:",
+                _ => "---",
+            }
+        })
+        .collect();
+
+    let mut lines = vec![
+        render_row(header),
+        format!("| {} |", delimiters.join(" | ")),
+    ];
+    lines.extend(cells[1..].iter().map(|row| render_row(row)));
+    Some(lines.join("\n"))
+}
+
+fn render_inline(markup: &RichTextMarkup, nodes: &[Handle]) -> String {
+    let mut rendered = String::new();
+    for node in nodes {
+        let piece = render_inline_node(markup, node);
+        // Whitespace collapses across element boundaries too
+        match piece.strip_prefix(' ') {
+            Some(rest) if rendered.ends_with(' ') => rendered.push_str(rest),
+            _ => rendered.push_str(&piece),
+        }
+    }
+    rendered
+}
+
+fn render_inline_node(markup: &RichTextMarkup, node: &Handle) -> String {
+    let name = match node.data {
+        NodeData::Text { ref contents } => {
+            return escape_text(&collapse_whitespace(&contents.borrow(), &KEEP_NBSP))
+        }
+        NodeData::Comment { ref contents } => return format!("<!--{}-->", contents),
+        NodeData::Element { ref name, .. } => name.local.as_ref(),
+        _ => return String::new(),
+    };
+    let children = node.children.borrow();
+
+    match name {
+        _ if markup.is_rich_text_node(node) => format!(
+            "{}{}{}",
+            rich_text_open(markup, node),
+            render_inline(markup, &children),
+            rich_text_close(markup)
+        ),
+        "br" => "\\\n".to_string(),
+        _ if !has_only_attributes(node, &["href", "title", "src", "alt"]) => node.outer_html(),
+        "strong" | "b" => delimit("**", &render_inline(markup, &children)),
+        "em" | "i" => delimit("*", &render_inline(markup, &children)),
+        "del" | "s" | "strike" => delimit("~~", &render_inline(markup, &children)),
+        "span" if has_only_attributes(node, &[]) => render_inline(markup, &children),
+        "code" if has_only_attributes(node, &[]) => code_span(&node.text_content()),
+        "a" if has_only_attributes(node, &["href", "title"]) => match attribute(node, "href") {
+            Some(href) => format!(
+                "[{}]({}{})",
+                render_inline(markup, &children).trim(),
+                link_destination(&href),
+                link_title(node)
+            ),
+            None => node.outer_html(),
+        },
+        "img" if has_only_attributes(node, &["src", "alt", "title"]) => format!(
+            "![{}]({}{})",
+            escape_text(&attribute(node, "alt").unwrap_or_default()),
+            link_destination(&attribute(node, "src").unwrap_or_default()),
+            link_title(node)
+        ),
+        _ => node.outer_html(),
+    }
+}
+
+/// Wraps inline content in emphasis delimiters, keeping surrounding whitespace outside.
+fn delimit(delimiter: &str, content: &str) -> String {
+    let trimmed = content.trim();
+    if trimmed.is_empty() {
+        return content.to_string();
+    }
+    let leading = if content.starts_with(char::is_whitespace) {
+        " "
+    } else {
+        ""
+    };
+    let trailing = if content.ends_with(char::is_whitespace) {
+        " "
+    } else {
+        ""
+    };
+    format!(
+        "{}{}{}{}{}",
+        leading, delimiter, trimmed, delimiter, trailing
+    )
+}
+
+fn code_span(code: &str) -> String {
+    let fence = "`".repeat(longest_run(code, '`') + 1);
+    let padding = if code.starts_with('`') || code.ends_with('`') {
+        " "
+    } else {
+        ""
+    };
+    format!("{}{}{}{}{}", fence, padding, code, padding, fence)
+}
+
+fn link_destination(url: &str) -> String {
+    if url.contains([' ', '(', ')', '<', '>']) {
+        format!("<{}>", url.replace('<', "%3C").replace('>', "%3E"))
+    } else {
+        url.to_string()
+    }
+}
+
+fn link_title(node: &Handle) -> String {
+    match attribute(node, "title") {
+        Some(title) => format!(" \"{}\"", title.replace('"', "\\\"")),
+        None => String::new(),
+    }
+}
+
+fn longest_run(text: &str, c: char) -> usize {
+    text.split(|other| other != c)
+        .map(str::len)
+        .max()
+        .unwrap_or(0)
+}
+
+/// Escapes characters that would otherwise be read as inline Markdown syntax.
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '~' => escaped.push('\\'),
+            '&' if chars
+                .peek()
+                .is_some_and(|next| next.is_ascii_alphanumeric() || *next == '#') =>
+            {
+                escaped.push('\\')
+            }
+            _ => {}
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Escapes a paragraph start that would otherwise be read as a heading, quote, list item
+/// or thematic break.
+fn escape_block_start(paragraph: &str) -> String {
+    let digits = paragraph.chars().take_while(char::is_ascii_digit).count();
+    let after_digits = &paragraph[digits..];
+    if digits > 0 && (after_digits.starts_with(". ") || after_digits.starts_with(") ")) {
+        format!("{}\\{}", &paragraph[..digits], after_digits)
+    } else if paragraph.starts_with(['#', '>', '-', '+', '=']) {
+        format!("\\{}", paragraph)
+    } else {
+        paragraph.to_string()
+    }
+}
+
+/// Parses a rich-text comment written by [`Handle::to_markdown`]: `Some(Some(id))` for an
+/// opening comment, `Some(None)` for a closing one.
+fn rich_text_comment(markup: &RichTextMarkup, node: &Handle) -> Option<Option<String>> {
+    let NodeData::Comment { ref contents } = node.data else {
+        return None;
+    };
+    let comment = contents.trim();
+    if comment.strip_prefix('/') == Some(markup.tag.as_str()) {
+        return Some(None);
+    }
+    let id = comment
+        .strip_prefix(markup.tag.as_str())?
+        .trim()
+        .strip_prefix(markup.id_attribute.as_str())?
+        .strip_prefix("=\"")?
+        .strip_suffix('"')?;
+    Some(Some(id.to_string()))
+}
+
+fn create_rich_text(markup: &RichTextMarkup, id: &str, children: Vec<Handle>) -> Handle {
+    markup.element(id).child(children).build()
+}
+
+/// Turns the rich-text comment pairs among the children of `parent`, and of its
+/// descendants, back into rich-text elements.
+fn restore_rich_text(markup: &RichTextMarkup, parent: &Handle) {
+    let children = std::mem::take(&mut *parent.children.borrow_mut());
+    let mut restored = Vec::with_capacity(children.len());
+    let mut nodes = children.into_iter();
+
+    while let Some(node) = nodes.next() {
+        let Some(Some(id)) = rich_text_comment(markup, &node) else {
+            restored.push(node);
+            continue;
+        };
+        let mut content = Vec::new();
+        let mut depth = 0;
+        for node in nodes.by_ref() {
+            match rich_text_comment(markup, &node) {
+                Some(None) if depth == 0 => break,
+                Some(None) => depth -= 1,
+                Some(Some(_)) => depth += 1,
+                None => {}
+            }
+            content.push(node);
+        }
+        restored.push(wrap_rich_text(markup, &id, content));
+    }
+
+    set_children(parent, restored);
+    for child in parent.children.borrow().iter() {
+        restore_rich_text(markup, child);
+    }
+}
+
+/// Wraps restored content in a rich-text element. A rich-text around a single paragraph or
+/// list goes inside it, where `OrphanVisitor` puts it.
+fn wrap_rich_text(markup: &RichTextMarkup, id: &str, content: Vec<Handle>) -> Handle {
+    let content = trim_whitespace(content);
+    if let [block] = content.as_slice() {
+        if matches!(element_name(block), Some("p" | "ul" | "ol")) {
+            let children = trim_whitespace(std::mem::take(&mut *block.children.borrow_mut()));
+            let rich_text = create_rich_text(markup, id, children);
+            set_children(block, vec![rich_text]);
+            return block.clone();
+        }
+    }
+    create_rich_text(markup, id, content)
+}
+
+/// Drops the line breaks the parser leaves around blocks.
+fn trim_whitespace(mut nodes: Vec<Handle>) -> Vec<Handle> {
+    let start = nodes.iter().take_while(|node| is_whitespace(node)).count();
+    nodes.drain(..start);
+    while nodes.last().is_some_and(is_whitespace) {
+        nodes.pop();
+    }
+    nodes
+}
+
+impl RcDom {
+    /// Parses CommonMark, with GFM tables and strikethrough, into a document. Rich-text
+    /// comment pairs written by [`Handle::to_markdown`] become rich-text elements again.
+    pub fn from_markdown(markdown: &str) -> Self {
+        Self::from_markdown_with_markup(markdown, &RichTextMarkup::default())
+    }
+
+    /// Like [`RcDom::from_markdown`], for rich-text blocks marked up as `markup`.
+    pub fn from_markdown_with_markup(markdown: &str, markup: &RichTextMarkup) -> Self {
+        let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH;
+        // Opens the body first, so that a leading comment is not put before `<html>`
+        let mut html = String::from("<body>");
+        pulldown_cmark::html::push_html(&mut html, Parser::new_ext(markdown, options));
+
+        let dom = RcDom::from_str(&html);
+        restore_rich_text(markup, &dom.document);
+        dom
+    }
+
+    pub fn from_markdown_file<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_markdown_file_with_markup(path, &RichTextMarkup::default())
+    }
+
+    pub fn from_markdown_file_with_markup<P: AsRef<Path>>(
+        path: P,
+        markup: &RichTextMarkup,
+    ) -> io::Result<Self> {
+        let markdown = std::fs::read_to_string(path)?;
+        Ok(Self::from_markdown_with_markup(&markdown, markup))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rc_dom::RcDom;
+
+    fn markdown(html: &str) -> String {
+        RcDom::from_str(html).document.to_markdown()
+    }
+
+    #[test]
+    fn test_blocks_and_inline() {
+        let html = "<h2>Dosing <em>guide</em></h2>\
+            <p>Take <strong> 10 mg</strong> of <code>a`b</code>, see <a href=\"/x y\" title=\"X\">this</a>.</p>\
+            <blockquote><p>Quoted</p><p>Twice</p></blockquote>\
+            <pre><code class=\"language-rust\">fn main() {}\n</code></pre>\
+            <p>1. Not a list * really</p>";
+        assert_eq!(
+            markdown(html),
+            "## Dosing *guide*\n\n\
+             Take **10 mg** of ``a`b``, see [this](</x y> \"X\").\n\n\
+             > Quoted\n>\n> Twice\n\n\
+             ```rust\nfn main() {}\n```\n\n\
+             1\\. Not a list \\* really\n"
+        );
+    }
+
+    #[test]
+    fn test_nested_lists_and_rich_text() {
+        let html = "<ol start=\"3\"><rich-text id=\"a\"><li>One<ul><li>Nested</li></ul></li><li>Two</li></rich-text></ol>\
+            <p><rich-text id=\"b\">Para <sup>2</sup></rich-text></p>";
+        assert_eq!(
+            markdown(html),
+            "<!-- rich-text id=\"a\" -->\n\n\
+             3. One\n   - Nested\n4. Two\n\n\
+             <!-- /rich-text -->\n\n\
+             <!-- rich-text id=\"b\" -->\n\nPara <sup>2</sup>\n\n<!-- /rich-text -->\n"
+        );
+    }
+
+    #[test]
+    fn test_tables() {
+        let simple = "<table><tr><th>Drug</th><th align=\"right\">Dose</th></tr><tr><td>A|B</td><td>5</td></tr></table>";
+        assert_eq!(
+            markdown(simple),
+            "| Drug | Dose |\n| --- | ---: |\n| A\\|B | 5 |\n"
+        );
+        let spanning = "<table><tr><td colspan=\"2\">x</td></tr></table>";
+        assert_eq!(
+            markdown(spanning),
+            "<table><tbody><tr><td colspan=\"2\">x</td></tr></tbody></table>\n"
+        );
+    }
+
+    #[test]
+    fn test_import_restores_rich_text() {
+        use crate::visitor::{orphan_visitor::OrphanVisitor, NodeVisitor};
+
+        let a = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+        let b = "9a1c7e2f-3b4d-4e5f-8a6b-7c8d9e0f1a2b";
+        let html = format!(
+            "<ul><rich-text id=\"{a}\"><li>One</li><li>Two</li></rich-text></ul>\
+             <p><rich-text id=\"{b}\">Take <b>10 mg</b></rich-text></p>"
+        );
+        let markdown = format!("{}\nA new paragraph.\n", markdown(&html));
+
+        let dom = RcDom::from_markdown(&markdown);
+        let body = dom.document.to_html_string();
+        assert!(body.contains(&format!(
+            "<ul><rich-text id=\"{a}\"><li>One</li>\n<li>Two</li></rich-text></ul>"
+        )));
+        assert!(body.contains(&format!(
+            "<p><rich-text id=\"{b}\">Take <strong>10 mg</strong></rich-text></p>"
+        )));
+
+        let mut visitor = OrphanVisitor::new();
+        visitor.traverse(dom.document);
+        let html_map = visitor.html_map();
+        assert_eq!(html_map.len(), 3);
+        assert_eq!(html_map[&a.parse().unwrap()], "<li>One</li>\n<li>Two</li>");
+        assert_eq!(html_map[&b.parse().unwrap()], "Take <strong>10 mg</strong>");
+        assert!(html_map.values().any(|html| html == "A new paragraph."));
+    }
+
+    #[test]
+    fn test_custom_rich_text_markup() {
+        use crate::rich_text::RichTextMarkup;
+
+        let markup = RichTextMarkup::new("cms-block", "data-rt-id").with_attribute("lang", "en");
+        let html = "<p><cms-block data-rt-id=\"a\">Take <b>10 mg</b></cms-block></p>\
+            <p><rich-text id=\"b\">Other</rich-text></p>";
+        let markdown = RcDom::from_str(html)
+            .document
+            .to_markdown_with_markup(&markup);
+        assert_eq!(
+            markdown,
+            "<!-- cms-block data-rt-id=\"a\" -->\n\nTake **10 mg**\n\n<!-- /cms-block -->\n\n\
+             <rich-text id=\"b\">Other</rich-text>\n"
+        );
+
+        let dom = RcDom::from_markdown_with_markup(&markdown, &markup);
+        assert!(dom.document.to_html_string().contains(
+            "<p><cms-block data-rt-id=\"a\" lang=\"en\">Take <strong>10 mg</strong></cms-block></p>"
+        ));
+    }
+}
@@ -0,0 +1,286 @@
+//! Constructors for new nodes, for visitors that build replacement subtrees.
+//!
+//! Elements are created in the HTML namespace, as the parser would create them, and get
+//! their children's parent pointers set:
+//!
+//! ```
+//! use html_ops::rc_dom::builder::{element, text};
+//!
+//! let link = element("a")
+//!     .attr("href", "#fn-1")
+//!     .attr("role", "doc-noteref")
+//!     .child(element("sup").text("1"))
+//!     .build();
+//! assert_eq!(link.outer_html(), r##"<a href="#fn-1" role="doc-noteref"><sup>1</sup></a>"##);
+//! # let _ = text("");
+//! ```
+//!
+//! The [`node!`](crate::node) macro builds the same trees inline.
+
+use super::{Handle, Node, NodeData, WeakHandle};
+use html5ever::{Attribute, LocalName, Namespace, QualName};
+use markup5ever::{namespace_url, ns};
+use std::cell::RefCell;
+
+/// Starts an element in the HTML namespace.
+pub fn element(name: &str) -> ElementBuilder {
+    ElementBuilder::new(QualName::new(None, ns!(html), LocalName::from(name)))
+}
+
+/// Starts an element in another namespace, such as `ns!(svg)`.
+pub fn element_ns(namespace: Namespace, name: &str) -> ElementBuilder {
+    ElementBuilder::new(QualName::new(None, namespace, LocalName::from(name)))
+}
+
+/// Creates a text node.
+pub fn text(content: &str) -> Handle {
+    Node::new(NodeData::Text {
+        contents: RefCell::new(content.into()),
+    })
+}
+
+/// Creates an attribute with no namespace, like every attribute parsed on an HTML element.
+pub fn attribute(name: &str, value: &str) -> Attribute {
+    Attribute {
+        name: QualName::new(None, ns!(), LocalName::from(name)),
+        value: value.into(),
+    }
+}
+
+/// Replaces the children of `parent`, pointing each new child's parent at it.
+pub fn set_children(parent: &Handle, children: Vec<Handle>) {
+    for child in children.iter() {
+        child.parent.set(Some(WeakHandle::from(parent)));
+    }
+    *parent.children.borrow_mut() = children;
+}
+
+/// An element under construction; see [`element`].
+#[derive(Debug)]
+pub struct ElementBuilder {
+    name: QualName,
+    attrs: Vec<Attribute>,
+    children: Vec<Handle>,
+}
+
+impl ElementBuilder {
+    pub fn new(name: QualName) -> Self {
+        ElementBuilder {
+            name,
+            attrs: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// Sets an attribute, replacing an earlier value of the same name.
+    pub fn attr(mut self, name: &str, value: impl AsRef<str>) -> Self {
+        let value = value.as_ref();
+        match self
+            .attrs
+            .iter_mut()
+            .find(|attr| attr.name.ns == ns!() && attr.name.local.as_ref() == name)
+        {
+            Some(attr) => attr.value = value.into(),
+            None => self.attrs.push(attribute(name, value)),
+        }
+        self
+    }
+
+    /// Sets an attribute if `value` is `Some`.
+    pub fn attr_opt(self, name: &str, value: Option<impl AsRef<str>>) -> Self {
+        match value {
+            Some(value) => self.attr(name, value),
+            None => self,
+        }
+    }
+
+    /// Appends attributes as they are, such as the attributes of an element being replaced.
+    pub fn attrs(mut self, attrs: impl IntoIterator<Item = Attribute>) -> Self {
+        self.attrs.extend(attrs);
+        self
+    }
+
+    /// Appends a child node, or several; see [`IntoNodes`].
+    pub fn child(mut self, nodes: impl IntoNodes) -> Self {
+        nodes.append_to(&mut self.children);
+        self
+    }
+
+    /// Appends a text node.
+    pub fn text(self, content: &str) -> Self {
+        self.child(text(content))
+    }
+
+    pub fn build(self) -> Handle {
+        // The parser gives every HTML template a fragment for its contents.
+        let template_contents = (self.name.ns == ns!(html)
+            && self.name.local.as_ref() == "template")
+            .then(|| Node::new(NodeData::Document));
+        // As the parser does, so visitors treat its children as HTML.
+        let mathml_annotation_xml_integration_point = self.name.ns == ns!(mathml)
+            && self.name.local.as_ref() == "annotation-xml"
+            && self.attrs.iter().any(|attr| {
+                attr.name.ns == ns!()
+                    && attr.name.local.as_ref() == "encoding"
+                    && (attr.value.eq_ignore_ascii_case("text/html")
+                        || attr.value.eq_ignore_ascii_case("application/xhtml+xml"))
+            });
+        let handle = Node::new(NodeData::Element {
+            name: self.name,
+            attrs: RefCell::new(self.attrs),
+            template_contents: RefCell::new(template_contents),
+            mathml_annotation_xml_integration_point,
+        });
+        set_children(&handle, self.children);
+        handle
+    }
+}
+
+impl From<ElementBuilder> for Handle {
+    fn from(builder: ElementBuilder) -> Self {
+        builder.build()
+    }
+}
+
+/// What [`ElementBuilder::child`] accepts, and what a `(expr)` splices into a
+/// [`node!`](crate::node) body: a node, an unfinished element, or a collection of nodes.
+pub trait IntoNodes {
+    fn append_to(self, nodes: &mut Vec<Handle>);
+}
+
+impl IntoNodes for Handle {
+    fn append_to(self, nodes: &mut Vec<Handle>) {
+        nodes.push(self);
+    }
+}
+
+impl IntoNodes for ElementBuilder {
+    fn append_to(self, nodes: &mut Vec<Handle>) {
+        nodes.push(self.build());
+    }
+}
+
+impl<I: IntoIterator<Item = Handle>> IntoNodes for I {
+    fn append_to(self, nodes: &mut Vec<Handle>) {
+        nodes.extend(self);
+    }
+}
+
+/// Builds a subtree inline, in the spirit of maud's `html!`.
+///
+/// An element is a tag name, optional `[name = value, ...]` attributes and a `{ ... }`
+/// body. Tag and attribute names are identifiers, or string literals for names with
+/// hyphens. The body holds string literals for text, nested elements, and `(expr)` to
+/// splice in anything implementing [`IntoNodes`](crate::rc_dom::builder::IntoNodes).
+/// Attribute values are any `AsRef<str>`.
+///
+/// ```
+/// use html_ops::node;
+/// use html_ops::rc_dom::builder::text;
+///
+/// let id = "0b6f2d1e-5c0a-4f3e-9c55-3a1b2c4d5e6f";
+/// let name = String::from("world");
+/// let p = node!(p [class = "greeting"] {
+///     "rich-text" [id = id] {
+///         "Hello, " em { (text(&name)) } "!"
+///     }
+/// });
+/// assert_eq!(
+///     p.outer_html(),
+///     format!(r#"<p class="greeting"><rich-text id="{id}">Hello, <em>world</em>!</rich-text></p>"#)
+/// );
+/// ```
+#[macro_export]
+macro_rules! node {
+    (@attrs $builder:expr;) => { $builder };
+    (@attrs $builder:expr; $name:ident = $value:expr $(, $($rest:tt)*)?) => {
+        $crate::node!(@attrs $builder.attr(stringify!($name), $value); $($($rest)*)?)
+    };
+    (@attrs $builder:expr; $name:literal = $value:expr $(, $($rest:tt)*)?) => {
+        $crate::node!(@attrs $builder.attr($name, $value); $($($rest)*)?)
+    };
+
+    (@children $builder:expr;) => { $builder };
+    (@children $builder:expr; ($nodes:expr) $($rest:tt)*) => {
+        $crate::node!(@children $builder.child($nodes); $($rest)*)
+    };
+    (@children $builder:expr; $tag:ident $([$($attrs:tt)*])? { $($body:tt)* } $($rest:tt)*) => {
+        $crate::node!(
+            @children $builder.child($crate::node!($tag $([$($attrs)*])? { $($body)* }));
+            $($rest)*
+        )
+    };
+    (@children $builder:expr; $tag:literal $([$($attrs:tt)*])? { $($body:tt)* } $($rest:tt)*) => {
+        $crate::node!(
+            @children $builder.child($crate::node!($tag $([$($attrs)*])? { $($body)* }));
+            $($rest)*
+        )
+    };
+    (@children $builder:expr; $text:literal $($rest:tt)*) => {
+        $crate::node!(@children $builder.text($text); $($rest)*)
+    };
+
+    (@element $tag:expr; [$($attrs:tt)*] { $($body:tt)* }) => {
+        $crate::node!(@children
+            $crate::node!(@attrs $crate::rc_dom::builder::element($tag); $($attrs)*);
+            $($body)*
+        )
+        .build()
+    };
+
+    ($tag:ident $([$($attrs:tt)*])? { $($body:tt)* }) => {
+        $crate::node!(@element stringify!($tag); [$($($attrs)*)?] { $($body)* })
+    };
+    ($tag:literal $([$($attrs:tt)*])? { $($body:tt)* }) => {
+        $crate::node!(@element $tag; [$($($attrs)*)?] { $($body)* })
+    };
+    ($text:literal) => {
+        $crate::rc_dom::builder::text($text)
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builds_html_namespace_elements() {
+        let list = element("ul")
+            .attr("class", "a")
+            .attr("class", "b")
+            .attr_opt("title", None::<&str>)
+            .child((1..=2).map(|n| element("li").text(&n.to_string()).build()))
+            .build();
+        assert_eq!(
+            list.outer_html(),
+            r#"<ul class="b"><li>1</li><li>2</li></ul>"#
+        );
+
+        let NodeData::Element { ref name, .. } = list.data else {
+            panic!("not an element");
+        };
+        assert_eq!(name.ns, ns!(html));
+        for child in list.children.borrow().iter() {
+            let parent = child.parent.take().and_then(|weak| weak.upgrade()).unwrap();
+            assert!(std::ptr::eq(&*parent, &*list));
+        }
+        assert!(matches!(
+            element("template").build().data,
+            NodeData::Element { ref template_contents, .. } if template_contents.borrow().is_some()
+        ));
+    }
+
+    #[test]
+    fn test_node_macro() {
+        let items = vec![text("a"), text("b")];
+        let nav = crate::node!(nav ["aria-label" = "Contents", class = String::from("toc")] {
+            ol { li { (items) } li {} }
+            "after"
+        });
+        assert_eq!(
+            nav.outer_html(),
+            r#"<nav aria-label="Contents" class="toc"><ol><li>ab</li><li></li></ol>after</nav>"#
+        );
+        assert_eq!(crate::node!("just text").text_content(), "just text");
+    }
+}
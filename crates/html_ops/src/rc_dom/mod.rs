@@ -1,4 +1,5 @@
-use html5ever::parse_document;
+use html5ever::serialize::SerializeOpts;
+use html5ever::{parse_document, parse_fragment};
 use std::borrow::Cow;
 use std::cell::{Cell, RefCell};
 use std::collections::{HashSet, VecDeque};
@@ -19,9 +20,14 @@ use markup5ever::serialize::{Serialize, Serializer};
 use markup5ever::Attribute;
 use markup5ever::ExpandedName;
 use markup5ever::QualName;
+use markup5ever::{local_name, namespace_url, ns};
 
 use std::ops::{Deref, DerefMut};
 
+pub mod builder;
+
+use crate::snapshot::FrozenDom;
+
 /// The different kinds of nodes in the DOM.
 #[derive(Debug)]
 pub enum NodeData {
@@ -153,6 +159,117 @@ impl Handle {
 
         String::from_utf8(output).expect("Failed to convert serialized HTML to string")
     }
+
+    /// Serializes the children of this node, without the node itself.
+    pub fn inner_html(&self) -> String {
+        self.serialize_with_scope(ChildrenOnly(None))
+    }
+
+    /// Serializes this node and its children. For the `Document` this is the same as
+    /// [`Handle::inner_html`].
+    pub fn outer_html(&self) -> String {
+        match self.data {
+            NodeData::Document => self.inner_html(),
+            _ => self.serialize_with_scope(IncludeNode),
+        }
+    }
+
+    /// The concatenated text of all descendant text nodes, in document order.
+    pub fn text_content(&self) -> String {
+        let mut text = String::new();
+        let mut stack = vec![self.clone()];
+        while let Some(node) = stack.pop() {
+            if let NodeData::Text { ref contents } = node.data {
+                text.push_str(&contents.borrow());
+            }
+            stack.extend(node.children.borrow().iter().rev().cloned());
+        }
+        text
+    }
+
+    /// Copies this node and its whole subtree, attributes and template contents included.
+    /// The copy has no parent, and every copied descendant points at its copied parent.
+    pub fn deep_clone(&self) -> Handle {
+        let data = match self.data {
+            NodeData::Document => NodeData::Document,
+            NodeData::Doctype {
+                ref name,
+                ref public_id,
+                ref system_id,
+            } => NodeData::Doctype {
+                name: name.clone(),
+                public_id: public_id.clone(),
+                system_id: system_id.clone(),
+            },
+            NodeData::Text { ref contents } => NodeData::Text {
+                contents: RefCell::new(contents.borrow().clone()),
+            },
+            NodeData::Comment { ref contents } => NodeData::Comment {
+                contents: contents.clone(),
+            },
+            NodeData::Element {
+                ref name,
+                ref attrs,
+                ref template_contents,
+                mathml_annotation_xml_integration_point,
+            } => NodeData::Element {
+                name: name.clone(),
+                attrs: RefCell::new(attrs.borrow().clone()),
+                template_contents: RefCell::new(
+                    template_contents.borrow().as_ref().map(Handle::deep_clone),
+                ),
+                mathml_annotation_xml_integration_point,
+            },
+            NodeData::ProcessingInstruction {
+                ref target,
+                ref contents,
+            } => NodeData::ProcessingInstruction {
+                target: target.clone(),
+                contents: contents.clone(),
+            },
+        };
+        let copy = Node::new(data);
+        for child in self.children.borrow().iter() {
+            append(&copy, child.deep_clone());
+        }
+        copy
+    }
+
+    /// Detaches this node from its parent and makes it the root element of a new document.
+    /// Extracting a `Document` node makes it the new document itself.
+    pub fn extract(&self) -> RcDom {
+        if let Some(parent) = self.parent.take().and_then(|weak| weak.upgrade()) {
+            parent
+                .children
+                .borrow_mut()
+                .retain(|child| !Rc::ptr_eq(&child.0, &self.0));
+        }
+        match self.data {
+            NodeData::Document => RcDom {
+                document: self.clone(),
+                ..Default::default()
+            },
+            _ => {
+                let dom = RcDom::default();
+                append(&dom.document, self.clone());
+                dom
+            }
+        }
+    }
+
+    fn serialize_with_scope(&self, traversal_scope: TraversalScope) -> String {
+        let mut output = Vec::new();
+        html5ever::serialize(
+            &mut output,
+            &SerializableHandle::from(self.clone()),
+            SerializeOpts {
+                traversal_scope,
+                ..Default::default()
+            },
+        )
+        .expect("Serialization failed");
+        String::from_utf8(output).expect("Failed to convert serialized HTML to string")
+    }
 }
 
 impl Deref for Handle {
@@ -304,11 +421,40 @@ impl RcDom {
             .expect("Failed to parse HTML");
         dom
     }
+    /// Parses an HTML fragment in the context of a `<body>` element.
+    ///
+    /// The nodes of the fragment are the children of the single `<html>` element of the
+    /// returned document; see [`RcDom::fragment_root`].
+    pub fn from_fragment_str(html: &str) -> Self {
+        parse_fragment(
+            RcDom::default(),
+            Default::default(),
+            QualName::new(None, ns!(html), local_name!("body")),
+            vec![],
+        )
+        .one(html)
+    }
+
+    /// The element holding the nodes of a document parsed with [`RcDom::from_fragment_str`].
+    pub fn fragment_root(&self) -> Handle {
+        self.document
+            .children
+            .borrow()
+            .first()
+            .cloned()
+            .expect("fragment document has a root element")
+    }
+
     pub fn from_file<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
         let html_str = std::fs::read_to_string(path)?;
         let dom = Self::from_str(&html_str);
         Ok(dom)
     }
+
+    /// A `Send` copy of this document, to move it to another thread and thaw it there.
+    pub fn freeze(&self) -> FrozenDom {
+        FrozenDom::new(self)
+    }
 }
 
 impl TreeSink for RcDom {
@@ -604,3 +750,49 @@ impl Serialize for SerializableHandle {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parent_of(node: &Handle) -> Option<Handle> {
+        let weak = node.parent.take();
+        node.parent.set(weak.clone());
+        weak.and_then(|weak| weak.upgrade())
+    }
+
+    #[test]
+    fn test_deep_clone_shares_nothing() {
+        let dom = RcDom::from_fragment_str("<p class=\"a\">Hello <em>world</em></p>");
+        let p = dom.fragment_root().children.borrow()[0].clone();
+        let copy = p.deep_clone();
+        assert_eq!(copy.outer_html(), p.outer_html());
+        assert!(parent_of(&copy).is_none());
+
+        let em = copy.children.borrow()[1].clone();
+        assert!(Rc::ptr_eq(&parent_of(&em).unwrap().0, &copy.0));
+        if let NodeData::Text { ref contents } = em.children.borrow()[0].data {
+            *contents.borrow_mut() = "there".into();
+        }
+        if let NodeData::Element { ref attrs, .. } = copy.data {
+            attrs.borrow_mut().clear();
+        }
+        assert_eq!(p.outer_html(), "<p class=\"a\">Hello <em>world</em></p>");
+        assert_eq!(copy.outer_html(), "<p>Hello <em>there</em></p>");
+    }
+
+    #[test]
+    fn test_extract_detaches_subtree() {
+        let dom = RcDom::from_fragment_str("<p>One</p><p>Two <b>bold</b></p>");
+        let root = dom.fragment_root();
+        let second = root.children.borrow()[1].clone();
+
+        let extracted = second.extract();
+        assert_eq!(root.inner_html(), "<p>One</p>");
+        assert_eq!(extracted.document.inner_html(), "<p>Two <b>bold</b></p>");
+        assert!(Rc::ptr_eq(
+            &parent_of(&second).unwrap().0,
+            &extracted.document.0
+        ));
+    }
+}
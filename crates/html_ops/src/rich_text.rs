@@ -0,0 +1,184 @@
+//! How rich-text blocks are marked up in processed HTML.
+//!
+//! Every visitor that creates or reads rich-text elements takes a [`RichTextMarkup`], so a
+//! site whose pages already use `rich-text` elements or `id` anchors can pick another tag
+//! and id attribute:
+//!
+//! ```
+//! use html_ops::rich_text::RichTextMarkup;
+//!
+//! let markup = RichTextMarkup::new("cms-block", "data-rt-id").with_attribute("contenteditable", "true");
+//! let block = markup.element("42").text("Hello").build();
+//! assert_eq!(
+//!     block.outer_html(),
+//!     r#"<cms-block data-rt-id="42" contenteditable="true">Hello</cms-block>"#
+//! );
+//! assert_eq!(markup.id_of(&block).as_deref(), Some("42"));
+//! ```
+
+use crate::rc_dom::{
+    builder::{attribute, element, ElementBuilder},
+    Handle, NodeData,
+};
+use html5ever::{Attribute, QualName};
+use markup5ever::{namespace_url, ns};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// The tag of rich-text elements unless configured otherwise.
+pub const RICH_TEXT_TAG: &str = "rich-text";
+
+/// The attribute holding the id of a rich-text block unless configured otherwise.
+pub const RICH_TEXT_ID_ATTRIBUTE: &str = "id";
+
+/// The element wrapping each rich-text block, and the attribute holding its id.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RichTextMarkup {
+    /// The tag of the element. A custom element name should contain a hyphen.
+    pub tag: String,
+    /// The attribute holding the block id, such as `data-rt-id`.
+    pub id_attribute: String,
+    /// Attributes added to every new element, such as `contenteditable`.
+    pub attributes: Vec<(String, String)>,
+}
+
+impl Default for RichTextMarkup {
+    fn default() -> Self {
+        RichTextMarkup::new(RICH_TEXT_TAG, RICH_TEXT_ID_ATTRIBUTE)
+    }
+}
+
+impl RichTextMarkup {
+    pub fn new(tag: &str, id_attribute: &str) -> Self {
+        RichTextMarkup {
+            tag: tag.to_ascii_lowercase(),
+            id_attribute: id_attribute.to_ascii_lowercase(),
+            attributes: Vec::new(),
+        }
+    }
+
+    /// Adds an attribute set on every new element.
+    pub fn with_attribute(mut self, name: &str, value: &str) -> Self {
+        self.attributes
+            .push((name.to_ascii_lowercase(), value.to_string()));
+        self
+    }
+
+    /// Whether an element is a rich-text element. Only HTML elements are; the parser
+    /// lowercases their names, as [`RichTextMarkup::new`] does the configured tag.
+    pub fn is_rich_text(&self, name: &QualName) -> bool {
+        name.ns == ns!(html) && name.local.as_ref() == self.tag
+    }
+
+    /// Whether a node is a rich-text element.
+    pub fn is_rich_text_node(&self, node: &Handle) -> bool {
+        matches!(node.data, NodeData::Element { ref name, .. } if self.is_rich_text(name))
+    }
+
+    /// The id among the attributes of a rich-text element.
+    pub fn id(&self, attrs: &[Attribute]) -> Option<String> {
+        attrs
+            .iter()
+            .find(|attr| attr.name.ns == ns!() && attr.name.local.as_ref() == self.id_attribute)
+            .map(|attr| attr.value.to_string())
+    }
+
+    /// The id of a rich-text element, if it is one.
+    pub fn id_of(&self, node: &Handle) -> Option<String> {
+        match node.data {
+            NodeData::Element {
+                ref name,
+                ref attrs,
+                ..
+            } if self.is_rich_text(name) => self.id(&attrs.borrow()),
+            _ => None,
+        }
+    }
+
+    /// The id of a rich-text element parsed as a UUID, as the processing visitors write it.
+    pub fn uuid(&self, attrs: &[Attribute]) -> Option<Uuid> {
+        self.id(attrs).and_then(|id| Uuid::parse_str(&id).ok())
+    }
+
+    /// Starts a new rich-text element with the given id and the configured attributes.
+    pub fn element(&self, id: &str) -> ElementBuilder {
+        let attributes = self.attributes.iter();
+        element(&self.tag)
+            .attr(&self.id_attribute, id)
+            .attrs(attributes.map(|(name, value)| attribute(name, value)))
+    }
+}
+
+/// Where a rich-text block was extracted from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// The file, relative to the directory being processed.
+    pub file: PathBuf,
+    /// The path of the element holding the block, as in `html > body > p:nth-of-type(3)`.
+    pub path: String,
+    /// The text of the heading of the section the block is in.
+    pub heading: Option<String>,
+    /// The position of the block among the blocks of its file, from 0.
+    pub order: usize,
+    /// A hash of the HTML as extracted, to tell whether the block was edited since.
+    pub content_hash: String,
+}
+
+impl Provenance {
+    /// The hash written to [`Provenance::content_hash`]: 16 hex digits of the FNV-1a hash,
+    /// which stays the same across runs and platforms.
+    pub fn hash_content(html: &str) -> String {
+        format!("{:016x}", crate::util::fnv1a(html.as_bytes()))
+    }
+}
+
+/// An extracted rich-text block, with its provenance.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RichTextBlock {
+    pub id: Uuid,
+    pub html: String,
+    #[serde(flatten)]
+    pub provenance: Provenance,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rc_dom::RcDom;
+
+    #[test]
+    fn test_default_markup() {
+        let markup = RichTextMarkup::default();
+        let dom = RcDom::from_fragment_str(r#"<p><rich-text id="a">x</rich-text></p>"#);
+        let p = dom.fragment_root().children.borrow()[0].clone();
+        let rich_text = p.children.borrow()[0].clone();
+        assert!(!markup.is_rich_text_node(&p));
+        assert_eq!(markup.id_of(&rich_text).as_deref(), Some("a"));
+        assert_eq!(
+            markup.element("b").build().outer_html(),
+            r#"<rich-text id="b"></rich-text>"#
+        );
+    }
+
+    #[test]
+    fn test_custom_markup_ignores_default_tag_and_id() {
+        let markup = RichTextMarkup::new("CMS-Block", "data-rt-id");
+        let dom = RcDom::from_fragment_str(
+            r#"<rich-text id="a">x</rich-text><cms-block id="b" data-rt-id="c">y</cms-block>"#,
+        );
+        let children = dom.fragment_root().children.borrow().clone();
+        assert_eq!(markup.id_of(&children[0]), None);
+        assert_eq!(markup.id_of(&children[1]).as_deref(), Some("c"));
+
+        let json = serde_json::to_string(&markup).unwrap();
+        assert_eq!(
+            serde_json::from_str::<RichTextMarkup>(&json).unwrap(),
+            markup
+        );
+        let partial: RichTextMarkup =
+            serde_json::from_str(r#"{"id_attribute": "data-id"}"#).unwrap();
+        assert_eq!(partial.tag, RICH_TEXT_TAG);
+    }
+}
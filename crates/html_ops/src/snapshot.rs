@@ -0,0 +1,463 @@
+//! Serde snapshots of [`rc_dom`](crate::rc_dom) trees.
+//!
+//! A [`Snapshot`] is a compact, versioned JSON tree that round-trips every node of an
+//! `RcDom`: elements with their namespaces and attributes, text, comments, doctypes,
+//! processing instructions and template contents. Text nodes are plain JSON strings and
+//! elements are objects keyed by `e`, so the output stays readable in snapshot tests:
+//!
+//! ```json
+//! {"version":1,"root":{"e":"p","a":[["class","note"]],"c":["Hello ",{"e":"em","c":["world"]}]}}
+//! ```
+//!
+//! Element and attribute namespaces are written as short aliases (`svg`, `math`, `xlink`,
+//! ...) and left out for HTML elements and plain attributes.
+//!
+//! Snapshots only hold owned strings, so unlike an `RcDom` they are `Send` and `Sync`.
+//! [`FrozenDom`] uses this to move parsed documents between threads, or to hold one across
+//! an `.await` in a multi-threaded runtime.
+
+use crate::rc_dom::{Handle, Node, NodeData, RcDom, WeakHandle};
+use html5ever::{tree_builder::QuirksMode, Attribute, LocalName, Namespace, Prefix, QualName};
+use markup5ever::{namespace_url, ns};
+use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+};
+use thiserror::Error;
+
+/// The format version written by [`Snapshot::new`].
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error("malformed snapshot: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("unsupported snapshot version {0} (expected {SNAPSHOT_VERSION})")]
+    UnsupportedVersion(u32),
+}
+
+/// A versioned snapshot of a node and its descendants.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub version: u32,
+    pub root: SnapshotNode,
+}
+
+impl Snapshot {
+    pub fn new(handle: &Handle) -> Self {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            root: SnapshotNode::from(handle),
+        }
+    }
+
+    /// Parses a snapshot, rejecting versions this crate cannot read.
+    ///
+    /// Each element nests two JSON levels deep, so serde_json's recursion limit would
+    /// reject documents nested more than about 64 elements deep; the stack grows as needed
+    /// instead.
+    pub fn from_json(json: &str) -> Result<Self, SnapshotError> {
+        let mut deserializer = serde_json::Deserializer::from_str(json);
+        deserializer.disable_recursion_limit();
+        let snapshot = Snapshot::deserialize(serde_stacker::Deserializer::new(&mut deserializer))?;
+        deserializer.end()?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(snapshot.version));
+        }
+        Ok(snapshot)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).expect("snapshots always serialize")
+    }
+
+    /// Pretty-printed JSON, one node per line where possible; convenient for snapshot tests.
+    pub fn to_json_pretty(&self) -> String {
+        serde_json::to_string_pretty(self).expect("snapshots always serialize")
+    }
+
+    /// Rebuilds the tree. The returned root has no parent.
+    pub fn to_handle(&self) -> Handle {
+        self.root.to_handle()
+    }
+}
+
+/// A node of a [`Snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SnapshotNode {
+    Text(String),
+    Element(SnapshotElement),
+    Comment {
+        comment: String,
+    },
+    Doctype {
+        doctype: String,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        public_id: String,
+        #[serde(default, skip_serializing_if = "String::is_empty")]
+        system_id: String,
+    },
+    ProcessingInstruction {
+        pi: String,
+        data: String,
+    },
+    Document {
+        document: Vec<SnapshotNode>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotElement {
+    /// The element name, with its prefix if it has one.
+    #[serde(rename = "e")]
+    pub name: String,
+    /// The namespace alias; absent for HTML.
+    #[serde(rename = "ns", default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(rename = "a", default, skip_serializing_if = "Vec::is_empty")]
+    pub attributes: Vec<SnapshotAttribute>,
+    #[serde(rename = "c", default, skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<SnapshotNode>,
+    /// The children of a `<template>`'s contents fragment.
+    #[serde(rename = "tc", default, skip_serializing_if = "Option::is_none")]
+    pub template_contents: Option<Vec<SnapshotNode>>,
+    #[serde(rename = "ip", default, skip_serializing_if = "std::ops::Not::not")]
+    pub integration_point: bool,
+}
+
+/// An attribute as `[name, value]`, or `[name, value, namespace]` for namespaced
+/// attributes such as `xlink:href`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum SnapshotAttribute {
+    Plain(String, String),
+    Namespaced(String, String, String),
+}
+
+const NAMESPACE_ALIASES: [(&str, Namespace); 6] = [
+    ("html", ns!(html)),
+    ("svg", ns!(svg)),
+    ("math", ns!(mathml)),
+    ("xlink", ns!(xlink)),
+    ("xml", ns!(xml)),
+    ("xmlns", ns!(xmlns)),
+];
+
+fn namespace_alias(namespace: &Namespace) -> String {
+    NAMESPACE_ALIASES
+        .iter()
+        .find(|(_, ns)| ns == namespace)
+        .map_or_else(|| namespace.to_string(), |(alias, _)| alias.to_string())
+}
+
+fn namespace_from_alias(alias: &str) -> Namespace {
+    NAMESPACE_ALIASES
+        .iter()
+        .find(|(name, _)| *name == alias)
+        .map_or_else(|| Namespace::from(alias), |(_, ns)| ns.clone())
+}
+
+fn qualified_name(name: &QualName) -> String {
+    match name.prefix {
+        Some(ref prefix) => format!("{}:{}", prefix, name.local),
+        None => name.local.to_string(),
+    }
+}
+
+fn parse_qualified_name(name: &str, ns: Namespace) -> QualName {
+    match name.split_once(':') {
+        Some((prefix, local)) => {
+            QualName::new(Some(Prefix::from(prefix)), ns, LocalName::from(local))
+        }
+        None => QualName::new(None, ns, LocalName::from(name)),
+    }
+}
+
+impl From<&Attribute> for SnapshotAttribute {
+    fn from(attr: &Attribute) -> Self {
+        let name = qualified_name(&attr.name);
+        let value = attr.value.to_string();
+        if attr.name.ns == ns!() {
+            SnapshotAttribute::Plain(name, value)
+        } else {
+            SnapshotAttribute::Namespaced(name, value, namespace_alias(&attr.name.ns))
+        }
+    }
+}
+
+impl From<&SnapshotAttribute> for Attribute {
+    fn from(attr: &SnapshotAttribute) -> Self {
+        let (name, value, ns) = match attr {
+            SnapshotAttribute::Plain(name, value) => (name, value, ns!()),
+            SnapshotAttribute::Namespaced(name, value, alias) => {
+                (name, value, namespace_from_alias(alias))
+            }
+        };
+        Attribute {
+            name: parse_qualified_name(name, ns),
+            value: value.as_str().into(),
+        }
+    }
+}
+
+fn snapshot_children(handle: &Handle) -> Vec<SnapshotNode> {
+    handle
+        .children
+        .borrow()
+        .iter()
+        .map(SnapshotNode::from)
+        .collect()
+}
+
+impl From<&Handle> for SnapshotNode {
+    fn from(handle: &Handle) -> Self {
+        match handle.data {
+            NodeData::Document => SnapshotNode::Document {
+                document: snapshot_children(handle),
+            },
+            NodeData::Doctype {
+                ref name,
+                ref public_id,
+                ref system_id,
+            } => SnapshotNode::Doctype {
+                doctype: name.to_string(),
+                public_id: public_id.to_string(),
+                system_id: system_id.to_string(),
+            },
+            NodeData::Text { ref contents } => SnapshotNode::Text(contents.borrow().to_string()),
+            NodeData::Comment { ref contents } => SnapshotNode::Comment {
+                comment: contents.to_string(),
+            },
+            NodeData::Element {
+                ref name,
+                ref attrs,
+                ref template_contents,
+                mathml_annotation_xml_integration_point,
+            } => SnapshotNode::Element(SnapshotElement {
+                name: qualified_name(name),
+                namespace: (name.ns != ns!(html)).then(|| namespace_alias(&name.ns)),
+                attributes: attrs.borrow().iter().map(SnapshotAttribute::from).collect(),
+                children: snapshot_children(handle),
+                template_contents: template_contents.borrow().as_ref().map(snapshot_children),
+                integration_point: mathml_annotation_xml_integration_point,
+            }),
+            NodeData::ProcessingInstruction {
+                ref target,
+                ref contents,
+            } => SnapshotNode::ProcessingInstruction {
+                pi: target.to_string(),
+                data: contents.to_string(),
+            },
+        }
+    }
+}
+
+fn append_children(parent: &Handle, children: &[SnapshotNode]) {
+    let handles: Vec<Handle> = children
+        .iter()
+        .map(|child| {
+            let handle = child.to_handle();
+            handle.parent.set(Some(WeakHandle::from(parent)));
+            handle
+        })
+        .collect();
+    parent.children.borrow_mut().extend(handles);
+}
+
+impl SnapshotNode {
+    /// Rebuilds this node and its descendants, with parent pointers set throughout.
+    pub fn to_handle(&self) -> Handle {
+        match self {
+            SnapshotNode::Text(text) => Node::new(NodeData::Text {
+                contents: RefCell::new(text.as_str().into()),
+            }),
+            SnapshotNode::Comment { comment } => Node::new(NodeData::Comment {
+                contents: comment.as_str().into(),
+            }),
+            SnapshotNode::Doctype {
+                doctype,
+                public_id,
+                system_id,
+            } => Node::new(NodeData::Doctype {
+                name: doctype.as_str().into(),
+                public_id: public_id.as_str().into(),
+                system_id: system_id.as_str().into(),
+            }),
+            SnapshotNode::ProcessingInstruction { pi, data } => {
+                Node::new(NodeData::ProcessingInstruction {
+                    target: pi.as_str().into(),
+                    contents: data.as_str().into(),
+                })
+            }
+            SnapshotNode::Document { document } => {
+                let handle = Node::new(NodeData::Document);
+                append_children(&handle, document);
+                handle
+            }
+            SnapshotNode::Element(element) => {
+                let ns = element
+                    .namespace
+                    .as_deref()
+                    .map_or(ns!(html), namespace_from_alias);
+                let template_contents = element.template_contents.as_ref().map(|children| {
+                    let fragment = Node::new(NodeData::Document);
+                    append_children(&fragment, children);
+                    fragment
+                });
+                let handle = Node::new(NodeData::Element {
+                    name: parse_qualified_name(&element.name, ns),
+                    attrs: RefCell::new(element.attributes.iter().map(Attribute::from).collect()),
+                    template_contents: RefCell::new(template_contents),
+                    mathml_annotation_xml_integration_point: element.integration_point,
+                });
+                append_children(&handle, &element.children);
+                handle
+            }
+        }
+    }
+}
+
+impl From<&SnapshotNode> for Handle {
+    fn from(node: &SnapshotNode) -> Self {
+        node.to_handle()
+    }
+}
+
+/// A parsed document that can cross threads, made with [`RcDom::freeze`].
+///
+/// Freezing copies the tree out of its `Rc` nodes; thawing builds a fresh `RcDom`, which is
+/// much cheaper than parsing the HTML again.
+#[derive(Debug, Clone)]
+pub struct FrozenDom {
+    document: SnapshotNode,
+    errors: Vec<Cow<'static, str>>,
+    quirks_mode: QuirksMode,
+}
+
+impl FrozenDom {
+    pub fn new(dom: &RcDom) -> Self {
+        FrozenDom {
+            document: SnapshotNode::from(&dom.document),
+            errors: dom.errors.borrow().clone(),
+            quirks_mode: dom.quirks_mode.get(),
+        }
+    }
+
+    /// Parses `html` and freezes the result.
+    pub fn parse(html: &str) -> Self {
+        RcDom::from_str(html).freeze()
+    }
+
+    /// Rebuilds the document on the current thread. A frozen document can be thawed any
+    /// number of times.
+    pub fn thaw(&self) -> RcDom {
+        RcDom {
+            document: self.document.to_handle(),
+            errors: RefCell::new(self.errors.clone()),
+            quirks_mode: Cell::new(self.quirks_mode),
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            version: SNAPSHOT_VERSION,
+            root: self.document.clone(),
+        }
+    }
+}
+
+impl From<&RcDom> for FrozenDom {
+    fn from(dom: &RcDom) -> Self {
+        FrozenDom::new(dom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rc_dom::{builder, RcDom};
+
+    fn parent_of(node: &Handle) -> Option<Handle> {
+        let weak = node.parent.take();
+        node.parent.set(weak.clone());
+        weak.and_then(|weak| weak.upgrade())
+    }
+
+    fn assert_parents(node: &Handle) {
+        for child in node.children.borrow().iter() {
+            let parent = parent_of(child).expect("child has a parent");
+            assert!(std::ptr::eq(&*parent, &**node));
+            assert_parents(child);
+        }
+    }
+
+    #[test]
+    fn test_document_round_trip() {
+        let html = "<!DOCTYPE html><html><head><title>T</title></head><body>\
+            <!-- note --><p class=\"x\" id=\"a\">Hello <em>world</em></p>\
+            <svg viewBox=\"0 0 1 1\"><use xlink:href=\"#i\"></use></svg>\
+            <math><annotation-xml encoding=\"text/html\"><b>x</b></annotation-xml></math>\
+            <template><li>item</li></template></body></html>";
+        let dom = RcDom::from_str(html);
+
+        let json = Snapshot::new(&dom.document).to_json();
+        let snapshot = Snapshot::from_json(&json).unwrap();
+        let handle = snapshot.to_handle();
+
+        assert_eq!(handle.to_html_string(), dom.document.to_html_string());
+        assert_eq!(Snapshot::new(&handle), snapshot);
+        assert!(json.contains(r##"["xlink:href","#i","xlink"]"##));
+        assert!(json.contains(r#""ns":"svg""#));
+        assert!(json.contains(r#""ip":true"#));
+        assert!(json.contains(r#""tc":[{"e":"li","c":["item"]}]"#));
+        assert_parents(&handle);
+    }
+
+    #[test]
+    fn test_compact_fragment() {
+        let dom = RcDom::from_fragment_str("<p class=\"note\">Hello <em>world</em></p>");
+        let p = dom.fragment_root().children.borrow()[0].clone();
+        assert_eq!(
+            Snapshot::new(&p).to_json(),
+            r#"{"version":1,"root":{"e":"p","a":[["class","note"]],"c":["Hello ",{"e":"em","c":["world"]}]}}"#
+        );
+    }
+
+    #[test]
+    fn test_frozen_dom_crosses_threads() {
+        let frozen = FrozenDom::parse("<p>One</p><p>Two</p>");
+        let html = std::thread::spawn(move || {
+            let dom = frozen.thaw();
+            assert_parents(&dom.document);
+            dom.document.inner_html()
+        })
+        .join()
+        .unwrap();
+        assert_eq!(
+            html,
+            "<html><head></head><body><p>One</p><p>Two</p></body></html>"
+        );
+    }
+
+    #[test]
+    fn test_deep_tree_round_trip() {
+        let mut node = builder::text("deep");
+        for _ in 0..256 {
+            node = builder::element("div").child(node).build();
+        }
+        let json = Snapshot::new(&node).to_json();
+        let handle = Snapshot::from_json(&json).unwrap().to_handle();
+        assert_eq!(handle.outer_html(), node.outer_html());
+    }
+
+    #[test]
+    fn test_rejects_other_versions() {
+        let json = r#"{"version":2,"root":"text"}"#;
+        assert!(matches!(
+            Snapshot::from_json(json),
+            Err(SnapshotError::UnsupportedVersion(2))
+        ));
+    }
+}
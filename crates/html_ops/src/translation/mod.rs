@@ -0,0 +1,336 @@
+//! Export of rich-text units for translation, and import of the translated files.
+//!
+//! Units are exported with their inline markup replaced by placeholders (see
+//! [`segment::Segment`]), and validated against the same units when imported.
+
+pub mod po;
+pub mod segment;
+pub mod xliff;
+
+use crate::{
+    rc_dom::{Handle, RcDom},
+    rich_text::RichTextMarkup,
+    visitor::NodeVisitor,
+    walk::process_html_files,
+};
+use html5ever::{Attribute, QualName};
+use segment::{Part, Segment, SegmentError};
+use serde::Serialize;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Debug, Error)]
+pub enum TranslationError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("malformed {format} file: {message}")]
+    Malformed {
+        format: &'static str,
+        message: String,
+    },
+    #[error("unknown unit {0}")]
+    UnknownUnit(Uuid),
+    #[error("invalid translation of unit {id}: {source}")]
+    InvalidSegment {
+        id: Uuid,
+        #[source]
+        source: SegmentError,
+    },
+    #[error("unsupported translation file {0:?}")]
+    UnsupportedFormat(PathBuf),
+}
+
+/// The file formats translations can be exchanged in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranslationFormat {
+    Xliff,
+    Po,
+}
+
+impl TranslationFormat {
+    /// Picks the format from a file extension: `.xlf`/`.xliff` or `.po`/`.pot`.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, TranslationError> {
+        let path = path.as_ref();
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("xlf" | "xliff") => Ok(TranslationFormat::Xliff),
+            Some("po" | "pot") => Ok(TranslationFormat::Po),
+            _ => Err(TranslationError::UnsupportedFormat(path.to_path_buf())),
+        }
+    }
+}
+
+/// A translatable rich-text block.
+#[derive(Debug, Clone, Serialize)]
+pub struct TranslationUnit {
+    pub id: Uuid,
+    /// The file the block was found in, relative to the walked directory.
+    pub source_file: PathBuf,
+    /// The inner HTML of the block.
+    pub source: String,
+}
+
+impl TranslationUnit {
+    pub fn segment(&self) -> Segment {
+        Segment::from_html(&self.source)
+    }
+}
+
+/// The translated inner HTML of rich-text blocks in one locale.
+#[derive(Debug, Clone, Default)]
+pub struct Translations {
+    pub locale: String,
+    pub units: HashMap<Uuid, String>,
+}
+
+/// The set of units to translate.
+#[derive(Debug, Clone, Default)]
+pub struct Catalog {
+    units: Vec<TranslationUnit>,
+    index: HashMap<Uuid, usize>,
+}
+
+impl Catalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collects the rich-text blocks of every HTML file under `directory`, which should hold
+    /// documents already processed by `OrphanVisitor`.
+    pub fn from_dir<P: AsRef<Path>>(directory: P) -> io::Result<Self> {
+        Self::from_dir_with_markup(directory, &RichTextMarkup::default())
+    }
+
+    /// Like [`Catalog::from_dir`], for documents whose rich-text blocks use other markup.
+    pub fn from_dir_with_markup<P: AsRef<Path>>(
+        directory: P,
+        markup: &RichTextMarkup,
+    ) -> io::Result<Self> {
+        let mut catalog = Catalog::new();
+        process_html_files(directory, |path, relative_path| {
+            let dom = RcDom::from_file(path)?;
+            catalog.add_document_with_markup(relative_path, &dom.document, markup);
+            Ok(())
+        })?;
+        Ok(catalog)
+    }
+
+    pub fn units(&self) -> &[TranslationUnit] {
+        &self.units
+    }
+
+    pub fn get(&self, id: &Uuid) -> Option<&TranslationUnit> {
+        self.index.get(id).map(|&i| &self.units[i])
+    }
+
+    /// Adds the rich-text blocks of a processed document, in document order.
+    pub fn add_document<P: AsRef<Path>>(&mut self, source_file: P, document: &Handle) {
+        self.add_document_with_markup(source_file, document, &RichTextMarkup::default());
+    }
+
+    /// Like [`Catalog::add_document`], for rich-text blocks marked up as `markup`.
+    pub fn add_document_with_markup<P: AsRef<Path>>(
+        &mut self,
+        source_file: P,
+        document: &Handle,
+        markup: &RichTextMarkup,
+    ) {
+        let mut collector = UnitCollector {
+            markup,
+            units: Vec::new(),
+        };
+        collector.traverse(document.clone());
+        for (id, source) in collector.units {
+            self.add(TranslationUnit {
+                id,
+                source_file: source_file.as_ref().to_path_buf(),
+                source,
+            });
+        }
+    }
+
+    /// Adds the entries of an `OrphanVisitor::html_map`, whose values are the inner HTML
+    /// of each block, for the given file.
+    pub fn add_html_map<P: AsRef<Path>>(
+        &mut self,
+        source_file: P,
+        html_map: &HashMap<Uuid, String>,
+    ) {
+        let mut entries: Vec<_> = html_map.iter().collect();
+        entries.sort_by_key(|(id, _)| **id);
+        for (id, html) in entries {
+            self.add(TranslationUnit {
+                id: *id,
+                source_file: source_file.as_ref().to_path_buf(),
+                source: html.clone(),
+            });
+        }
+    }
+
+    fn add(&mut self, unit: TranslationUnit) {
+        if !unit.segment().is_translatable() {
+            return;
+        }
+        match self.index.get(&unit.id) {
+            Some(&i) => self.units[i] = unit,
+            None => {
+                self.index.insert(unit.id, self.units.len());
+                self.units.push(unit);
+            }
+        }
+    }
+
+    /// The units grouped by source file, in file order.
+    pub fn units_by_file(&self) -> BTreeMap<&Path, Vec<&TranslationUnit>> {
+        let mut files: BTreeMap<&Path, Vec<&TranslationUnit>> = BTreeMap::new();
+        for unit in self.units.iter() {
+            files
+                .entry(unit.source_file.as_path())
+                .or_default()
+                .push(unit);
+        }
+        files
+    }
+
+    pub fn to_xliff(&self, source_lang: &str, target_lang: &str) -> String {
+        xliff::write(self, source_lang, target_lang)
+    }
+
+    pub fn to_po(&self, locale: &str) -> String {
+        po::write(self, locale)
+    }
+
+    pub fn import_xliff(&self, xml: &str) -> Result<Translations, TranslationError> {
+        let (locale, targets) = xliff::read(xml)?;
+        self.render(locale, targets)
+    }
+
+    pub fn import_po(&self, po: &str) -> Result<Translations, TranslationError> {
+        let (locale, targets) = po::read(po)?;
+        self.render(locale, targets)
+    }
+
+    /// Imports a translated file, picking the format from its extension.
+    pub fn import_file<P: AsRef<Path>>(&self, path: P) -> Result<Translations, TranslationError> {
+        let format = TranslationFormat::from_path(&path)?;
+        let contents = std::fs::read_to_string(path)?;
+        match format {
+            TranslationFormat::Xliff => self.import_xliff(&contents),
+            TranslationFormat::Po => self.import_po(&contents),
+        }
+    }
+
+    /// Validates translated parts against their source units and restores their markup.
+    fn render(
+        &self,
+        locale: String,
+        targets: HashMap<Uuid, Vec<Part>>,
+    ) -> Result<Translations, TranslationError> {
+        let mut units = HashMap::with_capacity(targets.len());
+        for (id, parts) in targets {
+            let unit = self.get(&id).ok_or(TranslationError::UnknownUnit(id))?;
+            let html = unit
+                .segment()
+                .render(&parts)
+                .map_err(|source| TranslationError::InvalidSegment { id, source })?;
+            units.insert(id, html);
+        }
+        log::info!("Imported {} translations for {}", units.len(), locale);
+        Ok(Translations { locale, units })
+    }
+}
+
+/// Collects the id and inner HTML of each rich-text element.
+struct UnitCollector<'a> {
+    markup: &'a RichTextMarkup,
+    units: Vec<(Uuid, String)>,
+}
+
+impl NodeVisitor for UnitCollector<'_> {
+    fn visit_element(
+        &mut self,
+        name: &QualName,
+        attrs: &RefCell<Vec<Attribute>>,
+        _template_contents: &RefCell<Option<Handle>>,
+        _mathml_annotation_xml_integration_point: bool,
+        handle: &Handle,
+    ) -> (Option<Handle>, bool) {
+        if !self.markup.is_rich_text(name) {
+            return (None, true);
+        }
+        if let Some(id) = self.markup.uuid(&attrs.borrow()) {
+            self.units.push((id, handle.inner_html()));
+        }
+        (None, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<p><rich-text id="67e55044-10b1-426f-9247-bb680e5fe0c8">Take <b>10 mg</b> "daily"</rich-text></p>"#;
+
+    fn catalog() -> Catalog {
+        let dom = RcDom::from_str(PAGE);
+        let mut catalog = Catalog::new();
+        catalog.add_document("guide/index.html", &dom.document);
+        catalog
+    }
+
+    #[test]
+    fn test_xliff_round_trip() {
+        let catalog = catalog();
+        let exported = catalog.to_xliff("en", "fr");
+        assert!(exported.contains(r#"<file id="f1" original="guide/index.html">"#));
+        assert!(exported.contains(
+            r#"<source>Take <pc id="1" dataRefStart="d1" dataRefEnd="d1e">10 mg</pc> &quot;daily&quot;</source>"#
+        ));
+
+        let translated = exported.replace(
+            "<target></target>",
+            r#"<target>Prendre <pc id="1">10 mg</pc> par jour</target>"#,
+        );
+        let translations = catalog.import_xliff(&translated).unwrap();
+        assert_eq!(translations.locale, "fr");
+        assert_eq!(
+            translations.units[&catalog.units()[0].id],
+            "Prendre <b>10 mg</b> par jour"
+        );
+    }
+
+    #[test]
+    fn test_po_round_trip_and_validation() {
+        let catalog = catalog();
+        let exported = catalog.to_po("de");
+        assert!(exported.contains("msgid \"Take {1}10 mg{/1} \\\"daily\\\"\"\n"));
+
+        // Fill in the unit's msgstr, which is the last one after the header's
+        let translate = |msgstr: &str| {
+            let (head, _) = exported.rsplit_once("msgstr \"\"\n").unwrap();
+            format!("{}{}", head, msgstr)
+        };
+
+        let translations = catalog
+            .import_po(&translate("msgstr \"{1}10 mg{/1} \"\n\"täglich\"\n"))
+            .unwrap();
+        assert_eq!(translations.locale, "de");
+        assert_eq!(
+            translations.units[&catalog.units()[0].id],
+            "<b>10 mg</b> täglich"
+        );
+
+        assert!(matches!(
+            catalog.import_po(&translate("msgstr \"täglich\"\n")),
+            Err(TranslationError::InvalidSegment {
+                source: SegmentError::Missing(1),
+                ..
+            })
+        ));
+    }
+}
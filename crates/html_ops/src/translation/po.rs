@@ -0,0 +1,264 @@
+//! Reading and writing gettext PO files.
+//!
+//! Each unit is an entry whose `msgctxt` is the rich-text id, with a reference comment
+//! naming its source file. Inline markup is written as `{1}…{/1}` and `{1/}` placeholders.
+
+use super::{
+    segment::{Part, Segment},
+    Catalog, TranslationError,
+};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Writes every translatable unit of the catalog as a PO file for `locale`, with empty
+/// `msgstr`s.
+pub fn write(catalog: &Catalog, locale: &str) -> String {
+    let mut po = String::new();
+    po.push_str("msgid \"\"\n");
+    po.push_str("msgstr \"\"\n");
+    po.push_str("\"Content-Type: text/plain; charset=UTF-8\\n\"\n");
+    po.push_str(&format!("\"Language: {}\\n\"\n", escape(locale)));
+
+    for (source_file, units) in catalog.units_by_file() {
+        for unit in units {
+            let segment = unit.segment();
+            po.push('\n');
+            po.push_str(
+                "#. Placeholders such as {1}…{/1} and {1/} stand for markup and must be kept.\n",
+            );
+            po.push_str(&format!("#: {}\n", source_file.to_string_lossy()));
+            po.push_str(&format!("msgctxt \"{}\"\n", unit.id));
+            po.push_str(&format!(
+                "msgid \"{}\"\n",
+                escape(&Segment::to_tokens(&segment.parts))
+            ));
+            po.push_str("msgstr \"\"\n");
+        }
+    }
+    po
+}
+
+#[derive(Default)]
+struct Entry {
+    context: Option<String>,
+    id: String,
+    translation: String,
+    fuzzy: bool,
+}
+
+#[derive(Clone, Copy)]
+enum Field {
+    Context,
+    Id,
+    Translation,
+}
+
+/// Reads the language and the non-empty, non-fuzzy translations of a PO file.
+pub fn read(po: &str) -> Result<(String, HashMap<Uuid, Vec<Part>>), TranslationError> {
+    let mut entries = Vec::new();
+    let mut entry = Entry::default();
+    let mut field = None;
+
+    for (number, line) in po.lines().enumerate() {
+        let line = line.trim();
+        let malformed_line = || malformed(format!("line {}: `{}`", number + 1, line));
+
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('#') {
+            // Comments precede the entry they belong to.
+            if matches!(field, Some(Field::Translation)) {
+                entries.push(std::mem::take(&mut entry));
+                field = None;
+            }
+            if let Some(flags) = line.strip_prefix("#,") {
+                entry.fuzzy |= flags.split(',').any(|flag| flag.trim() == "fuzzy");
+            }
+            continue;
+        }
+
+        let (keyword, value) = match line.split_once(char::is_whitespace) {
+            Some((keyword, value)) if !line.starts_with('"') => (Some(keyword), value.trim()),
+            _ => (None, line),
+        };
+        let value = unquote(value).ok_or_else(malformed_line)?;
+
+        match keyword {
+            Some("msgctxt") => {
+                if field.is_some() {
+                    entries.push(std::mem::take(&mut entry));
+                }
+                entry.context = Some(value);
+                field = Some(Field::Context);
+            }
+            Some("msgid") => {
+                if matches!(field, Some(Field::Id | Field::Translation)) {
+                    entries.push(std::mem::take(&mut entry));
+                }
+                entry.id = value;
+                field = Some(Field::Id);
+            }
+            Some("msgstr") => {
+                entry.translation = value;
+                field = Some(Field::Translation);
+            }
+            Some(_) => return Err(malformed_line()),
+            None => match field {
+                Some(Field::Context) => entry
+                    .context
+                    .get_or_insert_with(String::new)
+                    .push_str(&value),
+                Some(Field::Id) => entry.id.push_str(&value),
+                Some(Field::Translation) => entry.translation.push_str(&value),
+                None => return Err(malformed_line()),
+            },
+        }
+    }
+    if field.is_some() {
+        entries.push(entry);
+    }
+
+    let mut locale = None;
+    let mut translations = HashMap::new();
+    for entry in entries {
+        let Some(context) = entry.context else {
+            if entry.id.is_empty() {
+                locale = header_field(&entry.translation, "Language");
+            }
+            continue;
+        };
+        if entry.fuzzy || entry.translation.is_empty() {
+            continue;
+        }
+        let id = Uuid::parse_str(&context)
+            .map_err(|_| malformed(format!("msgctxt `{}` is not a UUID", context)))?;
+        let parts = Segment::parse_tokens(&entry.translation)
+            .map_err(|source| TranslationError::InvalidSegment { id, source })?;
+        translations.insert(id, parts);
+    }
+
+    let locale = locale.ok_or_else(|| malformed("the header has no Language field"))?;
+    Ok((locale, translations))
+}
+
+fn header_field(header: &str, name: &str) -> Option<String> {
+    header.lines().find_map(|line| {
+        let (key, value) = line.split_once(':')?;
+        (key.trim() == name && !value.trim().is_empty()).then(|| value.trim().to_string())
+    })
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+}
+
+/// Parses a quoted PO string, resolving its escape sequences.
+fn unquote(value: &str) -> Option<String> {
+    let inner = value.strip_prefix('"')?.strip_suffix('"')?;
+    let mut text = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            text.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => text.push('\n'),
+            't' => text.push('\t'),
+            'r' => text.push('\r'),
+            other => text.push(other),
+        }
+    }
+    Some(text)
+}
+
+fn malformed<E: ToString>(error: E) -> TranslationError {
+    TranslationError::Malformed {
+        format: "PO",
+        message: error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+
+    fn catalog(html: &str) -> Catalog {
+        let mut catalog = Catalog::new();
+        catalog.add_html_map(
+            "guide/index.html",
+            &HashMap::from([(Uuid::parse_str(ID).unwrap(), html.to_string())]),
+        );
+        catalog
+    }
+
+    #[test]
+    fn test_write_escapes_msgid() {
+        let po = write(&catalog("Say \"hi\"\tto C:\\<b>you</b>\nnow"), "fr");
+        assert!(po.contains("\"Language: fr\\n\"\n"));
+        assert!(po.contains(&format!("#: guide/index.html\nmsgctxt \"{}\"\n", ID)));
+        assert!(po.contains("msgid \"Say \\\"hi\\\"\\tto C:\\\\{1}you{/1}\\nnow\"\nmsgstr \"\"\n"));
+    }
+
+    #[test]
+    fn test_read_multiline_entries() {
+        let po = format!(
+            "msgid \"\"\nmsgstr \"\"\n\"Language: de\\n\"\n\n\
+             #. A comment\n#: guide/index.html\nmsgctxt \"{}\"\n\
+             msgid \"\"\n\"Say \\\"hi\\\" \"\n\"to {{1}}you{{/1}}\"\n\
+             msgstr \"\"\n\"Sag \\\"hallo\\\"\\n\"\n\"zu {{1}}dir{{/1}}\\\\\"\n",
+            ID
+        );
+        let (locale, translations) = read(&po).unwrap();
+        assert_eq!(locale, "de");
+        assert_eq!(
+            translations[&Uuid::parse_str(ID).unwrap()],
+            vec![
+                Part::Text("Sag \"hallo\"\nzu ".to_string()),
+                Part::Open(1),
+                Part::Text("dir".to_string()),
+                Part::Close(1),
+                Part::Text("\\".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_skips_missing_and_fuzzy_translations() {
+        let po = write(&catalog("One"), "it");
+        let (locale, translations) = read(&po).unwrap();
+        assert_eq!(locale, "it");
+        assert!(translations.is_empty());
+
+        let translated = format!("{}\"Uno\"\n", po);
+        assert_eq!(read(&translated).unwrap().1.len(), 1);
+        let fuzzy = translated.replace("#: ", "#, fuzzy\n#: ");
+        assert!(read(&fuzzy).unwrap().1.is_empty());
+    }
+
+    #[test]
+    fn test_read_rejects_malformed_files() {
+        let error = |po: &str| match read(po) {
+            Err(TranslationError::Malformed { message, .. }) => message,
+            other => panic!("expected a malformed file, got {:?}", other),
+        };
+        assert_eq!(
+            error("msgid \"\"\nmsgstr \"\"\n"),
+            "the header has no Language field"
+        );
+        assert_eq!(
+            error("msgid \"\"\nmsgstr \"Language: de\\n\"\n\nmsgctxt \"x\"\nmsgid \"a\"\nmsgstr \"b\"\n"),
+            "msgctxt `x` is not a UUID"
+        );
+        assert_eq!(
+            error("msgid \"unterminated\n"),
+            "line 1: `msgid \"unterminated`"
+        );
+    }
+}
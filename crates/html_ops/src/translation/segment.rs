@@ -0,0 +1,285 @@
+use crate::rc_dom::{Handle, NodeData, RcDom};
+use html5ever::{Attribute, QualName};
+use std::collections::{BTreeMap, HashSet};
+use thiserror::Error;
+
+/// Elements that never have content, and so become standalone placeholders.
+const VOID_ELEMENTS: &[&str] = &[
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "track",
+    "wbr",
+];
+
+/// A piece of translatable content: either text, or a reference to an inline tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Part {
+    Text(String),
+    /// The start of the paired tag with this id.
+    Open(usize),
+    /// The end of the paired tag with this id.
+    Close(usize),
+    /// A tag, comment or other markup with no translatable content.
+    Standalone(usize),
+}
+
+/// The original markup a placeholder stands for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InlineTag {
+    Paired { start: String, end: String },
+    Standalone(String),
+}
+
+/// An error in the placeholders of a translated segment.
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SegmentError {
+    #[error("malformed placeholder `{0}`")]
+    Malformed(String),
+    #[error("placeholder {0} does not exist in the source")]
+    Unknown(usize),
+    #[error("placeholder {0} is used more than once")]
+    Duplicate(usize),
+    #[error("placeholder {0} is missing")]
+    Missing(usize),
+    #[error("placeholder {0} is not closed in the right place")]
+    Unbalanced(usize),
+}
+
+/// The content of a rich-text unit, with its inline markup replaced by numbered
+/// placeholders so that translators cannot alter it.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Segment {
+    pub parts: Vec<Part>,
+    pub tags: BTreeMap<usize, InlineTag>,
+}
+
+impl Segment {
+    /// Splits an HTML fragment into text and placeholders.
+    pub fn from_html(html: &str) -> Self {
+        let dom = RcDom::from_fragment_str(html);
+        let mut segment = Segment::default();
+        segment.push_nodes(&dom.fragment_root().children.borrow());
+        segment
+    }
+
+    fn push_nodes(&mut self, nodes: &[Handle]) {
+        for node in nodes {
+            match node.data {
+                NodeData::Text { ref contents } => self.push_text(&contents.borrow()),
+                NodeData::Element {
+                    ref name,
+                    ref attrs,
+                    ..
+                } => {
+                    let id = self.tags.len() + 1;
+                    let start = start_tag(name, &attrs.borrow());
+                    let children = node.children.borrow();
+                    if children.is_empty() && VOID_ELEMENTS.contains(&name.local.as_ref()) {
+                        self.tags.insert(id, InlineTag::Standalone(start));
+                        self.parts.push(Part::Standalone(id));
+                    } else {
+                        let end = format!("</{}>", name.local);
+                        self.tags.insert(id, InlineTag::Paired { start, end });
+                        self.parts.push(Part::Open(id));
+                        self.push_nodes(&children);
+                        self.parts.push(Part::Close(id));
+                    }
+                }
+                _ => {
+                    let id = self.tags.len() + 1;
+                    self.tags
+                        .insert(id, InlineTag::Standalone(node.outer_html()));
+                    self.parts.push(Part::Standalone(id));
+                }
+            }
+        }
+    }
+
+    fn push_text(&mut self, text: &str) {
+        match self.parts.last_mut() {
+            Some(Part::Text(previous)) => previous.push_str(text),
+            _ => self.parts.push(Part::Text(text.to_string())),
+        }
+    }
+
+    /// Whether the segment has any text worth translating.
+    pub fn is_translatable(&self) -> bool {
+        self.parts
+            .iter()
+            .any(|part| matches!(part, Part::Text(text) if !text.trim().is_empty()))
+    }
+
+    /// Renders the segment with placeholders written as `{1}`, `{/1}` and `{1/}`. Literal
+    /// braces are written as `{{`.
+    pub fn to_tokens(parts: &[Part]) -> String {
+        parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => text.replace('{', "{{"),
+                Part::Open(id) => format!("{{{}}}", id),
+                Part::Close(id) => format!("{{/{}}}", id),
+                Part::Standalone(id) => format!("{{{}/}}", id),
+            })
+            .collect()
+    }
+
+    /// Parses text written with [`Segment::to_tokens`] placeholders.
+    pub fn parse_tokens(text: &str) -> Result<Vec<Part>, SegmentError> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut rest = text;
+
+        while let Some(brace) = rest.find('{') {
+            literal.push_str(&rest[..brace]);
+            rest = &rest[brace..];
+            if let Some(after) = rest.strip_prefix("{{") {
+                literal.push('{');
+                rest = after;
+                continue;
+            }
+            let close = rest
+                .find('}')
+                .ok_or_else(|| SegmentError::Malformed(rest.to_string()))?;
+            let token = &rest[1..close];
+            let part = if let Some(id) = token.strip_prefix('/') {
+                id.parse().map(Part::Close)
+            } else if let Some(id) = token.strip_suffix('/') {
+                id.parse().map(Part::Standalone)
+            } else {
+                token.parse().map(Part::Open)
+            }
+            .map_err(|_| SegmentError::Malformed(rest[..=close].to_string()))?;
+
+            if !literal.is_empty() {
+                parts.push(Part::Text(std::mem::take(&mut literal)));
+            }
+            parts.push(part);
+            rest = &rest[close + 1..];
+        }
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            parts.push(Part::Text(literal));
+        }
+        Ok(parts)
+    }
+
+    /// Checks that translated parts use every placeholder of this segment exactly once,
+    /// with paired tags properly nested.
+    pub fn validate(&self, parts: &[Part]) -> Result<(), SegmentError> {
+        let mut open = Vec::new();
+        let mut seen = HashSet::new();
+
+        for part in parts {
+            match *part {
+                Part::Text(_) => {}
+                Part::Open(id) => {
+                    if !matches!(self.tags.get(&id), Some(InlineTag::Paired { .. })) {
+                        return Err(SegmentError::Unknown(id));
+                    }
+                    if !seen.insert(id) {
+                        return Err(SegmentError::Duplicate(id));
+                    }
+                    open.push(id);
+                }
+                Part::Close(id) => {
+                    if open.pop() != Some(id) {
+                        return Err(SegmentError::Unbalanced(id));
+                    }
+                }
+                Part::Standalone(id) => {
+                    if !matches!(self.tags.get(&id), Some(InlineTag::Standalone(_))) {
+                        return Err(SegmentError::Unknown(id));
+                    }
+                    if !seen.insert(id) {
+                        return Err(SegmentError::Duplicate(id));
+                    }
+                }
+            }
+        }
+
+        if let Some(id) = open.pop() {
+            return Err(SegmentError::Unbalanced(id));
+        }
+        match self.tags.keys().find(|id| !seen.contains(id)) {
+            Some(&id) => Err(SegmentError::Missing(id)),
+            None => Ok(()),
+        }
+    }
+
+    /// Validates translated parts and restores the original markup of their placeholders.
+    pub fn render(&self, parts: &[Part]) -> Result<String, SegmentError> {
+        self.validate(parts)?;
+        let html = parts
+            .iter()
+            .map(|part| match part {
+                Part::Text(text) => escape_text(text),
+                Part::Open(id) | Part::Standalone(id) => match &self.tags[id] {
+                    InlineTag::Paired { start, .. } => start.clone(),
+                    InlineTag::Standalone(html) => html.clone(),
+                },
+                Part::Close(id) => match &self.tags[id] {
+                    InlineTag::Paired { end, .. } => end.clone(),
+                    InlineTag::Standalone(_) => unreachable!("validated as paired"),
+                },
+            })
+            .collect();
+        Ok(html)
+    }
+}
+
+fn start_tag(name: &QualName, attrs: &[Attribute]) -> String {
+    let mut tag = format!("<{}", name.local);
+    for attr in attrs {
+        tag.push_str(&format!(
+            " {}=\"{}\"",
+            attr.name.local,
+            attr.value.replace('&', "&amp;").replace('"', "&quot;")
+        ));
+    }
+    tag.push('>');
+    tag
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\u{00A0}', "&nbsp;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_round_trip() {
+        let segment =
+            Segment::from_html(r#"Take {one} <b>10<br>mg</b> <a href="/x?a&amp;b">now</a>"#);
+        let tokens = Segment::to_tokens(&segment.parts);
+        assert_eq!(tokens, "Take {{one} {1}10{2/}mg{/1} {3}now{/3}");
+        let parts = Segment::parse_tokens(&tokens).unwrap();
+        assert_eq!(parts, segment.parts);
+        assert_eq!(
+            segment.render(&parts).unwrap(),
+            r#"Take {one} <b>10<br>mg</b> <a href="/x?a&amp;b">now</a>"#
+        );
+    }
+
+    #[test]
+    fn test_validate_rejects_broken_placeholders() {
+        let segment = Segment::from_html("<i>a</i><b>b</b>");
+        let parse = |text| Segment::parse_tokens(text).unwrap();
+        assert_eq!(segment.validate(&parse("{2}b{/2}{1}a{/1}")), Ok(()));
+        assert_eq!(
+            segment.validate(&parse("{1}a{/1}")),
+            Err(SegmentError::Missing(2))
+        );
+        assert_eq!(
+            segment.validate(&parse("{1}a{2}b{/1}{/2}")),
+            Err(SegmentError::Unbalanced(1))
+        );
+        assert_eq!(
+            segment.validate(&parse("{3/}")),
+            Err(SegmentError::Unknown(3))
+        );
+        assert!(Segment::parse_tokens("{x}").is_err());
+    }
+}
@@ -0,0 +1,296 @@
+//! Reading and writing XLIFF 2.0 documents.
+//!
+//! Inline markup is written as `<pc>` (paired) and `<ph>` (standalone) codes, whose
+//! original markup is kept in the `<originalData>` of each unit.
+
+use super::{
+    segment::{InlineTag, Part},
+    Catalog, TranslationError,
+};
+use quick_xml::{
+    escape::escape,
+    events::{BytesStart, Event},
+    Reader,
+};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+const XLIFF_NAMESPACE: &str = "urn:oasis:names:tc:xliff:document:2.0";
+
+/// Writes every translatable unit of the catalog as an XLIFF 2.0 document, with one
+/// `<file>` per source file and empty targets.
+pub fn write(catalog: &Catalog, source_lang: &str, target_lang: &str) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<xliff xmlns=\"{}\" version=\"2.0\" srcLang=\"{}\" trgLang=\"{}\">\n",
+        XLIFF_NAMESPACE,
+        escape(source_lang),
+        escape(target_lang)
+    ));
+
+    for (index, (source_file, units)) in catalog.units_by_file().into_iter().enumerate() {
+        xml.push_str(&format!(
+            "  <file id=\"f{}\" original=\"{}\">\n",
+            index + 1,
+            escape(source_file.to_string_lossy().as_ref())
+        ));
+        for unit in units {
+            let segment = unit.segment();
+            xml.push_str(&format!("    <unit id=\"{}\">\n", unit.id));
+            if !segment.tags.is_empty() {
+                xml.push_str("      <originalData>\n");
+                for (id, tag) in segment.tags.iter() {
+                    match tag {
+                        InlineTag::Paired { start, end } => {
+                            push_data(&mut xml, &format!("d{}", id), start);
+                            push_data(&mut xml, &format!("d{}e", id), end);
+                        }
+                        InlineTag::Standalone(html) => {
+                            push_data(&mut xml, &format!("d{}", id), html)
+                        }
+                    }
+                }
+                xml.push_str("      </originalData>\n");
+            }
+            xml.push_str("      <segment>\n");
+            xml.push_str(&format!(
+                "        <source>{}</source>\n",
+                inline_codes(&segment.parts)
+            ));
+            xml.push_str("        <target></target>\n");
+            xml.push_str("      </segment>\n");
+            xml.push_str("    </unit>\n");
+        }
+        xml.push_str("  </file>\n");
+    }
+
+    xml.push_str("</xliff>\n");
+    xml
+}
+
+fn push_data(xml: &mut String, id: &str, markup: &str) {
+    xml.push_str(&format!(
+        "        <data id=\"{}\">{}</data>\n",
+        id,
+        escape(markup)
+    ));
+}
+
+fn inline_codes(parts: &[Part]) -> String {
+    parts
+        .iter()
+        .map(|part| match part {
+            Part::Text(text) => escape(text.as_str()).into_owned(),
+            Part::Open(id) => format!(
+                "<pc id=\"{0}\" dataRefStart=\"d{0}\" dataRefEnd=\"d{0}e\">",
+                id
+            ),
+            Part::Close(_) => "</pc>".to_string(),
+            Part::Standalone(id) => format!("<ph id=\"{0}\" dataRef=\"d{0}\"/>", id),
+        })
+        .collect()
+}
+
+/// Reads the target language and the non-empty targets of an XLIFF 2.0 document.
+pub fn read(xml: &str) -> Result<(String, HashMap<Uuid, Vec<Part>>), TranslationError> {
+    let mut reader = Reader::from_str(xml);
+    let mut target_lang = None;
+    let mut targets = HashMap::new();
+
+    let mut unit: Option<Uuid> = None;
+    let mut target: Option<Vec<Part>> = None;
+    let mut open_codes = Vec::new();
+
+    loop {
+        let event = reader.read_event().map_err(malformed)?;
+        match event {
+            Event::Start(ref e) | Event::Empty(ref e) => {
+                let is_empty = matches!(event, Event::Empty(_));
+                match e.local_name().as_ref() {
+                    b"xliff" => target_lang = attribute(e, "trgLang")?,
+                    b"unit" => {
+                        let id = attribute(e, "id")?.unwrap_or_default();
+                        let id = Uuid::parse_str(&id)
+                            .map_err(|_| malformed(format!("unit id `{}` is not a UUID", id)))?;
+                        unit = Some(id);
+                    }
+                    b"target" if !is_empty => target = Some(Vec::new()),
+                    name => {
+                        if let Some(parts) = target.as_mut() {
+                            let id = code_id(e)?;
+                            match (name, is_empty) {
+                                (b"pc", false) => {
+                                    open_codes.push(id);
+                                    parts.push(Part::Open(id));
+                                }
+                                (b"pc", true) => {
+                                    parts.push(Part::Open(id));
+                                    parts.push(Part::Close(id));
+                                }
+                                (b"ph", _) => parts.push(Part::Standalone(id)),
+                                (name, _) => {
+                                    return Err(malformed(format!(
+                                        "unsupported inline element <{}>",
+                                        String::from_utf8_lossy(name)
+                                    )))
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            Event::End(ref e) => match e.local_name().as_ref() {
+                b"target" => {
+                    let parts = target.take().unwrap_or_default();
+                    let id = unit.ok_or_else(|| malformed("<target> outside of a <unit>"))?;
+                    if !parts.is_empty() {
+                        let existing: &mut Vec<Part> = targets.entry(id).or_default();
+                        existing.extend(parts);
+                    }
+                }
+                b"unit" => unit = None,
+                b"pc" if target.is_some() => {
+                    let id = open_codes
+                        .pop()
+                        .ok_or_else(|| malformed("unmatched </pc>"))?;
+                    target.as_mut().unwrap().push(Part::Close(id));
+                }
+                _ => {}
+            },
+            Event::Text(ref e) => {
+                if let Some(parts) = target.as_mut() {
+                    push_text(parts, &e.unescape().map_err(malformed)?);
+                }
+            }
+            Event::CData(ref e) => {
+                if let Some(parts) = target.as_mut() {
+                    push_text(parts, &String::from_utf8_lossy(e));
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    let target_lang = target_lang.ok_or_else(|| malformed("the <xliff> element has no trgLang"))?;
+    Ok((target_lang, targets))
+}
+
+fn push_text(parts: &mut Vec<Part>, text: &str) {
+    match parts.last_mut() {
+        Some(Part::Text(previous)) => previous.push_str(text),
+        _ => parts.push(Part::Text(text.to_string())),
+    }
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Result<Option<String>, TranslationError> {
+    for attr in element.attributes() {
+        let attr = attr.map_err(malformed)?;
+        if attr.key.local_name().as_ref() == name.as_bytes() {
+            return Ok(Some(attr.unescape_value().map_err(malformed)?.into_owned()));
+        }
+    }
+    Ok(None)
+}
+
+fn code_id(element: &BytesStart) -> Result<usize, TranslationError> {
+    let id = attribute(element, "id")?.unwrap_or_default();
+    id.parse()
+        .map_err(|_| malformed(format!("inline code id `{}` is not a placeholder", id)))
+}
+
+fn malformed<E: ToString>(error: E) -> TranslationError {
+    TranslationError::Malformed {
+        format: "XLIFF",
+        message: error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+
+    fn unit(target: &str) -> String {
+        format!(
+            "<xliff xmlns=\"{}\" version=\"2.0\" srcLang=\"en\" trgLang=\"fr\">\
+             <file id=\"f1\"><unit id=\"{}\"><segment><source>x</source>{}</segment></unit></file></xliff>",
+            XLIFF_NAMESPACE, ID, target
+        )
+    }
+
+    #[test]
+    fn test_write_escapes_source_and_original_data() {
+        let mut catalog = Catalog::new();
+        catalog.add_html_map(
+            "a&b.html",
+            &HashMap::from([(
+                Uuid::parse_str(ID).unwrap(),
+                r#"1 &lt; 2 <a href="/x?a&amp;b">"now"</a><br>"#.to_string(),
+            )]),
+        );
+        let xml = write(&catalog, "en", "fr");
+        assert!(xml.contains(r#"<file id="f1" original="a&amp;b.html">"#));
+        assert!(xml.contains(r#"<data id="d1">&lt;a href=&quot;/x?a&amp;amp;b&quot;&gt;</data>"#));
+        assert!(xml.contains(r#"<data id="d1e">&lt;/a&gt;</data>"#));
+        assert!(xml.contains(r#"<data id="d2">&lt;br&gt;</data>"#));
+        assert!(xml.contains(
+            r#"<source>1 &lt; 2 <pc id="1" dataRefStart="d1" dataRefEnd="d1e">&quot;now&quot;</pc><ph id="2" dataRef="d2"/></source>"#
+        ));
+        assert!(xml.contains("<target></target>"));
+    }
+
+    #[test]
+    fn test_read_inline_codes_and_escapes() {
+        let (locale, targets) = read(&unit(
+            r#"<target>1 &lt; 2 <pc id="1">« <![CDATA[<now>]]> »</pc><ph id="2"/><pc id="3"/></target>"#,
+        ))
+        .unwrap();
+        assert_eq!(locale, "fr");
+        assert_eq!(
+            targets[&Uuid::parse_str(ID).unwrap()],
+            vec![
+                Part::Text("1 < 2 ".to_string()),
+                Part::Open(1),
+                Part::Text("« <now> »".to_string()),
+                Part::Close(1),
+                Part::Standalone(2),
+                Part::Open(3),
+                Part::Close(3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_skips_missing_targets() {
+        for target in ["", "<target/>", "<target></target>"] {
+            let (_, targets) = read(&unit(target)).unwrap();
+            assert!(targets.is_empty(), "{:?}", target);
+        }
+    }
+
+    #[test]
+    fn test_read_rejects_malformed_documents() {
+        let error = |xml: &str| match read(xml) {
+            Err(TranslationError::Malformed { message, .. }) => message,
+            other => panic!("expected a malformed document, got {:?}", other),
+        };
+        assert_eq!(
+            error(&unit("").replace(" trgLang=\"fr\"", "")),
+            "the <xliff> element has no trgLang"
+        );
+        assert_eq!(
+            error(&unit("").replace(ID, "u1")),
+            "unit id `u1` is not a UUID"
+        );
+        assert_eq!(
+            error(&unit("<target><mrk id=\"1\">a</mrk></target>")),
+            "unsupported inline element <mrk>"
+        );
+        assert_eq!(
+            error(&unit("<target><ph id=\"x\"/></target>")),
+            "inline code id `x` is not a placeholder"
+        );
+    }
+}
@@ -0,0 +1,86 @@
+//! Small helpers shared by visitors and other modules of the crate.
+
+use crate::rc_dom::{Handle, NodeData};
+use html5ever::Attribute;
+
+/// The 64-bit FNV-1a hash, which is stable across runs unlike the standard library hasher.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The local name of an element, or `None` for other nodes.
+pub(crate) fn element_name(node: &Handle) -> Option<&str> {
+    match node.data {
+        NodeData::Element { ref name, .. } => Some(name.local.as_ref()),
+        _ => None,
+    }
+}
+
+pub(crate) fn parent(node: &Handle) -> Option<Handle> {
+    let weak = node.parent.take();
+    node.parent.set(weak.clone());
+    weak.and_then(|weak| weak.upgrade())
+}
+
+/// The value of the attribute with the given local name.
+pub(crate) fn find_attribute(attrs: &[Attribute], name: &str) -> Option<String> {
+    attrs
+        .iter()
+        .find(|attr| attr.name.local.as_ref() == name)
+        .map(|attr| attr.value.to_string())
+}
+
+/// The value of an element's attribute with the given local name.
+pub(crate) fn attribute(node: &Handle, name: &str) -> Option<String> {
+    match node.data {
+        NodeData::Element { ref attrs, .. } => find_attribute(&attrs.borrow(), name),
+        _ => None,
+    }
+}
+
+/// 1 for `h1` to 6 for `h6`.
+pub(crate) fn heading_level(name: &str) -> Option<u8> {
+    match name {
+        "h1" => Some(1),
+        "h2" => Some(2),
+        "h3" => Some(3),
+        "h4" => Some(4),
+        "h5" => Some(5),
+        "h6" => Some(6),
+        _ => None,
+    }
+}
+
+/// A selector-like path from the root element to `node`. Elements are disambiguated
+/// from their siblings of the same name with `:nth-of-type`.
+pub(crate) fn node_path(node: &Handle) -> String {
+    let mut segments = Vec::new();
+    let mut current = Some(node.clone());
+    while let Some(node) = current {
+        let Some(name) = element_name(&node) else {
+            break;
+        };
+        let parent = parent(&node);
+        let mut segment = name.to_string();
+        if let Some(parent) = parent.as_ref() {
+            let siblings = parent.children.borrow();
+            let same_name: Vec<_> = siblings
+                .iter()
+                .filter(|sibling| element_name(sibling) == Some(name))
+                .collect();
+            if same_name.len() > 1 {
+                let index = same_name
+                    .iter()
+                    .position(|sibling| std::ptr::eq(&***sibling, &*node))
+                    .unwrap_or_default();
+                segment = format!("{}:nth-of-type({})", name, index + 1);
+            }
+        }
+        segments.push(segment);
+        current = parent;
+    }
+    segments.reverse();
+    segments.join(" > ")
+}
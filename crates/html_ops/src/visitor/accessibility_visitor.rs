@@ -0,0 +1,577 @@
+use super::NodeVisitor;
+use crate::{
+    rc_dom::{Handle, NodeData, RcDom},
+    rich_text::RichTextMarkup,
+    util::{element_name, find_attribute, heading_level, node_path, parent},
+    walk::process_html_files,
+    whitespace::{normalize_text, WhitespaceOptions},
+};
+use html5ever::{Attribute, QualName};
+use serde::Serialize;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    fmt, io,
+    path::{Path, PathBuf},
+};
+
+/// Link texts that do not say where the link goes when read out of context.
+const AMBIGUOUS_LINK_TEXTS: &[&str] = &[
+    "click here",
+    "here",
+    "link",
+    "more",
+    "read more",
+    "learn more",
+    "this link",
+    "this page",
+    "go",
+];
+
+/// `<input>` types that need no label.
+const UNLABELLED_INPUT_TYPES: &[&str] = &["hidden", "submit", "reset", "button", "image"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rule {
+    ImageAlt,
+    EmptyLink,
+    AmbiguousLinkText,
+    SkippedHeadingLevel,
+    TableHeaders,
+    HtmlLang,
+    DuplicateId,
+    FormLabel,
+}
+
+impl Rule {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Rule::ImageAlt => "image-alt",
+            Rule::EmptyLink => "empty-link",
+            Rule::AmbiguousLinkText => "ambiguous-link-text",
+            Rule::SkippedHeadingLevel => "skipped-heading-level",
+            Rule::TableHeaders => "table-headers",
+            Rule::HtmlLang => "html-lang",
+            Rule::DuplicateId => "duplicate-id",
+            Rule::FormLabel => "form-label",
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            Rule::AmbiguousLinkText | Rule::SkippedHeadingLevel | Rule::TableHeaders => {
+                Severity::Warning
+            }
+            _ => Severity::Error,
+        }
+    }
+}
+
+/// An accessibility problem found in a document.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    /// A selector-like path to the offending element, e.g. `html > body > p:nth-of-type(2) > a`.
+    pub path: String,
+    pub rule: Rule,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {}[{}] {}: {}",
+            self.file.display(),
+            self.severity,
+            self.rule.code(),
+            self.path,
+            self.message
+        )
+    }
+}
+
+/// A form control, checked once every `<label>` of the document has been seen.
+struct FormControl {
+    id: Option<String>,
+    path: String,
+    position: usize,
+}
+
+/// A visitor that checks a document for common accessibility problems.
+///
+/// Checks that depend on the whole document, such as whether a form control has a
+/// `<label for>` further down, are made by [`AccessibilityVisitor::into_diagnostics`].
+///
+/// Blocks marked by [`SharedIdVisitor`](super::duplicate_visitor::SharedIdVisitor) keep
+/// ids of their own, so reusing a rich-text id is always reported.
+pub struct AccessibilityVisitor {
+    file: PathBuf,
+    /// The diagnostics, each with the index of its element in document order.
+    diagnostics: Vec<(usize, Diagnostic)>,
+    position: usize,
+    ids: HashMap<String, String>,
+    rich_text_ids: Vec<(String, String, usize)>,
+    previous_heading: Option<u8>,
+    label_targets: HashSet<String>,
+    unlabelled_controls: Vec<FormControl>,
+    markup: RichTextMarkup,
+}
+
+impl AccessibilityVisitor {
+    pub fn new<P: AsRef<Path>>(file: P) -> Self {
+        AccessibilityVisitor {
+            file: file.as_ref().to_path_buf(),
+            diagnostics: Vec::new(),
+            position: 0,
+            ids: HashMap::new(),
+            rich_text_ids: Vec::new(),
+            previous_heading: None,
+            label_targets: HashSet::new(),
+            unlabelled_controls: Vec::new(),
+            markup: RichTextMarkup::default(),
+        }
+    }
+
+    /// Sets the element and id attribute of the rich-text blocks.
+    pub fn with_rich_text_markup(mut self, markup: RichTextMarkup) -> Self {
+        self.markup = markup;
+        self
+    }
+
+    /// The ids of the rich-text elements of the document, with their paths, so that
+    /// collisions between documents can be found.
+    pub fn rich_text_ids(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.rich_text_ids
+            .iter()
+            .map(|(id, path, _)| (id.as_str(), path.as_str()))
+    }
+
+    /// Finishes the deferred checks and returns every diagnostic, in document order.
+    pub fn into_diagnostics(self) -> Vec<Diagnostic> {
+        self.into_positioned_diagnostics()
+            .into_iter()
+            .map(|(_, diagnostic)| diagnostic)
+            .collect()
+    }
+
+    /// Like [`AccessibilityVisitor::into_diagnostics`], with the position of each
+    /// diagnostic's element.
+    fn into_positioned_diagnostics(mut self) -> Vec<(usize, Diagnostic)> {
+        let controls = std::mem::take(&mut self.unlabelled_controls);
+        for control in controls {
+            let labelled = control
+                .id
+                .as_ref()
+                .is_some_and(|id| self.label_targets.contains(id));
+            if !labelled {
+                self.report_at(
+                    control.position,
+                    control.path,
+                    Rule::FormLabel,
+                    "Form control has no label".to_string(),
+                );
+            }
+        }
+        // Stable, so diagnostics of the same element keep the order they were made in
+        self.diagnostics.sort_by_key(|(position, _)| *position);
+        self.diagnostics
+    }
+
+    fn report(&mut self, path: String, rule: Rule, message: String) {
+        self.report_at(self.position, path, rule, message);
+    }
+
+    fn report_at(&mut self, position: usize, path: String, rule: Rule, message: String) {
+        let diagnostic = Diagnostic {
+            file: self.file.clone(),
+            path,
+            rule,
+            severity: rule.severity(),
+            message,
+        };
+        self.diagnostics.push((position, diagnostic));
+    }
+
+    fn check_id(&mut self, name: &QualName, attrs: &[Attribute], handle: &Handle) {
+        let rich_text_id = self
+            .markup
+            .is_rich_text(name)
+            .then(|| self.markup.id(attrs))
+            .flatten();
+        if let Some(id) = rich_text_id.as_ref() {
+            let path = node_path(handle);
+            // Ids in an attribute of their own can only collide with each other
+            if self.markup.id_attribute != "id" {
+                let first = self.rich_text_ids.iter().find(|(other, ..)| other == id);
+                if let Some((_, first, _)) = first {
+                    let message = format!("Rich-text id {} is already used by {}", id, first);
+                    self.report(path.clone(), Rule::DuplicateId, message);
+                }
+            }
+            self.rich_text_ids.push((id.clone(), path, self.position));
+        }
+
+        let Some(id) = find_attribute(attrs, "id") else {
+            return;
+        };
+        let path = node_path(handle);
+        match self.ids.get(&id) {
+            Some(first) => {
+                let message = if rich_text_id.as_ref() == Some(&id) {
+                    format!("Rich-text id {} is already used by {}", id, first)
+                } else {
+                    format!("Id \"{}\" is already used by {}", id, first)
+                };
+                self.report(path, Rule::DuplicateId, message);
+            }
+            None => {
+                self.ids.insert(id, path);
+            }
+        }
+    }
+
+    fn check_link(&mut self, attrs: &[Attribute], handle: &Handle) {
+        if find_attribute(attrs, "href").is_none() {
+            return;
+        }
+        let text = accessible_text(handle);
+        if text.is_empty() && !has_label_attribute(attrs) {
+            self.report(
+                node_path(handle),
+                Rule::EmptyLink,
+                "Link has no text".to_string(),
+            );
+        } else if AMBIGUOUS_LINK_TEXTS.contains(&text.to_lowercase().trim_end_matches('.')) {
+            self.report(
+                node_path(handle),
+                Rule::AmbiguousLinkText,
+                format!("Link text \"{}\" does not describe its target", text),
+            );
+        }
+    }
+
+    fn check_heading(&mut self, level: u8, handle: &Handle) {
+        if let Some(previous) = self.previous_heading {
+            if level > previous + 1 {
+                self.report(
+                    node_path(handle),
+                    Rule::SkippedHeadingLevel,
+                    format!("Heading skips from h{} to h{}", previous, level),
+                );
+            }
+        }
+        self.previous_heading = Some(level);
+    }
+
+    fn check_form_control(&mut self, tag: &str, attrs: &[Attribute], handle: &Handle) {
+        if tag == "input" {
+            let input_type = find_attribute(attrs, "type")
+                .unwrap_or_default()
+                .to_lowercase();
+            if UNLABELLED_INPUT_TYPES.contains(&input_type.as_str()) {
+                return;
+            }
+        }
+        if has_label_attribute(attrs) || has_ancestor(handle, "label") {
+            return;
+        }
+        self.unlabelled_controls.push(FormControl {
+            id: find_attribute(attrs, "id"),
+            path: node_path(handle),
+            position: self.position,
+        });
+    }
+}
+
+impl NodeVisitor for AccessibilityVisitor {
+    fn visit_element(
+        &mut self,
+        name: &QualName,
+        attrs: &RefCell<Vec<Attribute>>,
+        _template_contents: &RefCell<Option<Handle>>,
+        _mathml_annotation_xml_integration_point: bool,
+        handle: &Handle,
+    ) -> (Option<Handle>, bool) {
+        self.position += 1;
+        let attrs = attrs.borrow();
+        let tag = name.local.as_ref();
+        self.check_id(name, &attrs, handle);
+        if let Some(level) = heading_level(tag) {
+            self.check_heading(level, handle);
+        }
+
+        match tag {
+            "html" => {
+                let lang = find_attribute(&attrs, "lang").unwrap_or_default();
+                if lang.trim().is_empty() {
+                    self.report(
+                        node_path(handle),
+                        Rule::HtmlLang,
+                        "Document has no lang attribute".to_string(),
+                    );
+                }
+            }
+            "img" if find_attribute(&attrs, "alt").is_none() && !is_presentational(&attrs) => {
+                self.report(
+                    node_path(handle),
+                    Rule::ImageAlt,
+                    "Image has no alt attribute".to_string(),
+                );
+            }
+            "a" => self.check_link(&attrs, handle),
+            "table" if !is_presentational(&attrs) && !has_descendant(handle, "th") => {
+                self.report(
+                    node_path(handle),
+                    Rule::TableHeaders,
+                    "Table has no header cells".to_string(),
+                );
+            }
+            "label" => {
+                if let Some(target) = find_attribute(&attrs, "for") {
+                    self.label_targets.insert(target);
+                }
+            }
+            "input" | "select" | "textarea" => self.check_form_control(tag, &attrs, handle),
+            _ => {}
+        }
+        (None, true)
+    }
+}
+
+/// Lints every HTML file under `directory`, including rich-text ids used in more than one
+/// file. Diagnostics are sorted by file, then by document order.
+pub fn lint_directory<P: AsRef<Path>>(directory: P) -> io::Result<Vec<Diagnostic>> {
+    lint_directory_with_markup(directory, &RichTextMarkup::default())
+}
+
+/// Like [`lint_directory`], for documents whose rich-text blocks use other markup.
+pub fn lint_directory_with_markup<P: AsRef<Path>>(
+    directory: P,
+    markup: &RichTextMarkup,
+) -> io::Result<Vec<Diagnostic>> {
+    let mut diagnostics = Vec::new();
+    let mut rich_text_ids: HashMap<String, (PathBuf, String)> = HashMap::new();
+
+    process_html_files(directory, |path, relative_path| {
+        let dom = RcDom::from_file(path)?;
+        let mut visitor =
+            AccessibilityVisitor::new(relative_path).with_rich_text_markup(markup.clone());
+        visitor.traverse(dom.document);
+
+        let mut collisions = Vec::new();
+        for (id, node, position) in &visitor.rich_text_ids {
+            match rich_text_ids.get(id) {
+                Some((file, _)) if file != relative_path => {
+                    let message =
+                        format!("Rich-text id {} is already used in {}", id, file.display());
+                    collisions.push((*position, node.clone(), message));
+                }
+                Some(_) => {}
+                None => {
+                    rich_text_ids.insert(id.clone(), (relative_path.to_path_buf(), node.clone()));
+                }
+            }
+        }
+        for (position, node, message) in collisions {
+            visitor.report_at(position, node, Rule::DuplicateId, message);
+        }
+        let file_diagnostics = visitor.into_positioned_diagnostics();
+        diagnostics.extend(
+            file_diagnostics
+                .into_iter()
+                .map(|(_, diagnostic)| diagnostic),
+        );
+        Ok(())
+    })?;
+
+    Ok(diagnostics)
+}
+
+fn has_label_attribute(attrs: &[Attribute]) -> bool {
+    ["aria-label", "aria-labelledby", "title"]
+        .iter()
+        .any(|name| find_attribute(attrs, name).is_some_and(|value| !value.trim().is_empty()))
+}
+
+fn is_presentational(attrs: &[Attribute]) -> bool {
+    matches!(
+        find_attribute(attrs, "role").as_deref(),
+        Some("presentation" | "none")
+    )
+}
+
+fn has_ancestor(node: &Handle, tag: &str) -> bool {
+    let mut current = parent(node);
+    while let Some(node) = current {
+        if element_name(&node) == Some(tag) {
+            return true;
+        }
+        current = parent(&node);
+    }
+    false
+}
+
+fn has_descendant(node: &Handle, tag: &str) -> bool {
+    node.children
+        .borrow()
+        .iter()
+        .any(|child| element_name(child) == Some(tag) || has_descendant(child, tag))
+}
+
+/// The text a screen reader announces for an element: its text, and the alt text of its
+/// images.
+fn accessible_text(node: &Handle) -> String {
+    fn collect(node: &Handle, text: &mut String) {
+        for child in node.children.borrow().iter() {
+            match child.data {
+                NodeData::Text { ref contents } => text.push_str(&contents.borrow()),
+                NodeData::Element { ref attrs, .. } if element_name(child) == Some("img") => {
+                    if let Some(alt) = find_attribute(&attrs.borrow(), "alt") {
+                        text.push(' ');
+                        text.push_str(&alt);
+                    }
+                }
+                NodeData::Element { .. } => collect(child, text),
+                _ => {}
+            }
+        }
+    }
+    let mut text = String::new();
+    collect(node, &mut text);
+    normalize_text(&text, &WhitespaceOptions::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lint(html: &str) -> Vec<Diagnostic> {
+        let dom = RcDom::from_str(html);
+        let mut visitor = AccessibilityVisitor::new("page.html");
+        visitor.traverse(dom.document);
+        visitor.into_diagnostics()
+    }
+
+    fn rules(diagnostics: &[Diagnostic]) -> Vec<Rule> {
+        diagnostics.iter().map(|d| d.rule).collect()
+    }
+
+    #[test]
+    fn test_reports_problems_with_paths() {
+        let diagnostics = lint(
+            "<html><body><p>a</p><p><img src=\"x.png\"><a href=\"/a\"></a>\
+             <a href=\"/b\">Click here.</a><a href=\"/c\"><img src=\"c.png\" alt=\"Chart\"></a></p>\
+             <table><tr><td>1</td></tr></table></body></html>",
+        );
+        assert_eq!(
+            rules(&diagnostics),
+            [
+                Rule::HtmlLang,
+                Rule::ImageAlt,
+                Rule::EmptyLink,
+                Rule::AmbiguousLinkText,
+                Rule::TableHeaders,
+            ]
+        );
+        assert_eq!(
+            diagnostics[1].to_string(),
+            "page.html: error[image-alt] html > body > p:nth-of-type(2) > img: Image has no alt attribute"
+        );
+        assert_eq!(
+            serde_json::to_value(&diagnostics[3]).unwrap()["rule"],
+            "ambiguous-link-text"
+        );
+    }
+
+    #[test]
+    fn test_headings_ids_and_labels() {
+        let diagnostics = lint(
+            "<html lang=\"en\"><body><h1 id=\"a\">A</h1><h3>C</h3>\
+             <rich-text id=\"r\">x</rich-text><rich-text id=\"r\">y</rich-text>\
+             <input id=\"name\"><label for=\"name\">Name</label>\
+             <label>Email <input type=\"email\"></label><input type=\"hidden\">\
+             <select id=\"a\"></select></body></html>",
+        );
+        assert_eq!(
+            rules(&diagnostics),
+            [
+                Rule::SkippedHeadingLevel,
+                Rule::DuplicateId,
+                Rule::DuplicateId,
+                Rule::FormLabel,
+            ]
+        );
+        assert_eq!(
+            diagnostics[1].message,
+            "Rich-text id r is already used by html > body > rich-text:nth-of-type(1)"
+        );
+    }
+
+    #[test]
+    fn test_lint_directory_finds_rich_text_collisions() {
+        let dir = tempfile::tempdir().unwrap();
+        let page = "<html lang=\"en\"><body><p><rich-text id=\"r\">x</rich-text></p></body></html>";
+        std::fs::write(dir.path().join("a.html"), page).unwrap();
+        std::fs::write(dir.path().join("b.html"), page).unwrap();
+
+        let diagnostics = lint_directory(dir.path()).unwrap();
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].rule, Rule::DuplicateId);
+        assert_eq!(diagnostics[0].file, Path::new("b.html"));
+        assert_eq!(
+            diagnostics[0].message,
+            "Rich-text id r is already used in a.html"
+        );
+    }
+
+    #[test]
+    fn test_diagnostics_are_in_document_order() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("a.html"),
+            "<html lang=\"en\"><body><rich-text id=\"r\">x</rich-text></body></html>",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("b.html"),
+            "<html lang=\"en\"><body><input id=\"name\"><rich-text id=\"r\">x</rich-text>\
+             <img src=\"x.png\"></body></html>",
+        )
+        .unwrap();
+
+        let diagnostics = lint_directory(dir.path()).unwrap();
+        assert_eq!(
+            rules(&diagnostics),
+            [Rule::FormLabel, Rule::DuplicateId, Rule::ImageAlt]
+        );
+    }
+
+    #[test]
+    fn test_shared_ids_are_not_duplicates() {
+        let diagnostics = lint(&format!(
+            "<html lang=\"en\"><body><rich-text id=\"a\">x</rich-text>\
+             <rich-text id=\"b\" {}=\"a\">x</rich-text></body></html>",
+            crate::visitor::duplicate_visitor::SHARED_ID_ATTRIBUTE
+        ));
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    }
+}
@@ -0,0 +1,271 @@
+use super::NodeVisitor;
+use crate::rc_dom::{
+    builder::{self, element},
+    Handle,
+};
+use html5ever::{Attribute, QualName};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use std::{cell::RefCell, collections::HashSet, fmt};
+use tendril::StrTendril;
+
+/// Elements whose text is never scanned for identifiers.
+const SKIPPED_ELEMENTS: &[&str] = &["a", "head", "script", "style", "code", "pre", "textarea"];
+
+/// Characters that commonly trail a DOI in prose but are not part of it. A closing
+/// parenthesis may be either, which [`trim_unbalanced_parens`] decides.
+const DOI_TRAILING_PUNCTUATION: &[char] = &['.', ',', ';', ':', ']', '\'', '"'];
+
+const IDENTIFIER_PATTERN: &str = r"(?xi)
+    (?:\bdoi:\s*|\bhttps?://(?:dx\.)?doi\.org/)?(?P<doi>\b10\.\d{4,9}/[-._;()/:a-z0-9<>]+)
+  | \bPMID:?\s*(?P<pmid>\d{1,8})\b
+  | (?:\bPMCID:?\s*)?\b(?P<pmcid>PMC\d{4,9})\b
+  | \barXiv:\s*(?P<arxiv>\d{4}\.\d{4,5}(?:v\d+)?|[a-z\-]+(?:\.[a-z]{2})?/\d{7}(?:v\d+)?)
+";
+
+/// The kind of persistent identifier recognised in text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdentifierKind {
+    Doi,
+    Pmid,
+    Pmcid,
+    Arxiv,
+}
+
+impl IdentifierKind {
+    /// The prefix used for this kind in `data-ref` values, e.g. `doi` in `doi:10.1234/abc`.
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            IdentifierKind::Doi => "doi",
+            IdentifierKind::Pmid => "pmid",
+            IdentifierKind::Pmcid => "pmcid",
+            IdentifierKind::Arxiv => "arxiv",
+        }
+    }
+}
+
+/// A persistent identifier found in a text node, not yet resolved to a reference.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Identifier {
+    pub kind: IdentifierKind,
+    pub value: String,
+}
+
+impl Identifier {
+    /// The value written to the `data-ref` attribute of the generated anchor.
+    pub fn data_ref(&self) -> String {
+        self.to_string()
+    }
+}
+
+impl fmt::Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.kind.prefix(), self.value)
+    }
+}
+
+/// A visitor that detects DOI, PMID, PMCID and arXiv identifiers in text nodes and wraps
+/// each occurrence in an `<a data-ref="…">` anchor.
+///
+/// The anchors are in the shape expected by `DocumentIdVisitor`, and the identifiers found
+/// are kept in order of first occurrence so they can be resolved by the citation service.
+pub struct CitationAnchorVisitor {
+    pattern: Regex,
+    seen: HashSet<Identifier>,
+    unresolved: Vec<Identifier>,
+}
+
+impl Default for CitationAnchorVisitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CitationAnchorVisitor {
+    pub fn new() -> Self {
+        CitationAnchorVisitor {
+            pattern: Regex::new(IDENTIFIER_PATTERN).expect("identifier pattern is valid"),
+            seen: HashSet::new(),
+            unresolved: Vec::new(),
+        }
+    }
+
+    /// Identifiers that were linked but have not been resolved, deduplicated and in
+    /// document order.
+    pub fn unresolved(&self) -> &[Identifier] {
+        &self.unresolved
+    }
+
+    /// Splits `text` around every identifier it contains. Returns `None` if there are none.
+    fn link_identifiers(&mut self, text: &str) -> Option<Vec<Handle>> {
+        let mut nodes = Vec::new();
+        let mut cursor = 0;
+
+        for captures in self.pattern.captures_iter(text) {
+            let whole = captures.get(0).expect("capture group 0 always exists");
+            if whole.start() < cursor {
+                continue;
+            }
+            let (identifier, end) = identifier_from_captures(&captures);
+
+            if whole.start() > cursor {
+                nodes.push(builder::text(&text[cursor..whole.start()]));
+            }
+            nodes.push(
+                element("a")
+                    .attr("data-ref", identifier.data_ref())
+                    .text(&text[whole.start()..end])
+                    .build(),
+            );
+            cursor = end;
+
+            log::debug!("Linked identifier {}", identifier);
+            if self.seen.insert(identifier.clone()) {
+                self.unresolved.push(identifier);
+            }
+        }
+
+        if nodes.is_empty() {
+            return None;
+        }
+        if cursor < text.len() {
+            nodes.push(builder::text(&text[cursor..]));
+        }
+        Some(nodes)
+    }
+}
+
+impl NodeVisitor for CitationAnchorVisitor {
+    fn visit_element(
+        &mut self,
+        name: &QualName,
+        _attrs: &RefCell<Vec<Attribute>>,
+        _template_contents: &RefCell<Option<Handle>>,
+        _mathml_annotation_xml_integration_point: bool,
+        _handle: &Handle,
+    ) -> (Option<Handle>, bool) {
+        if SKIPPED_ELEMENTS.contains(&name.local.as_ref()) {
+            return (None, false);
+        }
+        (None, true)
+    }
+
+    fn split_text(
+        &mut self,
+        contents: &RefCell<StrTendril>,
+        _handle: &Handle,
+    ) -> Option<Vec<Handle>> {
+        self.link_identifiers(&contents.borrow())
+    }
+}
+
+/// Builds the identifier for a match, returning it with the byte offset where the match
+/// really ends once trailing punctuation has been excluded.
+fn identifier_from_captures(captures: &Captures) -> (Identifier, usize) {
+    let whole = captures.get(0).expect("capture group 0 always exists");
+
+    if let Some(doi) = captures.name("doi") {
+        let value = doi.as_str().trim_end_matches(DOI_TRAILING_PUNCTUATION);
+        let value = trim_unbalanced_parens(value);
+        let identifier = Identifier {
+            kind: IdentifierKind::Doi,
+            value: value.to_string(),
+        };
+        return (identifier, doi.start() + value.len());
+    }
+
+    let (kind, value) = if let Some(pmid) = captures.name("pmid") {
+        (IdentifierKind::Pmid, pmid.as_str().to_string())
+    } else if let Some(pmcid) = captures.name("pmcid") {
+        (IdentifierKind::Pmcid, pmcid.as_str().to_uppercase())
+    } else if let Some(arxiv) = captures.name("arxiv") {
+        (IdentifierKind::Arxiv, arxiv.as_str().to_string())
+    } else {
+        unreachable!("identifier pattern matched without a named group")
+    };
+
+    (Identifier { kind, value }, whole.end())
+}
+
+/// Drops a trailing `)` that closes a parenthesis opened before the DOI, as in
+/// "(doi:10.1234/abc)", while keeping DOIs that contain balanced parentheses.
+fn trim_unbalanced_parens(value: &str) -> &str {
+    let mut value = value;
+    while value.ends_with(')') && value.matches(')').count() > value.matches('(').count() {
+        value = value[..value.len() - 1].trim_end_matches(DOI_TRAILING_PUNCTUATION);
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rc_dom::RcDom;
+
+    fn link(html: &str) -> (String, Vec<Identifier>) {
+        let dom = RcDom::from_str(html);
+        let mut visitor = CitationAnchorVisitor::new();
+        let (document, _) = visitor.traverse(dom.document);
+        (document.to_html_string(), visitor.unresolved().to_vec())
+    }
+
+    #[test]
+    fn test_links_each_identifier_kind() {
+        let (html, ids) = link(
+            "<p>See doi:10.1234/abc.def, PMID: 12345678, PMC1234567 and arXiv:2101.00001v2.</p>",
+        );
+        assert!(html.contains(r#"<a data-ref="doi:10.1234/abc.def">doi:10.1234/abc.def</a>,"#));
+        assert!(html.contains(r#"<a data-ref="pmid:12345678">PMID: 12345678</a>"#));
+        assert!(html.contains(r#"<a data-ref="pmcid:PMC1234567">PMC1234567</a>"#));
+        assert!(html.contains(r#"<a data-ref="arxiv:2101.00001v2">arXiv:2101.00001v2</a>."#));
+        let kinds: Vec<_> = ids.iter().map(|id| id.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                IdentifierKind::Doi,
+                IdentifierKind::Pmid,
+                IdentifierKind::Pmcid,
+                IdentifierKind::Arxiv
+            ]
+        );
+    }
+
+    #[test]
+    fn test_doi_in_parentheses() {
+        let (html, ids) = link("<p>(https://doi.org/10.1000/xyz(12)3)</p>");
+        assert!(html.contains(
+            r#"(<a data-ref="doi:10.1000/xyz(12)3">https://doi.org/10.1000/xyz(12)3</a>)"#
+        ));
+        assert_eq!(ids[0].value, "10.1000/xyz(12)3");
+    }
+
+    #[test]
+    fn test_doi_ending_in_parenthesis() {
+        let (html, ids) = link(
+            "<p>See 10.1002/(sici)1521-3773(19980316). (Also 10.1002/(sici)1521-3773(19980316)).</p>",
+        );
+        assert!(html.contains(
+            r#"See <a data-ref="doi:10.1002/(sici)1521-3773(19980316)">10.1002/(sici)1521-3773(19980316)</a>. (Also"#
+        ));
+        assert!(html.contains(r#"(19980316)</a>).</p>"#));
+        assert_eq!(ids.len(), 1);
+        assert_eq!(ids[0].value, "10.1002/(sici)1521-3773(19980316)");
+    }
+
+    #[test]
+    fn test_skips_existing_links_and_deduplicates() {
+        let (html, ids) = link(
+            r#"<p><a href="x">PMID 111</a> PMID 222 and again PMID 222</p><pre>PMID 333</pre>"#,
+        );
+        assert!(html.contains(r#"<a href="x">PMID 111</a>"#));
+        assert!(html.contains("<pre>PMID 333</pre>"));
+        assert_eq!(
+            ids,
+            vec![Identifier {
+                kind: IdentifierKind::Pmid,
+                value: "222".to_string()
+            }]
+        );
+    }
+}
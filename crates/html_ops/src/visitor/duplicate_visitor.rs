@@ -0,0 +1,575 @@
+use super::NodeVisitor;
+use crate::{
+    rc_dom::{builder::attribute, Handle, RcDom},
+    rich_text::RichTextMarkup,
+    util::fnv1a,
+    walk::process_html_files,
+    whitespace::{normalize_text, WhitespaceOptions},
+};
+use html5ever::{Attribute, QualName};
+use markup5ever::{namespace_url, ns};
+use serde::Serialize;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    io,
+    path::{Path, PathBuf},
+};
+use uuid::Uuid;
+
+/// How blocks are compared.
+#[derive(Debug, Clone)]
+pub struct DuplicateOptions {
+    /// Blocks with fewer words are ignored, as short blocks such as "Yes" are repeated on
+    /// purpose.
+    pub min_words: usize,
+    /// The number of words in each shingle.
+    pub shingle_size: usize,
+    /// The number of MinHash functions; must be a multiple of `bands`.
+    pub hashes: usize,
+    /// The number of locality-sensitive hashing bands the signatures are split into.
+    pub bands: usize,
+    /// The estimated Jaccard similarity above which two blocks are near-duplicates.
+    pub threshold: f64,
+    /// The element and id attribute of the rich-text blocks.
+    pub rich_text: RichTextMarkup,
+}
+
+impl Default for DuplicateOptions {
+    fn default() -> Self {
+        DuplicateOptions {
+            min_words: 5,
+            shingle_size: 3,
+            hashes: 128,
+            bands: 32,
+            threshold: 0.8,
+            rich_text: RichTextMarkup::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateKind {
+    /// Every member has the same markup once whitespace is normalized.
+    Exact,
+    /// The members have similar text.
+    Near,
+}
+
+/// A rich-text block of a file.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct BlockRef {
+    pub file: PathBuf,
+    pub id: Uuid,
+}
+
+/// Blocks with the same or similar content, the first of which is the canonical one.
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateGroup {
+    pub kind: DuplicateKind,
+    /// The lowest estimated similarity between two members found to be near-duplicates,
+    /// or 1 for exact duplicates.
+    pub similarity: f64,
+    /// The text of the canonical block.
+    pub text: String,
+    pub members: Vec<BlockRef>,
+}
+
+impl DuplicateGroup {
+    pub fn canonical(&self) -> &BlockRef {
+        &self.members[0]
+    }
+}
+
+struct Block {
+    block: BlockRef,
+    text: String,
+    /// Hash of the markup with whitespace normalized.
+    hash: u64,
+}
+
+/// Finds duplicated rich-text blocks across the `html_map`s of several files.
+///
+/// Exact duplicates share a hash of their normalized markup. Near-duplicates are found by
+/// comparing MinHash signatures of word shingles, with locality-sensitive hashing so that
+/// only blocks sharing a band of their signature are compared.
+pub struct DuplicateFinder {
+    options: DuplicateOptions,
+    blocks: Vec<Block>,
+}
+
+impl DuplicateFinder {
+    pub fn new(options: DuplicateOptions) -> Self {
+        DuplicateFinder {
+            options,
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Collects the rich-text blocks of every HTML file under `directory`, which should
+    /// hold documents already processed by `OrphanVisitor`. Content outside rich-text
+    /// elements is ignored, as it has no id to share.
+    pub fn from_dir<P: AsRef<Path>>(directory: P, options: DuplicateOptions) -> io::Result<Self> {
+        let mut finder = DuplicateFinder::new(options);
+        process_html_files(directory, |path, relative_path| {
+            let dom = RcDom::from_file(path)?;
+            let mut collector = BlockCollector {
+                markup: &finder.options.rich_text,
+                html_map: HashMap::new(),
+            };
+            collector.traverse(dom.document);
+            let html_map = collector.html_map;
+            finder.add_html_map(relative_path, &html_map);
+            Ok(())
+        })?;
+        Ok(finder)
+    }
+
+    /// Adds the blocks of a file, as collected by `OrphanVisitor`.
+    pub fn add_html_map<P: AsRef<Path>>(&mut self, file: P, html_map: &HashMap<Uuid, String>) {
+        for (id, html) in html_map.iter() {
+            let (markup, text) = normalize_block(html, &self.options.rich_text);
+            if text.split_whitespace().count() < self.options.min_words {
+                continue;
+            }
+            self.blocks.push(Block {
+                block: BlockRef {
+                    file: file.as_ref().to_path_buf(),
+                    id: *id,
+                },
+                text,
+                hash: fnv1a(markup.as_bytes()),
+            });
+        }
+    }
+
+    /// Groups the blocks added so far, in order of file and id of their canonical block.
+    ///
+    /// Every set of exact duplicates is an [`Exact`](DuplicateKind::Exact) group. Blocks
+    /// similar to blocks with other markup also form a [`Near`](DuplicateKind::Near) group,
+    /// which lists their exact duplicates too.
+    pub fn groups(&self) -> Vec<DuplicateGroup> {
+        let mut blocks: Vec<&Block> = self.blocks.iter().collect();
+        blocks.sort_by(|a, b| a.block.cmp(&b.block));
+
+        // Exact duplicates form classes, compared with each other through their first block.
+        let mut classes: Vec<Vec<&Block>> = Vec::new();
+        let mut class_by_hash = HashMap::new();
+        for block in blocks {
+            let class = *class_by_hash.entry(block.hash).or_insert_with(|| {
+                classes.push(Vec::new());
+                classes.len() - 1
+            });
+            classes[class].push(block);
+        }
+
+        let signatures: Vec<Vec<u64>> = classes
+            .iter()
+            .map(|class| {
+                minhash(
+                    &shingles(&class[0].text, self.options.shingle_size),
+                    self.options.hashes,
+                )
+            })
+            .collect();
+        let mut components = UnionFind::new(classes.len());
+        let mut edges = Vec::new();
+        for (first, second) in self.candidates(&signatures) {
+            let similarity = estimate_similarity(&signatures[first], &signatures[second]);
+            if similarity >= self.options.threshold {
+                components.union(first, second);
+                edges.push((first, similarity));
+            }
+        }
+
+        let mut members: Vec<Vec<usize>> = vec![Vec::new(); classes.len()];
+        for class in 0..classes.len() {
+            members[components.find(class)].push(class);
+        }
+        let mut lowest_similarity = vec![1.0_f64; classes.len()];
+        for (class, similarity) in edges {
+            let root = components.find(class);
+            lowest_similarity[root] = lowest_similarity[root].min(similarity);
+        }
+
+        let mut groups = Vec::new();
+        for (root, component) in members.iter().enumerate() {
+            for &class in component.iter().filter(|&&class| classes[class].len() > 1) {
+                groups.push(DuplicateGroup {
+                    kind: DuplicateKind::Exact,
+                    similarity: 1.0,
+                    text: classes[class][0].text.clone(),
+                    members: classes[class]
+                        .iter()
+                        .map(|block| block.block.clone())
+                        .collect(),
+                });
+            }
+            if component.len() > 1 {
+                groups.push(DuplicateGroup {
+                    kind: DuplicateKind::Near,
+                    similarity: lowest_similarity[root],
+                    text: classes[component[0]][0].text.clone(),
+                    members: component
+                        .iter()
+                        .flat_map(|&class| classes[class].iter().map(|block| block.block.clone()))
+                        .collect(),
+                });
+            }
+        }
+        // Stable, so exact groups stay before the near group they are part of
+        groups.sort_by(|a, b| a.canonical().cmp(b.canonical()));
+        groups
+    }
+
+    /// Pairs of classes sharing at least one band of their signatures.
+    fn candidates(&self, signatures: &[Vec<u64>]) -> Vec<(usize, usize)> {
+        let rows = (self.options.hashes / self.options.bands.max(1)).max(1);
+        let mut pairs = HashSet::new();
+        for (band, start) in (0..self.options.hashes).step_by(rows).enumerate() {
+            let mut buckets: HashMap<u64, Vec<usize>> = HashMap::new();
+            for (class, signature) in signatures.iter().enumerate() {
+                let end = (start + rows).min(signature.len());
+                let mut bytes = (band as u64).to_le_bytes().to_vec();
+                for value in signature[start..end].iter() {
+                    bytes.extend(value.to_le_bytes());
+                }
+                buckets.entry(fnv1a(&bytes)).or_default().push(class);
+            }
+            for bucket in buckets.values() {
+                for (i, &first) in bucket.iter().enumerate() {
+                    for &second in bucket[i + 1..].iter() {
+                        pairs.insert((first, second));
+                    }
+                }
+            }
+        }
+        let mut pairs: Vec<_> = pairs.into_iter().collect();
+        pairs.sort();
+        pairs
+    }
+}
+
+/// Maps the id of every duplicate to the id of the canonical block of its group. Only exact
+/// duplicates are included unless `include_near` is set, as merging near-duplicates drops
+/// their differences. Near groups then take precedence, so that every member of one maps
+/// to the same canonical block.
+pub fn shared_ids(groups: &[DuplicateGroup], include_near: bool) -> HashMap<Uuid, Uuid> {
+    let (exact, near): (Vec<_>, Vec<_>) = groups
+        .iter()
+        .partition(|group| group.kind == DuplicateKind::Exact);
+    exact
+        .into_iter()
+        .chain(near.into_iter().filter(|_| include_near))
+        .flat_map(|group| {
+            let canonical = group.canonical().id;
+            group.members[1..]
+                .iter()
+                .filter(move |member| member.id != canonical)
+                .map(move |member| (member.id, canonical))
+        })
+        .collect()
+}
+
+/// Collects the existing rich-text blocks of a document by id, leaving it untouched.
+struct BlockCollector<'a> {
+    markup: &'a RichTextMarkup,
+    html_map: HashMap<Uuid, String>,
+}
+
+impl NodeVisitor for BlockCollector<'_> {
+    fn visit_element(
+        &mut self,
+        _name: &QualName,
+        _attrs: &RefCell<Vec<Attribute>>,
+        _template_contents: &RefCell<Option<Handle>>,
+        _mathml_annotation_xml_integration_point: bool,
+        handle: &Handle,
+    ) -> (Option<Handle>, bool) {
+        let Some(id) = self.markup.id_of(handle) else {
+            return (None, true);
+        };
+        match Uuid::parse_str(&id) {
+            Ok(id) => {
+                self.html_map.insert(id, handle.to_html_string());
+            }
+            Err(_) => log::debug!("Skipping rich-text block with non-UUID id {:?}", id),
+        }
+        (None, false)
+    }
+}
+
+/// The attribute holding the id of the canonical block on its duplicates.
+pub const SHARED_ID_ATTRIBUTE: &str = "data-shared-id";
+
+/// A visitor that marks each duplicate rich-text element with the id of its canonical
+/// block, in [`SHARED_ID_ATTRIBUTE`], so that a single edit can propagate to every copy.
+///
+/// Every block keeps its own id: two elements of a page never share one, and the blocks
+/// stay in the database.
+pub struct SharedIdVisitor<'a> {
+    ids: &'a HashMap<Uuid, Uuid>,
+    marked: usize,
+    markup: RichTextMarkup,
+}
+
+impl<'a> SharedIdVisitor<'a> {
+    pub fn new(ids: &'a HashMap<Uuid, Uuid>) -> Self {
+        SharedIdVisitor {
+            ids,
+            marked: 0,
+            markup: RichTextMarkup::default(),
+        }
+    }
+
+    /// Sets the element and id attribute of the rich-text blocks.
+    pub fn with_rich_text_markup(mut self, markup: RichTextMarkup) -> Self {
+        self.markup = markup;
+        self
+    }
+
+    /// The number of elements marked as duplicates.
+    pub fn marked(&self) -> usize {
+        self.marked
+    }
+}
+
+impl NodeVisitor for SharedIdVisitor<'_> {
+    fn visit_element(
+        &mut self,
+        name: &QualName,
+        attrs: &RefCell<Vec<Attribute>>,
+        _template_contents: &RefCell<Option<Handle>>,
+        _mathml_annotation_xml_integration_point: bool,
+        _handle: &Handle,
+    ) -> (Option<Handle>, bool) {
+        if !self.markup.is_rich_text(name) {
+            return (None, true);
+        }
+        let mut attrs = attrs.borrow_mut();
+        let Some(canonical) = self.markup.uuid(&attrs).and_then(|id| self.ids.get(&id)) else {
+            return (None, false);
+        };
+        let canonical = canonical.to_string();
+        let shared_id = attrs
+            .iter_mut()
+            .find(|attr| attr.name.ns == ns!() && attr.name.local.as_ref() == SHARED_ID_ATTRIBUTE);
+        match shared_id {
+            Some(shared_id) if *shared_id.value == *canonical => return (None, false),
+            Some(shared_id) => shared_id.value = canonical.into(),
+            None => attrs.push(attribute(SHARED_ID_ATTRIBUTE, &canonical)),
+        }
+        self.marked += 1;
+        (None, false)
+    }
+}
+
+/// The markup of a block without its rich-text wrapper, and its text, both with
+/// whitespace normalized.
+fn normalize_block(html: &str, rich_text: &RichTextMarkup) -> (String, String) {
+    let dom = RcDom::from_fragment_str(html);
+    let mut root = dom.fragment_root();
+    let wrapper = {
+        let children = root.children.borrow();
+        match children.as_slice() {
+            [only] if rich_text.is_rich_text_node(only) => Some(only.clone()),
+            _ => None,
+        }
+    };
+    if let Some(wrapper) = wrapper {
+        root = wrapper;
+    }
+    let options = WhitespaceOptions::default();
+    (
+        normalize_text(&root.inner_html(), &options),
+        normalize_text(&root.text_content(), &options),
+    )
+}
+
+/// Hashes of the overlapping runs of `size` words of the text, ignoring case and
+/// punctuation.
+fn shingles(text: &str, size: usize) -> Vec<u64> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    words
+        .windows(size.clamp(1, words.len().max(1)))
+        .map(|window| fnv1a(window.join(" ").as_bytes()))
+        .collect()
+}
+
+/// The minimum of each of `count` hash functions over the shingles.
+fn minhash(shingles: &[u64], count: usize) -> Vec<u64> {
+    (0..count as u64)
+        .map(|seed| {
+            let seed = seed.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+            shingles
+                .iter()
+                .map(|&shingle| mix(shingle ^ seed))
+                .min()
+                .unwrap_or(u64::MAX)
+        })
+        .collect()
+}
+
+/// The share of equal values in two signatures, which estimates the Jaccard similarity of
+/// their shingles.
+fn estimate_similarity(first: &[u64], second: &[u64]) -> f64 {
+    let equal = first.iter().zip(second).filter(|(a, b)| a == b).count();
+    equal as f64 / first.len().max(1) as f64
+}
+
+/// The SplitMix64 finalizer, used to derive independent hash functions from one hash.
+fn mix(mut value: u64) -> u64 {
+    value = (value ^ (value >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    value = (value ^ (value >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    value ^ (value >> 31)
+}
+
+struct UnionFind {
+    parents: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(size: usize) -> Self {
+        UnionFind {
+            parents: (0..size).collect(),
+        }
+    }
+
+    fn find(&mut self, mut node: usize) -> usize {
+        while self.parents[node] != node {
+            self.parents[node] = self.parents[self.parents[node]];
+            node = self.parents[node];
+        }
+        node
+    }
+
+    /// Joins two sets, keeping the smaller root so that groups are led by their first
+    /// class.
+    fn union(&mut self, first: usize, second: usize) {
+        let (first, second) = (self.find(first), self.find(second));
+        let (root, child) = (first.min(second), first.max(second));
+        self.parents[child] = root;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DISCLAIMER: &str = "This information does not replace the advice of your doctor \
+        or pharmacist. Always read the label before taking any medicine.";
+
+    fn html_map(blocks: &[(u128, &str)]) -> HashMap<Uuid, String> {
+        blocks
+            .iter()
+            .map(|&(id, html)| (Uuid::from_u128(id), html.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_exact_and_near_duplicates() {
+        let mut finder = DuplicateFinder::new(DuplicateOptions::default());
+        finder.add_html_map(
+            "a.html",
+            &html_map(&[
+                (1, DISCLAIMER),
+                (2, "Take one tablet twice a day with food."),
+                (3, "Yes"),
+            ]),
+        );
+        let wrapped = format!(
+            "<rich-text id=\"{}\">\n  {}</rich-text>",
+            Uuid::from_u128(4),
+            DISCLAIMER
+        );
+        finder.add_html_map(
+            "b.html",
+            &html_map(&[
+                (4, &wrapped),
+                (5, &DISCLAIMER.replace("medicine.", "medicines.")),
+                (6, "Yes"),
+            ]),
+        );
+
+        let groups = finder.groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].kind, DuplicateKind::Exact);
+        let ids: Vec<_> = groups[0].members.iter().map(|m| m.id.as_u128()).collect();
+        assert_eq!(ids, [1, 4]);
+        let group = &groups[1];
+        assert_eq!(group.kind, DuplicateKind::Near);
+        assert!(group.similarity >= 0.8 && group.similarity < 1.0);
+        assert_eq!(group.text, DISCLAIMER);
+        let ids: Vec<_> = group.members.iter().map(|m| m.id.as_u128()).collect();
+        assert_eq!(ids, [1, 4, 5]);
+        assert_eq!(group.canonical().file, Path::new("a.html"));
+
+        let exact_only = shared_ids(&groups, false);
+        assert_eq!(exact_only.len(), 1);
+        assert_eq!(exact_only[&Uuid::from_u128(4)], Uuid::from_u128(1));
+        let all = shared_ids(&groups, true);
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[&Uuid::from_u128(4)], Uuid::from_u128(1));
+        assert_eq!(all[&Uuid::from_u128(5)], Uuid::from_u128(1));
+    }
+
+    #[test]
+    fn test_from_dir_reads_existing_blocks_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let block = |id: u128| {
+            format!(
+                "<rich-text id=\"{}\">{}</rich-text>",
+                Uuid::from_u128(id),
+                DISCLAIMER
+            )
+        };
+        std::fs::write(
+            dir.path().join("a.html"),
+            format!("<p>{}</p><p>{}</p>", block(1), DISCLAIMER),
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("b.html"), format!("<p>{}</p>", block(2))).unwrap();
+
+        let groups = DuplicateFinder::from_dir(dir.path(), DuplicateOptions::default())
+            .unwrap()
+            .groups();
+        assert_eq!(groups.len(), 1);
+        let ids: Vec<_> = groups[0].members.iter().map(|m| m.id.as_u128()).collect();
+        assert_eq!(ids, [1, 2]);
+    }
+
+    #[test]
+    fn test_share_ids() {
+        let mut finder = DuplicateFinder::new(DuplicateOptions::default());
+        finder.add_html_map("a.html", &html_map(&[(1, DISCLAIMER)]));
+        finder.add_html_map("b.html", &html_map(&[(2, DISCLAIMER)]));
+        let groups = finder.groups();
+        assert_eq!(groups[0].kind, DuplicateKind::Exact);
+        let ids = shared_ids(&groups, false);
+
+        let html = format!(
+            "<p><rich-text id=\"{}\">{}</rich-text></p>",
+            Uuid::from_u128(2),
+            DISCLAIMER
+        );
+        let dom = RcDom::from_str(&html);
+        let mut visitor = SharedIdVisitor::new(&ids);
+        let (document, _) = visitor.traverse(dom.document);
+        assert_eq!(visitor.marked(), 1);
+        assert!(document.to_html_string().contains(&format!(
+            "<rich-text id=\"{}\" data-shared-id=\"{}\">",
+            Uuid::from_u128(2),
+            Uuid::from_u128(1)
+        )));
+
+        // Marking again changes nothing
+        let mut visitor = SharedIdVisitor::new(&ids);
+        visitor.traverse(document);
+        assert_eq!(visitor.marked(), 0);
+    }
+}
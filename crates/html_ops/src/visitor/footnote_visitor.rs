@@ -0,0 +1,564 @@
+use super::NodeVisitor;
+use crate::{
+    node,
+    rc_dom::{
+        builder::{self, element},
+        Handle, NodeData,
+    },
+    rich_text::RichTextMarkup,
+    util::{attribute, element_name, parent},
+};
+use html5ever::{Attribute, QualName};
+use regex::Regex;
+use serde::Serialize;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+};
+use uuid::Uuid;
+
+/// The attribute tying the rich-text block of a note to the block citing it.
+pub const NOTE_FOR_ATTRIBUTE: &str = "data-note-for";
+
+/// Ids of notes and of their references, as written by Word (`_ftn1`, `_ftnref1`,
+/// `_edn1`), Pandoc (`fn1`, `fnref1`), Markdown processors (`fn:1`, `fnref:1`) and this
+/// visitor (`fn-1`, `fnref-1`).
+const NOTE_ID_PATTERN: &str =
+    r"(?i)^_?(?P<kind>ftn|fn|footnote|note|edn|endnote)(?P<reference>ref)?[-:_]?(?P<number>\d+)$";
+
+/// Elements that can hold a note when its id is on an anchor inside it.
+const NOTE_CONTAINERS: &[&str] = &["li", "p", "div", "aside", "section", "td"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteKind {
+    Footnote,
+    Endnote,
+}
+
+impl NoteKind {
+    /// The prefix of the ids written for notes of this kind.
+    fn prefix(self) -> &'static str {
+        match self {
+            NoteKind::Footnote => "fn",
+            NoteKind::Endnote => "edn",
+        }
+    }
+}
+
+/// A note linked to its references.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Footnote {
+    pub kind: NoteKind,
+    /// The number of the note, in order of first reference.
+    pub number: usize,
+    /// The id of the rich-text block holding the note.
+    pub id: Uuid,
+    /// The id of the rich-text block containing the first reference.
+    pub citing: Option<Uuid>,
+    /// The number of references to the note.
+    pub references: usize,
+}
+
+struct Reference {
+    anchor: Handle,
+    key: (NoteKind, u32),
+    citing: Option<Uuid>,
+}
+
+/// A visitor that recognizes footnote and endnote references and the notes they point to,
+/// then links them both ways with [`FootnoteVisitor::link_notes`].
+///
+/// Notes are numbered in order of their first reference, so running the visitor again
+/// after an edit renumbers them. The content of each note is gathered into a single
+/// rich-text block tied to the block citing it by a `data-note-for` attribute, keeping the
+/// id of the first rich-text block found in the note.
+pub struct FootnoteVisitor {
+    pattern: Regex,
+    references: Vec<Reference>,
+    notes: BTreeMap<(NoteKind, u32), Handle>,
+    linked: Vec<Footnote>,
+    html_map: HashMap<Uuid, String>,
+    dangling: Vec<String>,
+    unreferenced: Vec<String>,
+    markup: RichTextMarkup,
+}
+
+impl Default for FootnoteVisitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FootnoteVisitor {
+    pub fn new() -> Self {
+        FootnoteVisitor {
+            pattern: Regex::new(NOTE_ID_PATTERN).expect("note id pattern is valid"),
+            references: Vec::new(),
+            notes: BTreeMap::new(),
+            linked: Vec::new(),
+            html_map: HashMap::new(),
+            dangling: Vec::new(),
+            unreferenced: Vec::new(),
+            markup: RichTextMarkup::default(),
+        }
+    }
+
+    /// Sets the element and id attribute of rich-text blocks, both those found in notes and
+    /// those the notes are moved into.
+    pub fn with_rich_text_markup(mut self, markup: RichTextMarkup) -> Self {
+        self.markup = markup;
+        self
+    }
+
+    /// The notes linked by [`FootnoteVisitor::link_notes`], by kind and number.
+    pub fn notes(&self) -> &[Footnote] {
+        &self.linked
+    }
+
+    /// The rich-text blocks of the linked notes.
+    pub fn html_map(&self) -> &HashMap<Uuid, String> {
+        &self.html_map
+    }
+
+    /// Reference targets without a note.
+    pub fn dangling(&self) -> &[String] {
+        &self.dangling
+    }
+
+    /// Ids of notes without a reference, which are left untouched.
+    pub fn unreferenced(&self) -> &[String] {
+        &self.unreferenced
+    }
+
+    /// Parses a note or reference id into its kind, number, and whether it is the id of a
+    /// reference.
+    fn parse_id(&self, id: &str) -> Option<(NoteKind, u32, bool)> {
+        let captures = self.pattern.captures(id)?;
+        let kind = match captures["kind"].to_lowercase().as_str() {
+            "edn" | "endnote" => NoteKind::Endnote,
+            _ => NoteKind::Footnote,
+        };
+        let number = captures["number"].parse().ok()?;
+        Some((kind, number, captures.name("reference").is_some()))
+    }
+
+    /// Renumbers the notes found during traversal, links each reference to its note and
+    /// back, and moves the content of each note into its own rich-text block.
+    pub fn link_notes(&mut self) -> &[Footnote] {
+        let mut numbers: HashMap<(NoteKind, u32), usize> = HashMap::new();
+        let mut counts: HashMap<NoteKind, usize> = HashMap::new();
+        for reference in self.references.iter() {
+            if !self.notes.contains_key(&reference.key) {
+                let (kind, number) = reference.key;
+                log::warn!("Reference to missing {:?} {}", kind, number);
+                self.dangling
+                    .push(attribute(&reference.anchor, "href").unwrap_or_default());
+                continue;
+            }
+            numbers.entry(reference.key).or_insert_with(|| {
+                let count = counts.entry(reference.key.0).or_default();
+                *count += 1;
+                *count
+            });
+        }
+        for (key, note) in self.notes.iter() {
+            if !numbers.contains_key(key) {
+                self.unreferenced
+                    .push(attribute(note, "id").unwrap_or_else(|| format!("{}", key.1)));
+            }
+        }
+
+        let mut linked: BTreeMap<(NoteKind, usize), Footnote> = BTreeMap::new();
+        for reference in self.references.iter() {
+            let Some(&number) = numbers.get(&reference.key) else {
+                continue;
+            };
+            let kind = reference.key.0;
+            let note = linked.entry((kind, number)).or_insert_with(|| Footnote {
+                kind,
+                number,
+                id: Uuid::nil(),
+                citing: reference.citing,
+                references: 0,
+            });
+            note.references += 1;
+            let id = match note.references {
+                1 => format!("{}ref-{}", kind.prefix(), number),
+                n => format!("{}ref-{}-{}", kind.prefix(), number, n),
+            };
+            rewrite_reference(&reference.anchor, &id, kind, number);
+        }
+
+        for (key, container) in self.notes.iter() {
+            let Some(&number) = numbers.get(key) else {
+                continue;
+            };
+            let note = linked
+                .get_mut(&(key.0, number))
+                .expect("numbered notes are referenced");
+            let rich_text = self.extract_note(container, note);
+            self.html_map.insert(note.id, rich_text.to_html_string());
+        }
+        reorder_notes(
+            self.notes
+                .iter()
+                .filter_map(|(key, note)| Some(((key.0, *numbers.get(key)?), note.clone()))),
+        );
+
+        self.linked = linked.into_values().collect();
+        &self.linked
+    }
+
+    /// Replaces the content of a note with a rich-text block followed by a backlink.
+    fn extract_note(&self, container: &Handle, note: &mut Footnote) -> Handle {
+        let mut id = None;
+        let mut content = self.strip_markers(container.children.take(), &mut id);
+        trim_edges(&mut content);
+        note.id = id.unwrap_or_else(Uuid::new_v4);
+
+        let rich_text = self
+            .markup
+            .element(&note.id.to_string())
+            .attr_opt(
+                NOTE_FOR_ATTRIBUTE,
+                note.citing.map(|citing| citing.to_string()),
+            )
+            .child(content)
+            .build();
+        let backlink = node!(a [
+            href = format!("#{}ref-{}", note.kind.prefix(), note.number),
+            role = "doc-backlink",
+        ] { "↩" });
+        builder::set_children(
+            container,
+            vec![rich_text.clone(), builder::text(" "), backlink],
+        );
+        set_attribute(
+            container,
+            "id",
+            &format!("{}-{}", note.kind.prefix(), note.number),
+        );
+        rich_text
+    }
+
+    /// Removes number markers and backlinks from the content of a note, and unwraps its
+    /// rich-text blocks, keeping the id of the first one.
+    fn strip_markers(&self, children: Vec<Handle>, id: &mut Option<Uuid>) -> Vec<Handle> {
+        let mut stripped = Vec::with_capacity(children.len());
+        for child in children {
+            match element_name(&child) {
+                Some(_) if self.markup.is_rich_text_node(&child) => {
+                    if id.is_none() {
+                        *id = self
+                            .markup
+                            .id_of(&child)
+                            .and_then(|id| Uuid::parse_str(&id).ok());
+                    }
+                    stripped.extend(self.strip_markers(child.children.take(), id));
+                }
+                Some("a") if self.is_marker(&child) => {}
+                Some(_) => {
+                    let grandchildren = self.strip_markers(child.children.take(), id);
+                    builder::set_children(&child, grandchildren);
+                    stripped.push(child);
+                }
+                None => stripped.push(child),
+            }
+        }
+        stripped
+    }
+
+    /// Whether an anchor inside a note is its number or a link back to the reference.
+    fn is_marker(&self, anchor: &Handle) -> bool {
+        let backlink = attribute(anchor, "href")
+            .and_then(|href| self.parse_id(href.strip_prefix('#')?))
+            .is_some_and(|(_, _, reference)| reference);
+        let number = ["name", "id"]
+            .iter()
+            .filter_map(|name| attribute(anchor, name))
+            .any(|id| {
+                self.parse_id(&id)
+                    .is_some_and(|(_, _, reference)| !reference)
+            });
+        backlink || number || attribute(anchor, "role").as_deref() == Some("doc-backlink")
+    }
+}
+
+impl NodeVisitor for FootnoteVisitor {
+    fn visit_element(
+        &mut self,
+        name: &QualName,
+        attrs: &RefCell<Vec<Attribute>>,
+        _template_contents: &RefCell<Option<Handle>>,
+        _mathml_annotation_xml_integration_point: bool,
+        handle: &Handle,
+    ) -> (Option<Handle>, bool) {
+        let is_anchor = name.local.as_ref() == "a";
+        if is_anchor {
+            let target = attribute(handle, "href")
+                .and_then(|href| href.strip_prefix('#').map(str::to_string))
+                .and_then(|target| self.parse_id(&target));
+            if let Some((kind, number, false)) = target {
+                self.references.push(Reference {
+                    anchor: handle.clone(),
+                    key: (kind, number),
+                    citing: citing_block(&self.markup, handle),
+                });
+                return (None, false);
+            }
+        }
+
+        let ids: Vec<String> = attrs
+            .borrow()
+            .iter()
+            .filter(|attr| matches!(attr.name.local.as_ref(), "id" | "name"))
+            .map(|attr| attr.value.to_string())
+            .collect();
+        for id in ids {
+            let Some((kind, number, false)) = self.parse_id(&id) else {
+                continue;
+            };
+            // The note is the element with the id, or the block around a Word anchor.
+            let container = if is_anchor {
+                container_of(handle)
+            } else {
+                Some(handle.clone())
+            };
+            if let Some(container) = container {
+                self.notes.entry((kind, number)).or_insert(container);
+            }
+        }
+        (None, true)
+    }
+}
+
+/// Points a reference at its note, with the note number as its only content.
+fn rewrite_reference(anchor: &Handle, id: &str, kind: NoteKind, number: usize) {
+    if let NodeData::Element { ref attrs, .. } = anchor.data {
+        attrs
+            .borrow_mut()
+            .retain(|attr| !matches!(attr.name.local.as_ref(), "name" | "id" | "href" | "role"));
+    }
+    set_attribute(anchor, "id", id);
+    set_attribute(anchor, "href", &format!("#{}-{}", kind.prefix(), number));
+    set_attribute(anchor, "role", "doc-noteref");
+
+    let number = builder::text(&number.to_string());
+    let in_sup = parent(anchor).is_some_and(|parent| element_name(&parent) == Some("sup"));
+    let content = if in_sup {
+        number
+    } else {
+        element("sup").child(number).build()
+    };
+    builder::set_children(anchor, vec![content]);
+}
+
+/// A note with its kind and number.
+type NumberedNote = ((NoteKind, usize), Handle);
+
+/// Sorts notes sharing a parent by number, keeping the positions of the other children.
+fn reorder_notes(notes: impl Iterator<Item = NumberedNote>) {
+    let mut by_parent: Vec<(Handle, Vec<NumberedNote>)> = Vec::new();
+    for (key, note) in notes {
+        let Some(parent) = parent(&note) else {
+            continue;
+        };
+        match by_parent
+            .iter_mut()
+            .find(|(other, _)| std::ptr::eq(&**other, &*parent))
+        {
+            Some((_, siblings)) => siblings.push((key, note)),
+            None => by_parent.push((parent, vec![(key, note)])),
+        }
+    }
+
+    for (parent, mut notes) in by_parent {
+        let mut children = parent.children.borrow_mut();
+        let positions: Vec<usize> = children
+            .iter()
+            .enumerate()
+            .filter(|(_, child)| {
+                notes
+                    .iter()
+                    .any(|(_, note)| std::ptr::eq(&***child, &**note))
+            })
+            .map(|(index, _)| index)
+            .collect();
+        notes.sort_by_key(|(key, _)| *key);
+        for (position, (_, note)) in positions.into_iter().zip(notes) {
+            children[position] = note;
+        }
+    }
+}
+
+/// Trims the whitespace at the start and end of the content of a note.
+fn trim_edges(content: &mut Vec<Handle>) {
+    trim_start(content);
+    trim_end(content);
+}
+
+fn trim_start(nodes: &mut Vec<Handle>) {
+    while let Some(first) = nodes.first().cloned() {
+        match first.data {
+            NodeData::Text { ref contents } => {
+                let trimmed = contents.borrow().trim_start().to_string();
+                if !trimmed.is_empty() {
+                    *contents.borrow_mut() = trimmed.into();
+                    return;
+                }
+                nodes.remove(0);
+            }
+            NodeData::Element { .. } => {
+                trim_start(&mut first.children.borrow_mut());
+                return;
+            }
+            _ => return,
+        }
+    }
+}
+
+fn trim_end(nodes: &mut Vec<Handle>) {
+    while let Some(last) = nodes.last().cloned() {
+        match last.data {
+            NodeData::Text { ref contents } => {
+                let trimmed = contents.borrow().trim_end().to_string();
+                if !trimmed.is_empty() {
+                    *contents.borrow_mut() = trimmed.into();
+                    return;
+                }
+                nodes.pop();
+            }
+            NodeData::Element { .. } => {
+                trim_end(&mut last.children.borrow_mut());
+                return;
+            }
+            _ => return,
+        }
+    }
+}
+
+/// The id of the rich-text block containing a reference.
+fn citing_block(markup: &RichTextMarkup, node: &Handle) -> Option<Uuid> {
+    let mut current = parent(node)?;
+    loop {
+        if markup.is_rich_text_node(&current) {
+            return markup
+                .id_of(&current)
+                .and_then(|id| Uuid::parse_str(&id).ok());
+        }
+        current = parent(&current)?;
+    }
+}
+
+/// The nearest block around a note anchor.
+fn container_of(node: &Handle) -> Option<Handle> {
+    let mut current = parent(node)?;
+    loop {
+        if element_name(&current).is_some_and(|name| NOTE_CONTAINERS.contains(&name)) {
+            return Some(current);
+        }
+        current = parent(&current)?;
+    }
+}
+
+fn set_attribute(node: &Handle, name: &str, value: &str) {
+    let NodeData::Element { ref attrs, .. } = node.data else {
+        return;
+    };
+    let mut attrs = attrs.borrow_mut();
+    match attrs
+        .iter_mut()
+        .find(|attr| attr.name.local.as_ref() == name)
+    {
+        Some(attr) => attr.value = value.into(),
+        None => attrs.push(builder::attribute(name, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rc_dom::RcDom;
+
+    const CITING: &str = "11111111-1111-1111-1111-111111111111";
+    const NOTE: &str = "22222222-2222-2222-2222-222222222222";
+
+    fn link(html: &str) -> (FootnoteVisitor, String) {
+        let dom = RcDom::from_fragment_str(html);
+        let mut visitor = FootnoteVisitor::new();
+        let (root, _) = visitor.traverse(dom.fragment_root());
+        visitor.link_notes();
+        (visitor, root.inner_html())
+    }
+
+    #[test]
+    fn test_word_notes() {
+        let (visitor, html) = link(&format!(
+            "<p><rich-text id=\"{CITING}\">Dose<a href=\"#_ftn1\" name=\"_ftnref1\">\
+             <span class=\"MsoFootnoteReference\">[1]</span></a>.</rich-text></p>\
+             <div id=\"ftn1\"><p class=\"MsoFootnoteText\"><rich-text id=\"{NOTE}\">\
+             <a href=\"#_ftnref1\" name=\"_ftn1\">[1]</a> See the label.</rich-text></p></div>"
+        ));
+        assert_eq!(
+            html,
+            format!(
+                "<p><rich-text id=\"{CITING}\">Dose<a id=\"fnref-1\" href=\"#fn-1\" \
+                 role=\"doc-noteref\"><sup>1</sup></a>.</rich-text></p>\
+                 <div id=\"fn-1\"><rich-text id=\"{NOTE}\" data-note-for=\"{CITING}\">\
+                 <p class=\"MsoFootnoteText\">See the label.</p></rich-text> \
+                 <a href=\"#fnref-1\" role=\"doc-backlink\">↩</a></div>"
+            )
+        );
+        let note = &visitor.notes()[0];
+        assert_eq!(note.id.to_string(), NOTE);
+        assert_eq!(
+            note.citing.map(|id| id.to_string()).as_deref(),
+            Some(CITING)
+        );
+        assert_eq!(
+            visitor.html_map()[&note.id],
+            "<p class=\"MsoFootnoteText\">See the label.</p>"
+        );
+    }
+
+    #[test]
+    fn test_renumbers_by_reference_order() {
+        let (visitor, html) = link(
+            "<p>A<sup><a href=\"#fn2\">2</a></sup> B<sup><a href=\"#fn1\">1</a></sup> \
+             C<sup><a href=\"#fn2\">2</a></sup> D<sup><a href=\"#fn9\">9</a></sup></p>\
+             <ol><li id=\"fn1\">One <a href=\"#fnref1\">↩</a></li>\
+             <li id=\"fn2\">Two <a href=\"#fnref2\">↩</a></li><li id=\"fn3\">Three</li></ol>",
+        );
+        assert!(html.starts_with(
+            "<p>A<sup><a id=\"fnref-1\" href=\"#fn-1\" role=\"doc-noteref\">1</a></sup> \
+             B<sup><a id=\"fnref-2\" href=\"#fn-2\" role=\"doc-noteref\">2</a></sup> \
+             C<sup><a id=\"fnref-1-2\" href=\"#fn-1\" role=\"doc-noteref\">1</a></sup>"
+        ));
+        let ids: Vec<_> = RcDom::from_fragment_str(&html)
+            .fragment_root()
+            .children
+            .borrow()[1]
+            .children
+            .borrow()
+            .iter()
+            .map(|li| (attribute(li, "id").unwrap(), li.text_content()))
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                ("fn-1".to_string(), "Two ↩".to_string()),
+                ("fn-2".to_string(), "One ↩".to_string()),
+                ("fn3".to_string(), "Three".to_string()),
+            ]
+        );
+        assert_eq!(visitor.notes()[0].references, 2);
+        assert_eq!(visitor.dangling(), ["#fn9"]);
+        assert_eq!(visitor.unreferenced(), ["fn3"]);
+
+        // Running again keeps the numbers and the note ids
+        let (again, rerun) = link(&html);
+        assert_eq!(rerun, html);
+        assert_eq!(again.notes()[1].id, visitor.notes()[1].id);
+    }
+}
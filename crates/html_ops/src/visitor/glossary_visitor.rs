@@ -0,0 +1,362 @@
+use super::NodeVisitor;
+use crate::{
+    rc_dom::{
+        builder::{self, element},
+        Handle,
+    },
+    util::heading_level,
+    whitespace::{collapse_whitespace, WhitespaceOptions},
+};
+use html5ever::{Attribute, QualName};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    io,
+    path::Path,
+};
+use tendril::StrTendril;
+
+/// The attribute holding the definition id on linked terms.
+pub const GLOSSARY_ATTRIBUTE: &str = "data-glossary";
+
+/// Elements whose text is never scanned for terms, besides headings.
+const SKIPPED_ELEMENTS: &[&str] = &[
+    "a", "abbr", "code", "pre", "kbd", "samp", "script", "style", "textarea", "head",
+];
+
+/// A glossary term, with the synonyms that link to the same definition.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct GlossaryEntry {
+    pub term: String,
+    #[serde(default)]
+    pub synonyms: Vec<String>,
+    /// The id of the definition, as used in the glossary page.
+    pub id: String,
+    /// A short definition, used as the `title` of `<abbr>` markup.
+    #[serde(default)]
+    pub definition: Option<String>,
+}
+
+/// A set of glossary entries compiled into a single matcher.
+///
+/// Matching is case-insensitive, except for terms with capitals after their first letter,
+/// such as "SSRI" or "pH", which only match as written. Terms only match whole words, and
+/// the whitespace between the words of a term may be any run of whitespace.
+#[derive(Debug, Clone)]
+pub struct Glossary {
+    entries: Vec<GlossaryEntry>,
+    pattern: Option<Regex>,
+    exact: HashMap<String, usize>,
+    folded: HashMap<String, usize>,
+}
+
+impl Glossary {
+    pub fn new(entries: Vec<GlossaryEntry>) -> Self {
+        let mut exact = HashMap::new();
+        let mut folded = HashMap::new();
+        let mut terms = Vec::new();
+        for (index, entry) in entries.iter().enumerate() {
+            for term in std::iter::once(&entry.term).chain(entry.synonyms.iter()) {
+                let term = collapse_whitespace(term.trim(), &WhitespaceOptions::default());
+                if term.is_empty() {
+                    continue;
+                }
+                if is_case_sensitive(&term) {
+                    exact.entry(term.clone()).or_insert(index);
+                } else {
+                    folded.entry(term.to_lowercase()).or_insert(index);
+                }
+                terms.push(term);
+            }
+        }
+
+        // Longer terms first, so that "lithium carbonate" wins over "lithium".
+        terms.sort_by(|a, b| b.chars().count().cmp(&a.chars().count()).then(a.cmp(b)));
+        terms.dedup();
+        let pattern = (!terms.is_empty()).then(|| {
+            let alternatives: Vec<_> = terms.iter().map(|term| term_pattern(term)).collect();
+            Regex::new(&alternatives.join("|"))
+                .expect("escaped glossary terms form a valid pattern")
+        });
+
+        Glossary {
+            entries,
+            pattern,
+            exact,
+            folded,
+        }
+    }
+
+    /// Loads a glossary from a JSON array of entries.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let json = std::fs::read_to_string(path)?;
+        let entries: Vec<GlossaryEntry> = serde_json::from_str(&json)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(Self::new(entries))
+    }
+
+    pub fn entries(&self) -> &[GlossaryEntry] {
+        &self.entries
+    }
+
+    /// Finds the entry of a matched term.
+    fn lookup(&self, matched: &str) -> Option<&GlossaryEntry> {
+        let matched = collapse_whitespace(matched, &WhitespaceOptions::default());
+        self.exact
+            .get(&matched)
+            .or_else(|| self.folded.get(&matched.to_lowercase()))
+            .map(|&index| &self.entries[index])
+    }
+}
+
+/// Whether a term has a capital after its first letter, as acronyms and symbols do.
+fn is_case_sensitive(term: &str) -> bool {
+    term.chars().skip(1).any(char::is_uppercase)
+}
+
+/// The pattern of a single term, with word boundaries on the sides that are word
+/// characters.
+fn term_pattern(term: &str) -> String {
+    let words: Vec<_> = term.split(' ').map(regex::escape).collect();
+    let flags = if is_case_sensitive(term) { "-i" } else { "i" };
+    let is_word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+    let start = if is_word(term.chars().next()) {
+        r"\b"
+    } else {
+        ""
+    };
+    let end = if is_word(term.chars().last()) {
+        r"\b"
+    } else {
+        ""
+    };
+    format!("{}(?{}:{}){}", start, flags, words.join(r"\s+"), end)
+}
+
+/// How linked terms are marked up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GlossaryMarkup {
+    /// `<a data-glossary="id">term</a>`
+    #[default]
+    Link,
+    /// `<abbr data-glossary="id" title="definition">term</abbr>`
+    Abbr,
+}
+
+/// How often a term is linked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LinkScope {
+    /// Only the first occurrence in the document.
+    #[default]
+    Page,
+    /// The first occurrence after every heading.
+    Section,
+}
+
+/// A visitor that links the first occurrence of each [`Glossary`] term to its definition.
+///
+/// Text inside links, headings, code and existing `data-glossary` markup is left alone;
+/// terms already marked up in the document count as linked, so running the visitor twice
+/// changes nothing.
+pub struct GlossaryVisitor<'a> {
+    glossary: &'a Glossary,
+    markup: GlossaryMarkup,
+    scope: LinkScope,
+    linked: HashSet<String>,
+    links: Vec<String>,
+}
+
+impl<'a> GlossaryVisitor<'a> {
+    pub fn new(glossary: &'a Glossary) -> Self {
+        GlossaryVisitor {
+            glossary,
+            markup: GlossaryMarkup::default(),
+            scope: LinkScope::default(),
+            linked: HashSet::new(),
+            links: Vec::new(),
+        }
+    }
+
+    pub fn with_markup(mut self, markup: GlossaryMarkup) -> Self {
+        self.markup = markup;
+        self
+    }
+
+    pub fn with_scope(mut self, scope: LinkScope) -> Self {
+        self.scope = scope;
+        self
+    }
+
+    /// The definition ids linked by the visitor, in document order.
+    pub fn links(&self) -> &[String] {
+        &self.links
+    }
+
+    /// Splits `text` around the terms not linked yet. Returns `None` if there are none.
+    fn link_terms(&mut self, text: &str) -> Option<Vec<Handle>> {
+        let pattern = self.glossary.pattern.as_ref()?;
+        let mut nodes = Vec::new();
+        let mut cursor = 0;
+
+        for found in pattern.find_iter(text) {
+            let Some(entry) = self.glossary.lookup(found.as_str()) else {
+                continue;
+            };
+            if !self.linked.insert(entry.id.clone()) {
+                continue;
+            }
+
+            if found.start() > cursor {
+                nodes.push(builder::text(&text[cursor..found.start()]));
+            }
+            nodes.push(self.render_term(entry, found.as_str()));
+            cursor = found.end();
+
+            log::debug!(
+                "Linked glossary term \"{}\" to {}",
+                found.as_str(),
+                entry.id
+            );
+            self.links.push(entry.id.clone());
+        }
+
+        if nodes.is_empty() {
+            return None;
+        }
+        if cursor < text.len() {
+            nodes.push(builder::text(&text[cursor..]));
+        }
+        Some(nodes)
+    }
+
+    fn render_term(&self, entry: &GlossaryEntry, text: &str) -> Handle {
+        let term = match self.markup {
+            GlossaryMarkup::Link => element("a").attr(GLOSSARY_ATTRIBUTE, &entry.id),
+            GlossaryMarkup::Abbr => element("abbr")
+                .attr(GLOSSARY_ATTRIBUTE, &entry.id)
+                .attr_opt("title", entry.definition.as_ref()),
+        };
+        term.text(text).build()
+    }
+}
+
+impl NodeVisitor for GlossaryVisitor<'_> {
+    fn visit_element(
+        &mut self,
+        name: &QualName,
+        attrs: &RefCell<Vec<Attribute>>,
+        _template_contents: &RefCell<Option<Handle>>,
+        _mathml_annotation_xml_integration_point: bool,
+        _handle: &Handle,
+    ) -> (Option<Handle>, bool) {
+        let name = name.local.as_ref();
+        if self.scope == LinkScope::Section && heading_level(name).is_some() {
+            self.linked.clear();
+        }
+        let existing = attrs
+            .borrow()
+            .iter()
+            .find(|attr| attr.name.local.as_ref() == GLOSSARY_ATTRIBUTE)
+            .map(|attr| attr.value.to_string());
+        if let Some(id) = existing {
+            self.linked.insert(id);
+            return (None, false);
+        }
+        if SKIPPED_ELEMENTS.contains(&name) || heading_level(name).is_some() {
+            return (None, false);
+        }
+        (None, true)
+    }
+
+    fn split_text(
+        &mut self,
+        contents: &RefCell<StrTendril>,
+        _handle: &Handle,
+    ) -> Option<Vec<Handle>> {
+        self.link_terms(&contents.borrow())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rc_dom::RcDom;
+
+    fn glossary() -> Glossary {
+        Glossary::new(vec![
+            GlossaryEntry {
+                term: "lithium".to_string(),
+                synonyms: vec!["Li".to_string()],
+                id: "lithium".to_string(),
+                definition: None,
+            },
+            GlossaryEntry {
+                term: "lithium carbonate".to_string(),
+                synonyms: vec![],
+                id: "lithium-carbonate".to_string(),
+                definition: None,
+            },
+            GlossaryEntry {
+                term: "SSRI".to_string(),
+                synonyms: vec!["selective serotonin reuptake inhibitor".to_string()],
+                id: "ssri".to_string(),
+                definition: Some("Selective serotonin reuptake inhibitor".to_string()),
+            },
+        ])
+    }
+
+    fn link(html: &str, visitor: &mut GlossaryVisitor) -> String {
+        let dom = RcDom::from_fragment_str(html);
+        let (root, _) = visitor.traverse(dom.fragment_root());
+        root.inner_html()
+    }
+
+    #[test]
+    fn test_links_first_occurrence() {
+        let glossary = glossary();
+        let mut visitor = GlossaryVisitor::new(&glossary);
+        let html = link(
+            "<h2>Lithium</h2><p><a href=\"/x\">lithium</a> <code>lithium</code> Lithiums and \
+             Lithium\n carbonate, then lithium and lithium.</p>",
+            &mut visitor,
+        );
+        assert_eq!(
+            html,
+            "<h2>Lithium</h2><p><a href=\"/x\">lithium</a> <code>lithium</code> Lithiums and \
+             <a data-glossary=\"lithium-carbonate\">Lithium\n carbonate</a>, then \
+             <a data-glossary=\"lithium\">lithium</a> and lithium.</p>"
+        );
+        assert_eq!(visitor.links(), ["lithium-carbonate", "lithium"]);
+    }
+
+    #[test]
+    fn test_acronyms_match_case() {
+        let glossary = glossary();
+        let mut visitor = GlossaryVisitor::new(&glossary).with_markup(GlossaryMarkup::Abbr);
+        let html = link("<p>ssri, SSRIs and SSRI.</p>", &mut visitor);
+        assert_eq!(
+            html,
+            "<p>ssri, SSRIs and <abbr data-glossary=\"ssri\" \
+             title=\"Selective serotonin reuptake inhibitor\">SSRI</abbr>.</p>"
+        );
+    }
+
+    #[test]
+    fn test_section_scope_and_existing_links() {
+        let glossary = glossary();
+        let mut visitor = GlossaryVisitor::new(&glossary).with_scope(LinkScope::Section);
+        let html = link(
+            "<p><a data-glossary=\"ssri\">SSRI</a> SSRI</p><h2>Next</h2><p>SSRI SSRI</p>",
+            &mut visitor,
+        );
+        assert_eq!(
+            html,
+            "<p><a data-glossary=\"ssri\">SSRI</a> SSRI</p><h2>Next</h2>\
+             <p><a data-glossary=\"ssri\">SSRI</a> SSRI</p>"
+        );
+        let again = link(&html, &mut GlossaryVisitor::new(&glossary));
+        assert_eq!(again, html);
+    }
+}
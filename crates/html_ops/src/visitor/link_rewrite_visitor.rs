@@ -0,0 +1,527 @@
+use super::NodeVisitor;
+use crate::rc_dom::Handle;
+use html5ever::{Attribute, QualName};
+use serde::Serialize;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Attributes holding a single URL, by element.
+const URL_ATTRIBUTES: &[(&str, &str)] = &[
+    ("a", "href"),
+    ("area", "href"),
+    ("link", "href"),
+    ("img", "src"),
+    ("script", "src"),
+    ("iframe", "src"),
+    ("source", "src"),
+    ("video", "src"),
+    ("video", "poster"),
+    ("audio", "src"),
+    ("track", "src"),
+    ("embed", "src"),
+];
+
+/// Attributes holding a comma separated list of image candidates, by element.
+const SRCSET_ATTRIBUTES: &[(&str, &str)] = &[("img", "srcset"), ("source", "srcset")];
+
+/// The rules applied to internal URLs.
+///
+/// Every rule is optional; with the default rules URLs are only checked, never changed.
+#[derive(Debug, Clone, Default)]
+pub struct LinkRewriteRules {
+    /// Path prefix the site is served under, e.g. `/docs`. Internal URLs become
+    /// root-absolute under this prefix.
+    pub base_path: Option<String>,
+    /// Origin assets are served from, e.g. `https://cdn.example.com`. Applied to every
+    /// internal URL that does not point at an HTML page.
+    pub cdn_origin: Option<String>,
+    /// Rewrite `page.html` to `page` and `dir/index.html` to `dir/`.
+    pub pretty_urls: bool,
+    /// Maps site-relative asset paths to their fingerprinted names, e.g.
+    /// `img/logo.png` to `img/logo.3f2a1b.png`.
+    pub asset_manifest: HashMap<String, String>,
+}
+
+impl LinkRewriteRules {
+    /// Loads an asset manifest from a JSON object of original to fingerprinted paths.
+    pub fn load_asset_manifest<P: AsRef<Path>>(path: P) -> io::Result<HashMap<String, String>> {
+        let json = std::fs::read_to_string(path)?;
+        serde_json::from_str(&json).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// An internal link whose target does not exist in the site directory.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BrokenLink {
+    /// The file containing the link, relative to the site root.
+    pub source: PathBuf,
+    /// The URL as written in the document.
+    pub url: String,
+    /// The target the URL resolves to, relative to the site root.
+    pub target: String,
+}
+
+/// A visitor that rewrites `href`, `src` and `srcset` values according to a set of
+/// [`LinkRewriteRules`], and records internal links whose targets are missing.
+///
+/// One visitor is created per file, as relative URLs are resolved against the location of
+/// that file within the walked directory.
+pub struct LinkRewriteVisitor<'a> {
+    rules: &'a LinkRewriteRules,
+    site_root: PathBuf,
+    relative_path: PathBuf,
+    broken_links: Vec<BrokenLink>,
+}
+
+impl<'a> LinkRewriteVisitor<'a> {
+    /// Creates a visitor for the file at `relative_path` inside `site_root`.
+    pub fn new<P: AsRef<Path>, Q: AsRef<Path>>(
+        rules: &'a LinkRewriteRules,
+        site_root: P,
+        relative_path: Q,
+    ) -> Self {
+        LinkRewriteVisitor {
+            rules,
+            site_root: site_root.as_ref().to_path_buf(),
+            relative_path: relative_path.as_ref().to_path_buf(),
+            broken_links: Vec::new(),
+        }
+    }
+
+    /// Internal links found in the file whose targets do not exist.
+    pub fn broken_links(&self) -> &[BrokenLink] {
+        &self.broken_links
+    }
+
+    /// The directory of the current file, as a `/` separated site-relative path.
+    fn current_dir(&self) -> String {
+        site_dir(&self.relative_path)
+    }
+
+    /// Rewrites a single URL, returning `None` if it is left untouched.
+    fn rewrite_url(&mut self, url: &str) -> Option<String> {
+        if is_external(url) {
+            return None;
+        }
+        let (path, suffix) = split_suffix(url);
+        if path.is_empty() {
+            return None;
+        }
+
+        let current_dir = self.current_dir();
+        let target = match resolve(&current_dir, path) {
+            Some(target) => target,
+            None => {
+                self.report_broken(url, path);
+                return None;
+            }
+        };
+        if !self.target_exists(&target) {
+            self.report_broken(url, &target);
+        }
+
+        let is_page = is_page(&target) && !self.rules.asset_manifest.contains_key(&target);
+        let mut rewritten = target.clone();
+        if is_page {
+            if self.rules.pretty_urls {
+                rewritten = prettify(&rewritten);
+            }
+        } else if let Some(fingerprinted) = self.rules.asset_manifest.get(&target) {
+            rewritten = fingerprinted.trim_start_matches('/').to_string();
+        }
+
+        let new_url = match (&self.rules.cdn_origin, &self.rules.base_path) {
+            (Some(origin), _) if !is_page => {
+                format!("{}/{}{}", origin.trim_end_matches('/'), rewritten, suffix)
+            }
+            (_, Some(base_path)) => {
+                format!(
+                    "{}/{}{}",
+                    base_path.trim_end_matches('/'),
+                    rewritten,
+                    suffix
+                )
+            }
+            _ if rewritten == target => return None,
+            _ if path.starts_with('/') => format!("/{}{}", rewritten, suffix),
+            _ => format!("{}{}", relativize(&current_dir, &rewritten), suffix),
+        };
+
+        (new_url != url).then_some(new_url)
+    }
+
+    /// Rewrites each candidate URL of a `srcset` value, keeping its descriptor.
+    fn rewrite_srcset(&mut self, srcset: &str) -> Option<String> {
+        let mut changed = false;
+        let candidates = parse_srcset(srcset)
+            .into_iter()
+            .map(|(url, descriptor)| {
+                let url = match self.rewrite_url(url) {
+                    Some(new_url) => {
+                        changed = true;
+                        new_url
+                    }
+                    None => url.to_string(),
+                };
+                format!("{} {}", url, descriptor).trim_end().to_string()
+            })
+            .collect::<Vec<_>>();
+
+        changed.then(|| candidates.join(", "))
+    }
+
+    fn report_broken(&mut self, url: &str, target: &str) {
+        log::warn!(
+            "Broken link in {:?}: {} (resolved to {})",
+            self.relative_path,
+            url,
+            target
+        );
+        self.broken_links.push(BrokenLink {
+            source: self.relative_path.clone(),
+            url: url.to_string(),
+            target: target.to_string(),
+        });
+    }
+
+    /// Whether the site-relative target exists, either as a file, as a directory with an
+    /// `index.html`, or as an already prettified page.
+    fn target_exists(&self, target: &str) -> bool {
+        let path = self.site_root.join(percent_decode(target));
+        if target.is_empty() || target.ends_with('/') || path.is_dir() {
+            return path.join("index.html").is_file();
+        }
+        path.is_file() || path.with_extension("html").is_file()
+    }
+}
+
+impl<'a> NodeVisitor for LinkRewriteVisitor<'a> {
+    fn visit_element(
+        &mut self,
+        name: &QualName,
+        attrs: &RefCell<Vec<Attribute>>,
+        _template_contents: &RefCell<Option<Handle>>,
+        _mathml_annotation_xml_integration_point: bool,
+        _handle: &Handle,
+    ) -> (Option<Handle>, bool) {
+        let element = name.local.as_ref();
+        for attr in attrs.borrow_mut().iter_mut() {
+            let key = (element, attr.name.local.as_ref());
+            let rewritten = if URL_ATTRIBUTES.contains(&key) {
+                self.rewrite_url(attr.value.trim())
+            } else if SRCSET_ATTRIBUTES.contains(&key) {
+                self.rewrite_srcset(&attr.value)
+            } else {
+                None
+            };
+            if let Some(value) = rewritten {
+                log::debug!("Rewrote {} to {}", attr.value, value);
+                attr.value = value.into();
+            }
+        }
+        (None, true)
+    }
+
+    // Links inside SVG, `href` or `xlink:href` on `a`, move with the site too.
+    fn visits_foreign_content(&self) -> bool {
+        true
+    }
+}
+
+/// The directory of a site-relative file, as a `/` separated path.
+pub(crate) fn site_dir(relative_path: &Path) -> String {
+    relative_path
+        .parent()
+        .map(|dir| {
+            dir.components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/")
+        })
+        .unwrap_or_default()
+}
+
+/// Whether the URL points outside the site: it has a scheme, is protocol-relative, or
+/// only refers to a fragment of the current page.
+pub(crate) fn is_external(url: &str) -> bool {
+    if url.is_empty() || url.starts_with('#') || url.starts_with("//") {
+        return true;
+    }
+    match url.find(':') {
+        Some(colon) => !url[..colon].contains(['/', '?', '#']),
+        None => false,
+    }
+}
+
+/// Splits a URL into its path and its `?query#fragment` suffix.
+pub(crate) fn split_suffix(url: &str) -> (&str, &str) {
+    let at = url.find(['?', '#']).unwrap_or(url.len());
+    url.split_at(at)
+}
+
+/// Resolves a URL path against the site-relative directory of the current file. Returns
+/// `None` if the path escapes the site root.
+pub(crate) fn resolve(current_dir: &str, path: &str) -> Option<String> {
+    let mut segments: Vec<&str> = if path.starts_with('/') {
+        Vec::new()
+    } else {
+        current_dir.split('/').filter(|s| !s.is_empty()).collect()
+    };
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+    let mut resolved = segments.join("/");
+    if path.ends_with('/') && !resolved.is_empty() {
+        resolved.push('/');
+    }
+    Some(resolved)
+}
+
+/// Expresses a site-relative target relative to the directory of the current file.
+fn relativize(current_dir: &str, target: &str) -> String {
+    let from: Vec<&str> = current_dir.split('/').filter(|s| !s.is_empty()).collect();
+    let to: Vec<&str> = target.split('/').collect();
+    let common = from
+        .iter()
+        .zip(to.iter())
+        .take_while(|(a, b)| a == b)
+        .count()
+        .min(to.len() - 1);
+
+    let mut relative = vec![".."; from.len() - common];
+    relative.extend(&to[common..]);
+    match relative.join("/") {
+        empty if empty.is_empty() => "./".to_string(),
+        relative => relative,
+    }
+}
+
+/// Whether the target is an HTML page: a directory, a `.html` file, or a path without an
+/// extension, as prettified pages are.
+fn is_page(target: &str) -> bool {
+    let name = target.rsplit('/').next().unwrap_or_default();
+    name.is_empty() || name.ends_with(".html") || !name.contains('.')
+}
+
+/// Turns `page.html` into `page` and `dir/index.html` into `dir/`.
+fn prettify(target: &str) -> String {
+    if target == "index.html" {
+        String::new()
+    } else if let Some(dir) = target.strip_suffix("/index.html") {
+        format!("{}/", dir)
+    } else {
+        target.trim_end_matches(".html").to_string()
+    }
+}
+
+/// Splits a `srcset` value into its candidates' URLs and descriptors, as the HTML
+/// standard does. A URL runs to the next whitespace, so it may contain commas, as
+/// `data:` URLs do; a comma ends a candidate only after its URL, outside parentheses.
+pub(crate) fn parse_srcset(srcset: &str) -> Vec<(&str, &str)> {
+    let is_space = |c: char| c.is_ascii_whitespace();
+    let mut candidates = Vec::new();
+    let mut rest = srcset;
+    loop {
+        rest = rest.trim_start_matches(|c: char| is_space(c) || c == ',');
+        if rest.is_empty() {
+            return candidates;
+        }
+        let end = rest.find(is_space).unwrap_or(rest.len());
+        let (url, after) = rest.split_at(end);
+        rest = after;
+        // Commas ending the URL separate it from the next candidate
+        let trimmed = url.trim_end_matches(',');
+        if trimmed.len() < url.len() {
+            candidates.push((trimmed, ""));
+            continue;
+        }
+
+        let mut depth = 0usize;
+        let end = rest
+            .char_indices()
+            .find(|&(_, c)| {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth = depth.saturating_sub(1),
+                    _ => {}
+                }
+                c == ',' && depth == 0
+            })
+            .map_or(rest.len(), |(i, _)| i);
+        let (descriptor, after) = rest.split_at(end);
+        rest = after;
+        candidates.push((url, descriptor.trim()));
+    }
+}
+
+/// Decodes `%XX` escapes so URLs can be looked up on disk.
+pub(crate) fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| value.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rc_dom::RcDom;
+    use std::fs;
+
+    fn site() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("guide/img")).unwrap();
+        fs::write(dir.path().join("index.html"), "").unwrap();
+        fs::write(dir.path().join("guide/index.html"), "").unwrap();
+        fs::write(dir.path().join("guide/setup.html"), "").unwrap();
+        fs::write(dir.path().join("guide/img/logo.png"), "").unwrap();
+        dir
+    }
+
+    fn rewrite(rules: &LinkRewriteRules, root: &Path, html: &str) -> (String, Vec<BrokenLink>) {
+        let dom = RcDom::from_str(html);
+        let mut visitor = LinkRewriteVisitor::new(rules, root, "guide/index.html");
+        let (document, _) = visitor.traverse(dom.document);
+        (document.to_html_string(), visitor.broken_links().to_vec())
+    }
+
+    #[test]
+    fn test_pretty_urls_stay_relative() {
+        let root = site();
+        let rules = LinkRewriteRules {
+            pretty_urls: true,
+            ..Default::default()
+        };
+        let (html, broken) = rewrite(
+            &rules,
+            root.path(),
+            r##"<a href="setup.html#step-2">a</a><a href="../index.html">b</a><a href="https://x.org/a.html">c</a>"##,
+        );
+        assert!(html.contains(r##"href="setup#step-2""##));
+        assert!(html.contains(r#"href="../""#));
+        assert!(html.contains(r#"href="https://x.org/a.html""#));
+        assert!(broken.is_empty());
+    }
+
+    #[test]
+    fn test_base_path_cdn_and_manifest() {
+        let root = site();
+        let rules = LinkRewriteRules {
+            base_path: Some("/docs/".to_string()),
+            cdn_origin: Some("https://cdn.example.com".to_string()),
+            asset_manifest: HashMap::from([(
+                "guide/img/logo.png".to_string(),
+                "guide/img/logo.3f2a1b.png".to_string(),
+            )]),
+            ..Default::default()
+        };
+        let (html, _) = rewrite(
+            &rules,
+            root.path(),
+            r#"<a href="setup.html">a</a><img src="img/logo.png" srcset="img/logo.png 1x, /img/big.png 2x">"#,
+        );
+        assert!(html.contains(r#"href="/docs/guide/setup.html""#));
+        assert!(html.contains(r#"src="https://cdn.example.com/guide/img/logo.3f2a1b.png""#));
+        assert!(html.contains(
+            r#"srcset="https://cdn.example.com/guide/img/logo.3f2a1b.png 1x, https://cdn.example.com/img/big.png 2x""#
+        ));
+    }
+
+    #[test]
+    fn test_extensionless_links_are_pages() {
+        let root = site();
+        let rules = LinkRewriteRules {
+            cdn_origin: Some("https://cdn.example.com".to_string()),
+            ..Default::default()
+        };
+        let (html, _) = rewrite(
+            &rules,
+            root.path(),
+            r#"<a href="setup">a</a><a href="/guide/">b</a><a href="../">c</a><img src="img/logo.png">"#,
+        );
+        assert!(html.contains(r#"href="setup""#));
+        assert!(html.contains(r#"href="/guide/""#));
+        assert!(html.contains(r#"href="../""#));
+        assert!(html.contains(r#"src="https://cdn.example.com/guide/img/logo.png""#));
+        assert!(is_page("guide/setup"));
+        assert!(is_page("v1.2/"));
+        assert!(!is_page("v1.2/logo.png"));
+    }
+
+    #[test]
+    fn test_parses_srcset_candidates() {
+        assert_eq!(
+            parse_srcset(
+                " a.png 1x,b.png,, c.png, data:image/png;base64,iVBO= 2x, d.png,e.png 300w"
+            ),
+            vec![
+                ("a.png", "1x"),
+                ("b.png", ""),
+                ("c.png", ""),
+                ("data:image/png;base64,iVBO=", "2x"),
+                // Without whitespace, a comma belongs to the URL
+                ("d.png,e.png", "300w"),
+            ]
+        );
+        assert_eq!(
+            parse_srcset("a.png foo(1, 2) 1x, b.png"),
+            vec![("a.png", "foo(1, 2) 1x"), ("b.png", "")]
+        );
+    }
+
+    #[test]
+    fn test_srcset_keeps_data_urls() {
+        let root = site();
+        let rules = LinkRewriteRules {
+            base_path: Some("/docs/".to_string()),
+            ..Default::default()
+        };
+        let (html, _) = rewrite(
+            &rules,
+            root.path(),
+            r#"<img srcset="data:image/gif;base64,R0lGOD,lh 1x, img/logo.png 2x">"#,
+        );
+        assert!(html.contains(
+            r#"srcset="data:image/gif;base64,R0lGOD,lh 1x, /docs/guide/img/logo.png 2x""#
+        ));
+    }
+
+    #[test]
+    fn test_reports_broken_links() {
+        let root = site();
+        let rules = LinkRewriteRules::default();
+        let (_, broken) = rewrite(
+            &rules,
+            root.path(),
+            r#"<a href="setup">ok</a><a href="missing.html">no</a><a href="../../outside.html">no</a>"#,
+        );
+        let targets: Vec<_> = broken.iter().map(|link| link.target.as_str()).collect();
+        assert_eq!(targets, vec!["guide/missing.html", "../../outside.html"]);
+    }
+}
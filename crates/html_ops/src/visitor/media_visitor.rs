@@ -0,0 +1,470 @@
+use super::{
+    link_rewrite_visitor::{
+        is_external, parse_srcset, percent_decode, resolve, site_dir, split_suffix,
+    },
+    NodeVisitor,
+};
+use crate::{
+    rc_dom::{Handle, NodeData, RcDom},
+    util::{attribute, element_name, fnv1a, parent},
+    walk::process_html_files,
+    whitespace::{normalize_text, WhitespaceOptions},
+};
+use html5ever::{Attribute, QualName};
+use serde::Serialize;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaKind {
+    Image,
+    Video,
+    Audio,
+    Iframe,
+}
+
+/// A media reference of a page.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MediaEntry {
+    /// The page containing the reference, relative to the site root.
+    pub file: PathBuf,
+    pub kind: MediaKind,
+    /// The element and attribute holding the URL, as `img[srcset]`.
+    pub source: String,
+    /// The URL as written in the document. Data URLs are cut at their media type.
+    pub url: String,
+    /// The site-relative path of a local URL, or `None` for external URLs and paths
+    /// escaping the site root.
+    pub resolved: Option<String>,
+    /// The `alt` text of images, or the `title` or `aria-label` of other media.
+    pub alt: Option<String>,
+    /// The `figcaption` of the enclosing `figure`.
+    pub caption: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Whether the local file exists, or `None` for external URLs.
+    pub exists: Option<bool>,
+    /// The size of the local file in bytes.
+    pub size: Option<u64>,
+}
+
+/// A problem found in the media of a site.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "issue", rename_all = "kebab-case")]
+pub enum MediaIssue {
+    /// A local URL whose file does not exist.
+    Missing {
+        file: PathBuf,
+        url: String,
+        resolved: Option<String>,
+    },
+    /// An image file larger than the configured maximum.
+    Oversized {
+        resolved: String,
+        size: u64,
+        max_size: u64,
+    },
+    /// Different files with the same content.
+    Duplicate { size: u64, paths: Vec<String> },
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MediaOptions {
+    /// Images above this size in bytes are reported.
+    pub max_image_size: u64,
+}
+
+impl Default for MediaOptions {
+    fn default() -> Self {
+        MediaOptions {
+            max_image_size: 500 * 1024,
+        }
+    }
+}
+
+/// A visitor that records the `img`, `picture`/`source`, `video`, `audio` and `iframe`
+/// references of a page, resolving local URLs against the site directory.
+///
+/// One visitor is created per file, as relative URLs are resolved against the location of
+/// that file within the walked directory.
+pub struct MediaVisitor {
+    site_root: PathBuf,
+    relative_path: PathBuf,
+    entries: Vec<MediaEntry>,
+}
+
+impl MediaVisitor {
+    /// Creates a visitor for the file at `relative_path` inside `site_root`.
+    pub fn new<P: AsRef<Path>, Q: AsRef<Path>>(site_root: P, relative_path: Q) -> Self {
+        MediaVisitor {
+            site_root: site_root.as_ref().to_path_buf(),
+            relative_path: relative_path.as_ref().to_path_buf(),
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn entries(&self) -> &[MediaEntry] {
+        &self.entries
+    }
+
+    pub fn into_entries(self) -> Vec<MediaEntry> {
+        self.entries
+    }
+
+    /// Records the URLs of `element`, with the description of `described`, which differs
+    /// for the `source` elements of a `picture`.
+    fn record(&mut self, kind: MediaKind, element: &Handle, described: &Handle) {
+        let NodeData::Element {
+            ref name,
+            ref attrs,
+            ..
+        } = element.data
+        else {
+            return;
+        };
+        let name = name.local.as_ref();
+        let attrs = attrs.borrow();
+
+        let mut urls = Vec::new();
+        for attr in attrs.iter() {
+            let attribute = attr.name.local.as_ref();
+            match attribute {
+                "src" => urls.push((kind, attribute, attr.value.trim().to_string())),
+                "poster" => urls.push((MediaKind::Image, attribute, attr.value.trim().to_string())),
+                "srcset" => urls.extend(
+                    parse_srcset(&attr.value)
+                        .into_iter()
+                        .map(|(url, _)| (kind, attribute, url.to_string())),
+                ),
+                _ => {}
+            }
+        }
+
+        let alt = match kind {
+            MediaKind::Image => attribute(described, "alt"),
+            _ => attribute(described, "title").or_else(|| attribute(described, "aria-label")),
+        };
+        let caption = figure_caption(described);
+        let dimension = |name: &str| {
+            attribute(described, name)
+                .and_then(|value| value.trim_end_matches("px").trim().parse().ok())
+        };
+        let (width, height) = (dimension("width"), dimension("height"));
+
+        for (kind, attribute, url) in urls {
+            if url.is_empty() {
+                continue;
+            }
+            let mut entry = MediaEntry {
+                file: self.relative_path.clone(),
+                kind,
+                source: format!("{}[{}]", name, attribute),
+                url: url.clone(),
+                resolved: None,
+                alt: alt.clone(),
+                caption: caption.clone(),
+                width,
+                height,
+                exists: None,
+                size: None,
+            };
+            if is_external(&url) {
+                if url.starts_with("data:") {
+                    entry.url = url.split([',', ';']).next().unwrap_or_default().to_string();
+                }
+            } else {
+                let (path, _) = split_suffix(&url);
+                entry.resolved = resolve(&site_dir(&self.relative_path), &percent_decode(path));
+                let metadata = entry
+                    .resolved
+                    .as_ref()
+                    .and_then(|resolved| fs::metadata(self.site_root.join(resolved)).ok())
+                    .filter(|metadata| metadata.is_file());
+                entry.exists = Some(metadata.is_some());
+                entry.size = metadata.map(|metadata| metadata.len());
+                if entry.size.is_none() {
+                    log::warn!("Missing media in {:?}: {}", self.relative_path, url);
+                }
+            }
+            self.entries.push(entry);
+        }
+    }
+}
+
+impl NodeVisitor for MediaVisitor {
+    fn visit_element(
+        &mut self,
+        name: &QualName,
+        _attrs: &RefCell<Vec<Attribute>>,
+        _template_contents: &RefCell<Option<Handle>>,
+        _mathml_annotation_xml_integration_point: bool,
+        handle: &Handle,
+    ) -> (Option<Handle>, bool) {
+        match name.local.as_ref() {
+            "img" => self.record(MediaKind::Image, handle, handle),
+            "iframe" => self.record(MediaKind::Iframe, handle, handle),
+            "picture" => {
+                // Sources are described by the fallback image of the picture.
+                let children = handle.children.borrow();
+                let image = children
+                    .iter()
+                    .find(|child| element_name(child) == Some("img"));
+                for child in children.iter() {
+                    if element_name(child) == Some("source") {
+                        self.record(MediaKind::Image, child, image.unwrap_or(handle));
+                    }
+                }
+                if let Some(image) = image {
+                    self.record(MediaKind::Image, image, image);
+                }
+                return (None, false);
+            }
+            media @ ("video" | "audio") => {
+                let kind = if media == "video" {
+                    MediaKind::Video
+                } else {
+                    MediaKind::Audio
+                };
+                self.record(kind, handle, handle);
+                for child in handle.children.borrow().iter() {
+                    if element_name(child) == Some("source") {
+                        self.record(kind, child, handle);
+                    }
+                }
+                // The fallback content is only shown by browsers without media support.
+                return (None, false);
+            }
+            _ => {}
+        }
+        (None, true)
+    }
+}
+
+/// The media of every HTML file of a site, with the problems found in them.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MediaManifest {
+    pub entries: Vec<MediaEntry>,
+    pub issues: Vec<MediaIssue>,
+}
+
+impl MediaManifest {
+    /// Records the media of every HTML file under `directory`, then reports missing files,
+    /// oversized images, and local files with identical content.
+    pub fn from_dir<P: AsRef<Path>>(directory: P, options: &MediaOptions) -> io::Result<Self> {
+        let directory = directory.as_ref();
+        let mut manifest = MediaManifest::default();
+        process_html_files(directory, |path, relative_path| {
+            let dom = RcDom::from_file(path)?;
+            let mut visitor = MediaVisitor::new(directory, relative_path);
+            visitor.traverse(dom.document);
+            manifest.entries.extend(visitor.into_entries());
+            Ok(())
+        })?;
+        manifest.issues = manifest.find_issues(directory, options)?;
+        Ok(manifest)
+    }
+
+    fn find_issues(&self, directory: &Path, options: &MediaOptions) -> io::Result<Vec<MediaIssue>> {
+        let mut issues: Vec<MediaIssue> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.exists == Some(false))
+            .map(|entry| MediaIssue::Missing {
+                file: entry.file.clone(),
+                url: entry.url.clone(),
+                resolved: entry.resolved.clone(),
+            })
+            .collect();
+
+        let mut files = BTreeMap::new();
+        for entry in self.entries.iter() {
+            if let (Some(resolved), Some(size)) = (entry.resolved.as_ref(), entry.size) {
+                let file = files.entry(resolved.clone()).or_insert((size, false));
+                file.1 |= entry.kind == MediaKind::Image;
+            }
+        }
+        for (resolved, &(size, is_image)) in files.iter() {
+            if is_image && size > options.max_image_size {
+                log::warn!("Oversized image {}: {} bytes", resolved, size);
+                issues.push(MediaIssue::Oversized {
+                    resolved: resolved.clone(),
+                    size,
+                    max_size: options.max_image_size,
+                });
+            }
+        }
+
+        // Only files of the same size are read and compared.
+        let mut by_size: BTreeMap<u64, Vec<&String>> = BTreeMap::new();
+        for (resolved, &(size, _)) in files.iter() {
+            by_size.entry(size).or_default().push(resolved);
+        }
+        for (size, paths) in by_size.into_iter().filter(|(_, paths)| paths.len() > 1) {
+            let mut contents = Vec::new();
+            for path in paths {
+                contents.push((path.clone(), fs::read(directory.join(path))?));
+            }
+            for paths in identical_files(contents, fnv1a) {
+                issues.push(MediaIssue::Duplicate {
+                    size,
+                    paths: paths.into_iter().collect(),
+                });
+            }
+        }
+        Ok(issues)
+    }
+}
+
+/// Groups the files with the same contents, keeping the groups of more than one. Files
+/// with the same `hash` are compared byte for byte, so a collision is not a duplicate.
+fn identical_files(files: Vec<(String, Vec<u8>)>, hash: fn(&[u8]) -> u64) -> Vec<BTreeSet<String>> {
+    let mut groups: Vec<(u64, Vec<u8>, BTreeSet<String>)> = Vec::new();
+    for (path, contents) in files {
+        let key = hash(&contents);
+        match groups
+            .iter_mut()
+            .find(|(other_key, other, _)| *other_key == key && *other == contents)
+        {
+            Some((_, _, paths)) => {
+                paths.insert(path);
+            }
+            None => groups.push((key, contents, BTreeSet::from([path]))),
+        }
+    }
+    groups
+        .into_iter()
+        .map(|(_, _, paths)| paths)
+        .filter(|paths| paths.len() > 1)
+        .collect()
+}
+
+/// The caption of the nearest enclosing `figure`.
+fn figure_caption(node: &Handle) -> Option<String> {
+    let mut current = node.clone();
+    loop {
+        current = parent(&current)?;
+        if element_name(&current) == Some("figure") {
+            let caption = current
+                .children
+                .borrow()
+                .iter()
+                .find(|child| element_name(child) == Some("figcaption"))
+                .map(|caption| {
+                    normalize_text(&caption.text_content(), &WhitespaceOptions::default())
+                });
+            return caption.filter(|caption| !caption.is_empty());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn site() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("guide/img")).unwrap();
+        fs::write(dir.path().join("guide/img/logo.png"), "logo").unwrap();
+        fs::write(dir.path().join("guide/img/logo-copy.png"), "logo").unwrap();
+        fs::write(dir.path().join("guide/img/big.jpg"), vec![0; 2048]).unwrap();
+        fs::write(dir.path().join("intro.mp4"), "video").unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_records_media() {
+        let root = site();
+        let dom = RcDom::from_str(
+            r#"<figure><picture><source srcset="img/big.jpg 2x, img/logo.png 1x"><img src="img/logo.png" alt="Logo" width="120px" height="40"></picture><figcaption> Our  logo </figcaption></figure>
+            <video src="../intro.mp4" poster="data:image/png;base64,AAAA" title="Intro"><source src="../intro.webm"><img src="ignored.png"></video>
+            <iframe src="https://www.youtube.com/embed/x" aria-label="Talk"></iframe>"#,
+        );
+        let mut visitor = MediaVisitor::new(root.path(), "guide/index.html");
+        visitor.traverse(dom.document);
+
+        let sources: Vec<_> = visitor
+            .entries()
+            .iter()
+            .map(|e| (e.source.as_str(), e.url.as_str(), e.exists))
+            .collect();
+        assert_eq!(
+            sources,
+            [
+                ("source[srcset]", "img/big.jpg", Some(true)),
+                ("source[srcset]", "img/logo.png", Some(true)),
+                ("img[src]", "img/logo.png", Some(true)),
+                ("video[src]", "../intro.mp4", Some(true)),
+                ("video[poster]", "data:image/png", None),
+                ("source[src]", "../intro.webm", Some(false)),
+                ("iframe[src]", "https://www.youtube.com/embed/x", None),
+            ]
+        );
+        let logo = &visitor.entries()[2];
+        assert_eq!(logo.resolved.as_deref(), Some("guide/img/logo.png"));
+        assert_eq!(logo.alt.as_deref(), Some("Logo"));
+        assert_eq!(logo.caption.as_deref(), Some("Our logo"));
+        assert_eq!(
+            (logo.width, logo.height, logo.size),
+            (Some(120), Some(40), Some(4))
+        );
+        assert_eq!(visitor.entries()[0].alt.as_deref(), Some("Logo"));
+        assert_eq!(visitor.entries()[3].alt.as_deref(), Some("Intro"));
+        assert_eq!(visitor.entries()[6].alt.as_deref(), Some("Talk"));
+    }
+
+    #[test]
+    fn test_reports_issues() {
+        let root = site();
+        fs::write(
+            root.path().join("guide/index.html"),
+            r#"<img src="img/logo.png"><img src="/guide/img/logo-copy.png"><img src="img/big.jpg"><img src="img/gone.png">"#,
+        )
+        .unwrap();
+        let options = MediaOptions {
+            max_image_size: 1024,
+        };
+        let manifest = MediaManifest::from_dir(root.path(), &options).unwrap();
+        assert_eq!(manifest.entries.len(), 4);
+        assert_eq!(
+            manifest.issues,
+            [
+                MediaIssue::Missing {
+                    file: PathBuf::from("guide/index.html"),
+                    url: "img/gone.png".to_string(),
+                    resolved: Some("guide/img/gone.png".to_string()),
+                },
+                MediaIssue::Oversized {
+                    resolved: "guide/img/big.jpg".to_string(),
+                    size: 2048,
+                    max_size: 1024,
+                },
+                MediaIssue::Duplicate {
+                    size: 4,
+                    paths: vec![
+                        "guide/img/logo-copy.png".to_string(),
+                        "guide/img/logo.png".to_string()
+                    ],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_identical_files_compares_contents_on_collision() {
+        let files = vec![
+            ("a.png".to_string(), b"logo".to_vec()),
+            ("b.png".to_string(), b"icon".to_vec()),
+            ("c.png".to_string(), b"logo".to_vec()),
+        ];
+        let groups = identical_files(files, |_| 0);
+        assert_eq!(
+            groups,
+            [BTreeSet::from(["a.png".to_string(), "c.png".to_string()])]
+        );
+    }
+}
@@ -1,9 +1,22 @@
+pub mod accessibility_visitor;
+pub mod citation_anchor_visitor;
 pub mod document_id_visitor;
+pub mod duplicate_visitor;
+pub mod footnote_visitor;
+pub mod glossary_visitor;
+pub mod link_rewrite_visitor;
+pub mod media_visitor;
+pub mod outline_visitor;
 pub mod rich_text_wrapper_visitor;
+pub mod sanitizer_visitor;
 pub mod text_collector_visitor;
+pub mod translation_visitor;
 pub mod orphan_visitor;
-use crate::rc_dom::{Handle, NodeData};
-use html5ever::{Attribute, QualName};
+pub mod readability_visitor;
+pub mod style_class_visitor;
+pub mod word_cleanup_visitor;
+use crate::rc_dom::{Handle, NodeData, WeakHandle};
+use html5ever::{local_name, namespace_url, ns, Attribute, QualName};
 use std::cell::RefCell;
 use tendril::StrTendril;
 
@@ -63,6 +76,22 @@ pub trait NodeVisitor {
         (None, true)
     }
 
+    /// Split a text node into several nodes, such as text around a new element.
+    ///
+    /// Called for the text children of every element whose children are visited, before
+    /// [`NodeVisitor::visit_text`].
+    ///
+    /// # Returns
+    /// `Some` with the nodes that replace the text node, which are not visited themselves,
+    /// or `None` to visit the text node as usual.
+    fn split_text(
+        &mut self,
+        _contents: &RefCell<StrTendril>,
+        _handle: &Handle,
+    ) -> Option<Vec<Handle>> {
+        None
+    }
+
     /// Visit an element node.
     ///
     /// # Returns
@@ -110,21 +139,98 @@ pub trait NodeVisitor {
     /// - The `bool` is always true for the root call (unused, but kept for consistency
     ///   with other methods).
     fn traverse(&mut self, handle: Handle) -> (Handle, bool) {
-        let (new_node, continue_children) = match handle.data {
-            NodeData::Document => self.visit_document(&handle),
+        let foreign = in_foreign_content(&handle);
+        (traverse_node(self, handle, foreign), true)
+    }
+
+    /// Whether to visit SVG and MathML content.
+    ///
+    /// By default, elements in the SVG and MathML namespaces and everything inside them are
+    /// left alone, since HTML such as `<rich-text>` is invalid there. Their children are
+    /// still traversed, to reach the HTML content of integration points: an SVG
+    /// `foreignObject`, or a MathML `annotation-xml` the parser flagged as
+    /// `mathml_annotation_xml_integration_point`.
+    fn visits_foreign_content(&self) -> bool {
+        false
+    }
+}
+
+/// Whether an element is in the SVG or MathML namespace.
+pub fn is_foreign(name: &QualName) -> bool {
+    name.ns == ns!(svg) || name.ns == ns!(mathml)
+}
+
+/// Whether the children of a foreign element are HTML content.
+pub fn is_html_integration_point(
+    name: &QualName,
+    mathml_annotation_xml_integration_point: bool,
+) -> bool {
+    (name.ns == ns!(svg) && name.local == local_name!("foreignObject"))
+        || (name.ns == ns!(mathml) && mathml_annotation_xml_integration_point)
+}
+
+/// Whether the children of `node` are foreign content, given whether `node` itself is.
+fn children_in_foreign_content(node: &Handle, foreign: bool) -> bool {
+    match node.data {
+        NodeData::Element {
+            ref name,
+            mathml_annotation_xml_integration_point,
+            ..
+        } if is_foreign(name) => {
+            !is_html_integration_point(name, mathml_annotation_xml_integration_point)
+        }
+        _ => foreign,
+    }
+}
+
+/// Whether `node` is inside foreign content, going by its nearest SVG or MathML ancestor.
+fn in_foreign_content(node: &Handle) -> bool {
+    let parent = |node: &Handle| {
+        let weak = node.parent.take();
+        node.parent.set(weak.clone());
+        weak.and_then(|weak| weak.upgrade())
+    };
+    let mut ancestor = parent(node);
+    while let Some(node) = ancestor {
+        if let NodeData::Element { ref name, .. } = node.data {
+            if is_foreign(name) {
+                return children_in_foreign_content(&node, true);
+            }
+        }
+        ancestor = parent(&node);
+    }
+    false
+}
+
+/// The traversal behind [`NodeVisitor::traverse`]. `foreign` is whether `handle` is inside
+/// foreign content.
+fn traverse_node<V: NodeVisitor + ?Sized>(
+    visitor: &mut V,
+    handle: Handle,
+    foreign: bool,
+) -> Handle {
+    let foreign =
+        foreign || matches!(handle.data, NodeData::Element { ref name, .. } if is_foreign(name));
+    let skip = foreign && !visitor.visits_foreign_content();
+
+    let (new_node, continue_children) = if skip {
+        (None, true)
+    } else {
+        match handle.data {
+            NodeData::Document => visitor.visit_document(&handle),
             NodeData::Doctype {
                 ref name,
                 ref public_id,
                 ref system_id,
-            } => self.visit_doctype(name, public_id, system_id, &handle),
-            NodeData::Text { ref contents } => self.visit_text(contents, &handle),
-            NodeData::Comment { ref contents } => self.visit_comment(contents, &handle),
+            } => visitor.visit_doctype(name, public_id, system_id, &handle),
+            NodeData::Text { ref contents } => visitor.visit_text(contents, &handle),
+            NodeData::Comment { ref contents } => visitor.visit_comment(contents, &handle),
             NodeData::Element {
                 ref name,
                 ref attrs,
                 ref template_contents,
                 mathml_annotation_xml_integration_point,
-            } => self.visit_element(
+            } => visitor.visit_element(
                 name,
                 attrs,
                 template_contents,
@@ -134,20 +240,67 @@ pub trait NodeVisitor {
             NodeData::ProcessingInstruction {
                 ref target,
                 ref contents,
-            } => self.visit_processing_instruction(target, contents, &handle),
-        };
+            } => visitor.visit_processing_instruction(target, contents, &handle),
+        }
+    };
 
-        let node = new_node.unwrap_or(handle);
+    let node = new_node.unwrap_or(handle);
 
-        if continue_children {
-            let mut new_children = Vec::new();
-            for child in node.children.borrow().iter() {
-                let (new_child, _) = self.traverse(child.clone());
-                new_children.push(new_child);
+    if continue_children {
+        let children_foreign = children_in_foreign_content(&node, foreign);
+        let split = !children_foreign || visitor.visits_foreign_content();
+        let mut new_children = Vec::new();
+        for child in node.children.borrow().iter() {
+            if let NodeData::Text { ref contents } = child.data {
+                if let Some(nodes) = split.then(|| visitor.split_text(contents, child)).flatten() {
+                    for new_child in nodes {
+                        new_child.parent.set(Some(WeakHandle::from(&node)));
+                        new_children.push(new_child);
+                    }
+                    continue;
+                }
             }
-            *node.children.borrow_mut() = new_children;
+            new_children.push(traverse_node(visitor, child.clone(), children_foreign));
         }
+        *node.children.borrow_mut() = new_children;
+    }
+
+    node
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{orphan_visitor::OrphanVisitor, *};
+    use crate::rc_dom::RcDom;
+
+    fn process(html: &str) -> String {
+        let dom = RcDom::from_fragment_str(html);
+        let (root, _) = OrphanVisitor::new().traverse(dom.fragment_root());
+        root.inner_html()
+    }
+
+    #[test]
+    fn test_skips_svg_and_mathml() {
+        let html = process(
+            "<figure><svg viewBox=\"0 0 10 10\"><title>Dose curve</title><text>mg/kg</text></svg>\
+             <math><mi>x</mi><mtext><b>bold</b></mtext></math><figcaption>Caption</figcaption></figure>",
+        );
+        assert_eq!(html.matches("<rich-text").count(), 1);
+        assert!(html.contains("<title>Dose curve</title><text>mg/kg</text>"));
+        assert!(html.contains("<mi>x</mi><mtext><b>bold</b></mtext>"));
+        assert!(html.contains(">Caption</rich-text></figcaption>"));
+    }
 
-        (node, true)
+    #[test]
+    fn test_visits_html_integration_points() {
+        let html = process(
+            "<svg><foreignObject><p>Inside</p></foreignObject></svg>\
+             <math><annotation-xml encoding=\"text/html\"><p>Note</p></annotation-xml>\
+             <annotation-xml>Plain</annotation-xml></math>",
+        );
+        assert!(html.contains("<foreignObject><p><rich-text"));
+        assert!(html.contains(">Inside</rich-text></p>"));
+        assert!(html.contains(">Note</rich-text></p>"));
+        assert!(html.contains("<annotation-xml>Plain</annotation-xml>"));
     }
 }
@@ -1,25 +1,100 @@
 use super::NodeVisitor;
-use crate::rc_dom::{Handle, Node, NodeData};
-use html5ever::{Attribute, LocalName, Namespace, QualName};
+use crate::{
+    rc_dom::{builder::element, Handle, NodeData},
+    rich_text::{Provenance, RichTextBlock, RichTextMarkup},
+    util::{heading_level, node_path, parent},
+    whitespace::{normalize_text, normalize_whitespace, TextNormalizer, WhitespaceOptions},
+};
+use html5ever::{Attribute, QualName};
 use uuid::Uuid;
-use std::{cell::RefCell, collections::HashMap};
-use markup5ever::{namespace_url, ns};
-
-const RICH_TEXT_TAG: &str = "rich-text";
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
 
 pub struct OrphanVisitor {
     html_map: HashMap<Uuid, String>,
+    blocks: Vec<RichTextBlock>,
+    file: PathBuf,
+    /// The headings of the sections around the current node, outermost first, with their
+    /// level and the heading element.
+    headings: Vec<(u8, String, Handle)>,
+    whitespace: WhitespaceOptions,
+    texts: TextNormalizer,
+    markup: RichTextMarkup,
 }
 
 impl OrphanVisitor {
     pub fn new() -> Self {
         OrphanVisitor {
             html_map: HashMap::new(),
+            blocks: Vec::new(),
+            file: PathBuf::new(),
+            headings: Vec::new(),
+            whitespace: WhitespaceOptions::default(),
+            texts: TextNormalizer::default(),
+            markup: RichTextMarkup::default(),
         }
     }
+    /// Sets how whitespace in the extracted rich text is normalized.
+    pub fn with_whitespace_options(mut self, whitespace: WhitespaceOptions) -> Self {
+        self.whitespace = whitespace;
+        self.texts = TextNormalizer::new(whitespace);
+        self
+    }
+    /// Sets the element and id attribute the rich-text blocks are wrapped in.
+    pub fn with_rich_text_markup(mut self, markup: RichTextMarkup) -> Self {
+        self.markup = markup;
+        self
+    }
+    /// Sets the file recorded in the provenance of the blocks, relative to the directory
+    /// being processed.
+    pub fn with_file<P: AsRef<Path>>(mut self, file: P) -> Self {
+        self.file = file.as_ref().to_path_buf();
+        self
+    }
     pub fn html_map(&self) -> &HashMap<Uuid, String> {
         &self.html_map
     }
+    /// The blocks of the document with their provenance, in document order.
+    pub fn blocks(&self) -> &[RichTextBlock] {
+        &self.blocks
+    }
+    /// Records a block held by `container`: a paragraph or list, or the parent of a text
+    /// node or existing rich-text element.
+    fn record(&mut self, id: Uuid, html: String, container: Option<&Handle>) {
+        let path = container.map(node_path).unwrap_or_default();
+        // The heading of a block inside a heading is that of the enclosing section
+        let in_heading = self.headings.last().is_some_and(|(_, _, heading)| {
+            container.is_some_and(|container| is_within(container, heading))
+        });
+        let headings = &self.headings[..self.headings.len() - in_heading as usize];
+        let heading = headings.last().map(|(_, text, _)| text.clone());
+        self.blocks.push(RichTextBlock {
+            id,
+            provenance: Provenance {
+                file: self.file.clone(),
+                path,
+                heading,
+                order: self.blocks.len(),
+                content_hash: Provenance::hash_content(&html),
+            },
+            html: html.clone(),
+        });
+        self.html_map.insert(id, html);
+    }
+    /// Normalizes the whitespace inside an element, and returns copies of its children, so
+    /// the new elements built from them share no nodes with the original tree.
+    fn sanitize_children(&self, handle: &Handle) -> Vec<Handle> {
+        normalize_whitespace(handle, &self.whitespace);
+        handle
+            .children
+            .borrow()
+            .iter()
+            .map(Handle::deep_clone)
+            .collect()
+    }
 }
 
 impl NodeVisitor for OrphanVisitor {
@@ -32,18 +107,16 @@ impl NodeVisitor for OrphanVisitor {
         if !text.trim().is_empty() {
             log::info!("Visiting non-empty text node: {:?}", text);
 
-            let sanitized_text = sanitize_text(&text);
-            let text_node = create_text_node(&sanitized_text);
+            let sanitized_text = self.texts.normalize(handle);
             let uuid = uuid::Uuid::new_v4();
-            let new_attrs: Vec<Attribute> = vec![Attribute {
-                name: QualName::new(None, ns!(), LocalName::from("id")),
-                value: uuid.to_string().into(),
-            }];
-            let rich_text_node = create_element(RICH_TEXT_TAG, new_attrs, vec![text_node]);
-            
-            // Insert the UUID and inner HTML into the html_map
-            self.html_map.insert(uuid, sanitized_text);
-            
+            let rich_text_node = self
+                .markup
+                .element(&uuid.to_string())
+                .text(&sanitized_text)
+                .build();
+
+            self.record(uuid, sanitized_text, parent(handle).as_ref());
+
             (Some(rich_text_node), false)
         } else {
             log::debug!("Skipping empty or whitespace-only text node");
@@ -59,46 +132,72 @@ impl NodeVisitor for OrphanVisitor {
         handle: &Handle,
     ) -> (Option<Handle>, bool) {
         let element_name = name.local.as_ref();
+        if let Some(level) = heading_level(element_name) {
+            while self.headings.last().is_some_and(|(outer, _, _)| *outer >= level) {
+                self.headings.pop();
+            }
+            let text = normalize_text(&handle.text_content(), &self.whitespace);
+            self.headings.push((level, text, handle.clone()));
+        }
         match element_name {
+            _ if self.markup.is_rich_text(name) => {
+                log::info!("Visiting existing rich-text element");
+                let uuid = self.markup.uuid(&attrs.borrow());
+                if let Some(uuid) = uuid {
+                    self.record(uuid, handle.to_html_string(), parent(handle).as_ref());
+                }
+                (None, false)
+            },
+            "ul" | "ol" | "p" if wraps_rich_text(&self.markup, &handle.children.borrow()) => (None, true),
             lists @ ("ul" | "ol") => {
                 log::info!("Visiting list element");
               
-                let sanitized_children = sanitize_children(&handle.children.borrow());
+                let sanitized_children = self.sanitize_children(handle);
                 let uuid = uuid::Uuid::new_v4();
-                let rich_text_attrs: Vec<Attribute> = vec![Attribute {
-                    name: QualName::new(None, ns!(), LocalName::from("id")),
-                    value: uuid.to_string().into(),
-                }];
-                let rich_text_node = create_element(RICH_TEXT_TAG, rich_text_attrs, sanitized_children.clone());
+                let rich_text_node = self
+                    .markup
+                    .element(&uuid.to_string())
+                    .child(sanitized_children)
+                    .build();
                 
-                // Insert the UUID and inner HTML into the html_map
+                // Record the UUID and inner HTML of the new block
                 let inner_html = rich_text_node.to_html_string();
-                self.html_map.insert(uuid, inner_html);
+                self.record(uuid, inner_html, Some(handle));
                 
-                let new_node = create_element(lists, attrs.borrow().clone(), vec![rich_text_node]);
-                (Some(new_node), true)
+                let new_node = element(lists)
+                    .attrs(attrs.borrow().clone())
+                    .child(rich_text_node)
+                    .build();
+                // Its only child is the new block, recorded already
+                (Some(new_node), false)
             },
             li @ "li" => {
                 log::info!("Visiting list item element");       
-                let sanitized_children = sanitize_children(&handle.children.borrow());
-                let new_node = create_element(li, attrs.borrow().clone(), sanitized_children);
+                let sanitized_children = self.sanitize_children(handle);
+                let new_node = element(li)
+                    .attrs(attrs.borrow().clone())
+                    .child(sanitized_children)
+                    .build();
                 (Some(new_node), false)
             },
             paragraph @ "p" => {
                 log::info!("Visiting paragraph element");
-                let sanitized_children = sanitize_children(&handle.children.borrow());
+                let sanitized_children = self.sanitize_children(handle);
                 let uuid = uuid::Uuid::new_v4();
-                let rich_text_attrs: Vec<Attribute> = vec![Attribute {
-                    name: QualName::new(None, ns!(), LocalName::from("id")),
-                    value: uuid.to_string().into(),
-                }];
-                let rich_text_node = create_element(RICH_TEXT_TAG, rich_text_attrs, sanitized_children.clone());
+                let rich_text_node = self
+                    .markup
+                    .element(&uuid.to_string())
+                    .child(sanitized_children)
+                    .build();
                
-                // Insert the UUID and inner HTML into the html_map
+                // Record the UUID and inner HTML of the new block
                 let inner_html = rich_text_node.to_html_string();
-                self.html_map.insert(uuid, inner_html);
+                self.record(uuid, inner_html, Some(handle));
                
-                let new_node = create_element(paragraph, attrs.borrow().clone(), vec![rich_text_node]);
+                let new_node = element(paragraph)
+                    .attrs(attrs.borrow().clone())
+                    .child(rich_text_node)
+                    .build();
                 (Some(new_node), false)
             },
             _ => (None, true)
@@ -106,50 +205,71 @@ impl NodeVisitor for OrphanVisitor {
     }
 }
 
-// Add this new function to sanitize children nodes
-fn sanitize_children(children: &Vec<Handle>) -> Vec<Handle> {
-    children.iter().map(|child| {
-        match child.data {
-            NodeData::Text { ref contents } => {
-                let sanitized_text = sanitize_text(&contents.borrow());
-                create_text_node(&sanitized_text)
-            },
-            _ => child.clone(),
+/// Whether the children are a single rich-text element, ignoring whitespace, as in a
+/// document that has already been processed.
+fn wraps_rich_text(markup: &RichTextMarkup, children: &[Handle]) -> bool {
+    let mut content = children.iter().filter(|child| match child.data {
+        NodeData::Text { ref contents } => !contents.borrow().trim().is_empty(),
+        _ => true,
+    });
+    match (content.next(), content.next()) {
+        (Some(only), None) => markup.is_rich_text_node(only),
+        _ => false,
+    }
+}
+
+/// Whether `node` is `ancestor` or one of its descendants.
+fn is_within(node: &Handle, ancestor: &Handle) -> bool {
+    let mut current = Some(node.clone());
+    while let Some(node) = current {
+        if std::ptr::eq(&*node, &**ancestor) {
+            return true;
         }
-    }).collect()
+        current = parent(&node);
+    }
+    false
 }
 
-/// Creates a new element with the given name, attributes, and children.
-fn create_element(name: &str, attrs: Vec<Attribute>, children: Vec<Handle>) -> Handle {
-    let element = NodeData::Element {
-        name: html5ever::QualName::new(
-            None,
-            html5ever::ns!(),
-            html5ever::LocalName::from(name),
-        ),
-        attrs: RefCell::new(attrs),
-        template_contents: RefCell::new(None),
-        mathml_annotation_xml_integration_point: false,
-    };
-    let handle = Node::new(element);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rc_dom::RcDom;
 
-    *handle.children.borrow_mut() = children;
-    handle
-}
+    #[test]
+    fn test_records_provenance() {
+        let dom = RcDom::from_str(
+            "<body><h1>Leaflet</h1><p>Intro</p><section><h2>Dosing <em>guide</em></h2>\
+             <p>Take one.</p><ul><li>Morning</li></ul></section><h2>Storage</h2><p>Keep cool.</p></body>",
+        );
+        let mut visitor = OrphanVisitor::new().with_file("leaflets/a.html");
+        visitor.traverse(dom.document);
 
-/// Creates a new text node with the given content.
-fn create_text_node(content: &str) -> Handle {
-    Node::new(NodeData::Text {
-        contents: RefCell::new(content.into()),
-    })
-}
+        let blocks: Vec<_> = visitor
+            .blocks()
+            .iter()
+            .map(|block| {
+                let provenance = &block.provenance;
+                (provenance.order, provenance.path.as_str(), provenance.heading.as_deref())
+            })
+            .collect();
+        assert_eq!(
+            blocks,
+            [
+                (0, "html > body > h1", None),
+                (1, "html > body > p:nth-of-type(1)", Some("Leaflet")),
+                (2, "html > body > section > h2", Some("Leaflet")),
+                (3, "html > body > section > h2 > em", Some("Leaflet")),
+                (4, "html > body > section > p", Some("Dosing guide")),
+                (5, "html > body > section > ul", Some("Dosing guide")),
+                (6, "html > body > h2", Some("Leaflet")),
+                (7, "html > body > p:nth-of-type(2)", Some("Storage")),
+            ]
+        );
 
-fn sanitize_text(text: &str) -> String {
-    text.lines()
-        .map(|line| line.trim())
-        .filter(|line| !line.is_empty())
-        .collect::<Vec<&str>>()
-        .join(" ")
-        .replace('\u{00A0}', " ") // Replace non-breaking spaces with regular spaces
-        .replace("  ", " ") // Replace double spaces with single spaces
+        let block = &visitor.blocks()[1];
+        assert_eq!(block.provenance.file, Path::new("leaflets/a.html"));
+        assert_eq!(visitor.html_map()[&block.id], block.html);
+        assert_eq!(block.provenance.content_hash, Provenance::hash_content("Intro"));
+        assert_ne!(block.provenance.content_hash, Provenance::hash_content("Intro!"));
+    }
 }
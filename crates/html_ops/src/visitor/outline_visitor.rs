@@ -0,0 +1,308 @@
+use super::NodeVisitor;
+use crate::{
+    node,
+    rc_dom::{builder, Handle, NodeData},
+    util::{find_attribute, heading_level, parent},
+    whitespace::{normalize_text, WhitespaceOptions},
+};
+use html5ever::{Attribute, QualName};
+use serde::Serialize;
+use std::{cell::RefCell, collections::HashSet};
+
+/// The attribute marking the element the table of contents is rendered into.
+pub const TOC_PLACEHOLDER_ATTRIBUTE: &str = "data-toc";
+
+/// A heading, with the headings of lower rank that follow it until the next heading of the
+/// same or higher rank.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OutlineEntry {
+    /// 1 for `h1` to 6 for `h6`.
+    pub level: u8,
+    pub id: String,
+    pub text: String,
+    pub children: Vec<OutlineEntry>,
+}
+
+/// A heading more than one level below the heading before it, as an `h4` following an
+/// `h2`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SkippedLevel {
+    pub id: String,
+    pub previous_level: u8,
+    pub level: u8,
+}
+
+/// A visitor that collects `h1`–`h6` into an outline, giving every heading without an id
+/// a slug of its text, unique within the document.
+///
+/// The first `<div data-toc>` found is remembered, and [`OutlineVisitor::inject_toc`]
+/// renders the outline into it once the document has been traversed.
+pub struct OutlineVisitor {
+    headings: Vec<OutlineEntry>,
+    ids: HashSet<String>,
+    /// Whether the ids of the traversed tree are in `ids` yet.
+    reserved: bool,
+    skipped_levels: Vec<SkippedLevel>,
+    placeholder: Option<Handle>,
+}
+
+impl Default for OutlineVisitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OutlineVisitor {
+    pub fn new() -> Self {
+        OutlineVisitor {
+            headings: Vec::new(),
+            ids: HashSet::new(),
+            reserved: false,
+            skipped_levels: Vec::new(),
+            placeholder: None,
+        }
+    }
+
+    /// The headings nested by level, in document order.
+    pub fn outline(&self) -> Vec<OutlineEntry> {
+        let mut roots: Vec<OutlineEntry> = Vec::new();
+        for heading in self.headings.iter().cloned() {
+            let mut siblings = &mut roots;
+            while siblings
+                .last()
+                .is_some_and(|last| last.level < heading.level)
+            {
+                siblings = &mut siblings.last_mut().unwrap().children;
+            }
+            siblings.push(heading);
+        }
+        roots
+    }
+
+    pub fn skipped_levels(&self) -> &[SkippedLevel] {
+        &self.skipped_levels
+    }
+
+    /// Renders the outline as a `<nav>` into the `<div data-toc>` placeholder, replacing
+    /// its content. Returns whether the document had a placeholder.
+    pub fn inject_toc(&self) -> bool {
+        let Some(placeholder) = self.placeholder.as_ref() else {
+            return false;
+        };
+        builder::set_children(placeholder, vec![render_toc(&self.outline())]);
+        true
+    }
+
+    /// Reserves the ids already in the tree of the first node visited, whether a document,
+    /// a fragment root or an element deep in a document, so that slugs never clash with
+    /// them.
+    fn reserve_tree_ids(&mut self, handle: &Handle) {
+        if self.reserved {
+            return;
+        }
+        self.reserved = true;
+        let root = std::iter::successors(Some(handle.clone()), parent)
+            .last()
+            .unwrap_or_else(|| handle.clone());
+        self.reserve_ids(&root);
+    }
+
+    fn reserve_ids(&mut self, handle: &Handle) {
+        if let NodeData::Element { ref attrs, .. } = handle.data {
+            if let Some(id) = find_attribute(&attrs.borrow(), "id") {
+                self.ids.insert(id);
+            }
+        }
+        for child in handle.children.borrow().iter() {
+            self.reserve_ids(child);
+        }
+    }
+
+    fn unique_slug(&mut self, text: &str) -> String {
+        let slug = slugify(text);
+        let mut candidate = slug.clone();
+        let mut suffix = 1;
+        while self.ids.contains(&candidate) {
+            candidate = format!("{}-{}", slug, suffix);
+            suffix += 1;
+        }
+        self.ids.insert(candidate.clone());
+        candidate
+    }
+}
+
+impl NodeVisitor for OutlineVisitor {
+    fn visit_document(&mut self, handle: &Handle) -> (Option<Handle>, bool) {
+        self.reserve_tree_ids(handle);
+        (None, true)
+    }
+
+    fn visit_element(
+        &mut self,
+        name: &QualName,
+        attrs: &RefCell<Vec<Attribute>>,
+        _template_contents: &RefCell<Option<Handle>>,
+        _mathml_annotation_xml_integration_point: bool,
+        handle: &Handle,
+    ) -> (Option<Handle>, bool) {
+        self.reserve_tree_ids(handle);
+        if find_attribute(&attrs.borrow(), TOC_PLACEHOLDER_ATTRIBUTE).is_some() {
+            if self.placeholder.is_none() {
+                self.placeholder = Some(handle.clone());
+            }
+            return (None, false);
+        }
+
+        let Some(level) = heading_level(name.local.as_ref()) else {
+            return (None, true);
+        };
+        let text = normalize_text(&handle.text_content(), &WhitespaceOptions::default());
+
+        let existing_id = find_attribute(&attrs.borrow(), "id").filter(|id| !id.is_empty());
+        let id = match existing_id {
+            Some(id) => id,
+            None => {
+                let id = self.unique_slug(&text);
+                let mut attrs = attrs.borrow_mut();
+                attrs.retain(|attr| attr.name.local.as_ref() != "id");
+                attrs.push(builder::attribute("id", &id));
+                id
+            }
+        };
+
+        if let Some(previous) = self.headings.last() {
+            if level > previous.level + 1 {
+                log::warn!(
+                    "Heading #{} skips from h{} to h{}",
+                    id,
+                    previous.level,
+                    level
+                );
+                self.skipped_levels.push(SkippedLevel {
+                    id: id.clone(),
+                    previous_level: previous.level,
+                    level,
+                });
+            }
+        }
+
+        self.headings.push(OutlineEntry {
+            level,
+            id,
+            text,
+            children: Vec::new(),
+        });
+        (None, false)
+    }
+}
+
+/// Lowercases the text, keeps letters and digits, and joins the words with hyphens.
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if matches!(c, ' ' | '-' | '_') && !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "section".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+/// Renders an outline as nested ordered lists of links inside a `<nav>`.
+pub fn render_toc(outline: &[OutlineEntry]) -> Handle {
+    node!(nav [class = "toc", "aria-label" = "Table of contents"] {
+        (render_entries(outline))
+    })
+}
+
+fn render_entries(entries: &[OutlineEntry]) -> Handle {
+    let items = entries.iter().map(|entry| {
+        node!(li {
+            a [href = format!("#{}", entry.id)] { (builder::text(&entry.text)) }
+            ((!entry.children.is_empty()).then(|| render_entries(&entry.children)))
+        })
+    });
+    node!(ol { (items) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rc_dom::RcDom;
+
+    fn outline(html: &str) -> (OutlineVisitor, Handle) {
+        let dom = RcDom::from_str(html);
+        let mut visitor = OutlineVisitor::new();
+        let (document, _) = visitor.traverse(dom.document);
+        (visitor, document)
+    }
+
+    #[test]
+    fn test_slugs_are_unique() {
+        let (visitor, document) = outline(
+            "<h1>Dosing &amp; Safety</h1><h2 id=\"faq\">FAQ</h2><h2>FAQ</h2><h2>FAQ</h2><h2>  Ünïcode — 2024 </h2><h2>?</h2>",
+        );
+        let ids: Vec<_> = visitor.headings.iter().map(|h| h.id.as_str()).collect();
+        assert_eq!(
+            ids,
+            [
+                "dosing-safety",
+                "faq",
+                "faq-1",
+                "faq-2",
+                "ünïcode-2024",
+                "section"
+            ]
+        );
+        assert!(document
+            .to_html_string()
+            .contains("<h2 id=\"faq-1\">FAQ</h2>"));
+    }
+
+    #[test]
+    fn test_nested_outline_and_skipped_levels() {
+        let (visitor, _) = outline("<h1>A</h1><h2>B</h2><h4>C</h4><h2>D</h2><h1>E</h1>");
+        let outline = visitor.outline();
+        assert_eq!(outline.len(), 2);
+        assert_eq!(outline[0].children.len(), 2);
+        assert_eq!(outline[0].children[0].children[0].text, "C");
+        assert_eq!(
+            visitor.skipped_levels(),
+            [SkippedLevel {
+                id: "c".to_string(),
+                previous_level: 2,
+                level: 4,
+            }]
+        );
+        assert_eq!(
+            serde_json::to_value(&outline[1]).unwrap(),
+            serde_json::json!({ "level": 1, "id": "e", "text": "E", "children": [] })
+        );
+    }
+
+    #[test]
+    fn test_fragment_ids_are_reserved() {
+        let dom = RcDom::from_fragment_str("<h2>FAQ</h2><p id=\"faq\">See below</p>");
+        let mut visitor = OutlineVisitor::new();
+        visitor.traverse(dom.fragment_root());
+        assert_eq!(visitor.headings[0].id, "faq-1");
+    }
+
+    #[test]
+    fn test_inject_toc() {
+        let (visitor, document) =
+            outline("<div data-toc>old</div><h2>One</h2><h3>Two</h3><h2>Three</h2>");
+        assert!(visitor.inject_toc());
+        assert!(document.to_html_string().contains(
+            "<div data-toc=\"\"><nav class=\"toc\" aria-label=\"Table of contents\"><ol>\
+             <li><a href=\"#one\">One</a><ol><li><a href=\"#two\">Two</a></li></ol></li>\
+             <li><a href=\"#three\">Three</a></li></ol></nav></div>"
+        ));
+    }
+}
@@ -0,0 +1,443 @@
+use super::NodeVisitor;
+use crate::{
+    rc_dom::{Handle, RcDom},
+    rich_text::RichTextMarkup,
+    walk::process_html_files,
+    whitespace::{normalize_text, WhitespaceOptions},
+};
+use html5ever::{Attribute, QualName};
+use serde::Serialize;
+use std::{
+    cell::RefCell,
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Abbreviations whose final period does not end a sentence, lowercased.
+const ABBREVIATIONS: &[&str] = &[
+    "e.g.", "i.e.", "cf.", "vs.", "approx.", "ca.", "dr.", "mr.", "mrs.", "ms.", "prof.", "st.",
+    "no.", "fig.", "vol.", "al.", "min.", "max.",
+];
+
+/// Counts of a piece of text, from which the readability scores are computed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TextStats {
+    pub words: usize,
+    pub sentences: usize,
+    pub syllables: usize,
+    /// Words of three or more syllables.
+    pub polysyllables: usize,
+    pub longest_sentence: String,
+    pub longest_sentence_words: usize,
+}
+
+impl TextStats {
+    pub fn from_text(text: &str) -> Self {
+        let mut stats = TextStats::default();
+        for sentence in split_sentences(text) {
+            let words: Vec<&str> = sentence.split_whitespace().filter(|w| is_word(w)).collect();
+            if words.is_empty() {
+                continue;
+            }
+            stats.sentences += 1;
+            stats.words += words.len();
+            for word in words.iter() {
+                let syllables = count_syllables(word);
+                stats.syllables += syllables;
+                if syllables >= 3 {
+                    stats.polysyllables += 1;
+                }
+            }
+            if words.len() > stats.longest_sentence_words {
+                stats.longest_sentence_words = words.len();
+                stats.longest_sentence = sentence.to_string();
+            }
+        }
+        stats
+    }
+
+    /// Adds the counts of another text, as if it followed this one.
+    pub fn merge(&mut self, other: &TextStats) {
+        self.words += other.words;
+        self.sentences += other.sentences;
+        self.syllables += other.syllables;
+        self.polysyllables += other.polysyllables;
+        if other.longest_sentence_words > self.longest_sentence_words {
+            self.longest_sentence_words = other.longest_sentence_words;
+            self.longest_sentence = other.longest_sentence.clone();
+        }
+    }
+
+    pub fn reading_time_seconds(&self, words_per_minute: f64) -> f64 {
+        self.words as f64 * 60.0 / words_per_minute
+    }
+
+    /// The Flesch–Kincaid grade level, or `None` for text without words.
+    pub fn flesch_kincaid_grade(&self) -> Option<f64> {
+        if self.words == 0 {
+            return None;
+        }
+        let words_per_sentence = self.words as f64 / self.sentences as f64;
+        let syllables_per_word = self.syllables as f64 / self.words as f64;
+        Some(0.39 * words_per_sentence + 11.8 * syllables_per_word - 15.59)
+    }
+
+    /// The SMOG grade, or `None` for text without sentences. SMOG is calibrated on samples
+    /// of 30 sentences, so it is rough for short blocks.
+    pub fn smog_grade(&self) -> Option<f64> {
+        if self.sentences == 0 {
+            return None;
+        }
+        let polysyllables = self.polysyllables as f64 * 30.0 / self.sentences as f64;
+        Some(1.043 * polysyllables.sqrt() + 3.1291)
+    }
+}
+
+/// Settings of a readability report.
+#[derive(Debug, Clone)]
+pub struct ReadabilityOptions {
+    /// Blocks with a Flesch–Kincaid grade above this are flagged.
+    pub target_grade: Option<f64>,
+    /// Blocks with fewer words, such as headings, are never flagged, as their scores are
+    /// meaningless.
+    pub min_words: usize,
+    pub words_per_minute: f64,
+    /// The element and id attribute of the rich-text blocks.
+    pub rich_text: RichTextMarkup,
+}
+
+impl Default for ReadabilityOptions {
+    fn default() -> Self {
+        ReadabilityOptions {
+            target_grade: None,
+            min_words: 10,
+            words_per_minute: 200.0,
+            rich_text: RichTextMarkup::default(),
+        }
+    }
+}
+
+/// The statistics of a rich-text block or of a page.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReadabilityEntry {
+    pub file: PathBuf,
+    /// The rich-text id, or `None` for the statistics of the whole page.
+    pub id: Option<String>,
+    #[serde(flatten)]
+    pub stats: TextStats,
+    pub reading_time_seconds: f64,
+    pub flesch_kincaid_grade: Option<f64>,
+    pub smog_grade: Option<f64>,
+    /// Whether the block is above the target grade level. For a page, whether any of its
+    /// blocks is.
+    pub above_target: bool,
+}
+
+impl ReadabilityEntry {
+    fn new(
+        file: &Path,
+        id: Option<String>,
+        stats: TextStats,
+        options: &ReadabilityOptions,
+    ) -> Self {
+        let flesch_kincaid_grade = stats.flesch_kincaid_grade();
+        let above_target = match (options.target_grade, flesch_kincaid_grade) {
+            (Some(target), Some(grade)) => stats.words >= options.min_words && grade > target,
+            _ => false,
+        };
+        ReadabilityEntry {
+            file: file.to_path_buf(),
+            id,
+            reading_time_seconds: stats.reading_time_seconds(options.words_per_minute),
+            flesch_kincaid_grade,
+            smog_grade: stats.smog_grade(),
+            above_target,
+            stats,
+        }
+    }
+}
+
+/// A visitor that computes the statistics of every rich-text block of a page.
+pub struct ReadabilityVisitor<'a> {
+    file: PathBuf,
+    options: &'a ReadabilityOptions,
+    blocks: Vec<ReadabilityEntry>,
+}
+
+impl<'a> ReadabilityVisitor<'a> {
+    pub fn new<P: AsRef<Path>>(file: P, options: &'a ReadabilityOptions) -> Self {
+        ReadabilityVisitor {
+            file: file.as_ref().to_path_buf(),
+            options,
+            blocks: Vec::new(),
+        }
+    }
+
+    pub fn blocks(&self) -> &[ReadabilityEntry] {
+        &self.blocks
+    }
+
+    /// The statistics of the page, as the sum of its blocks.
+    pub fn page(&self) -> ReadabilityEntry {
+        let mut stats = TextStats::default();
+        for block in self.blocks.iter() {
+            stats.merge(&block.stats);
+        }
+        let mut page = ReadabilityEntry::new(&self.file, None, stats, self.options);
+        page.above_target = self.blocks.iter().any(|block| block.above_target);
+        page
+    }
+}
+
+impl NodeVisitor for ReadabilityVisitor<'_> {
+    fn visit_element(
+        &mut self,
+        name: &QualName,
+        attrs: &RefCell<Vec<Attribute>>,
+        _template_contents: &RefCell<Option<Handle>>,
+        _mathml_annotation_xml_integration_point: bool,
+        handle: &Handle,
+    ) -> (Option<Handle>, bool) {
+        if !self.options.rich_text.is_rich_text(name) {
+            return (None, true);
+        }
+        let id = self.options.rich_text.id(&attrs.borrow());
+        let text = normalize_text(&handle.text_content(), &WhitespaceOptions::default());
+        let stats = TextStats::from_text(&text);
+        if stats.words > 0 {
+            let block = ReadabilityEntry::new(&self.file, id, stats, self.options);
+            if block.above_target {
+                log::warn!(
+                    "Rich text {:?} in {:?} is above the target grade level",
+                    block.id,
+                    self.file
+                );
+            }
+            self.blocks.push(block);
+        }
+        (None, false)
+    }
+}
+
+/// The statistics of every page and block of a directory.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReadabilityReport {
+    pub pages: Vec<ReadabilityEntry>,
+    pub blocks: Vec<ReadabilityEntry>,
+}
+
+impl ReadabilityReport {
+    /// Computes the statistics of every HTML file under `directory`, which should hold
+    /// documents already processed by `OrphanVisitor`.
+    pub fn from_dir<P: AsRef<Path>>(
+        directory: P,
+        options: &ReadabilityOptions,
+    ) -> io::Result<Self> {
+        let mut report = ReadabilityReport::default();
+        process_html_files(directory, |path, relative_path| {
+            let dom = RcDom::from_file(path)?;
+            let mut visitor = ReadabilityVisitor::new(relative_path, options);
+            visitor.traverse(dom.document);
+            report.pages.push(visitor.page());
+            report.blocks.extend(visitor.blocks);
+            Ok(())
+        })?;
+        Ok(report)
+    }
+
+    /// Writes the pages and blocks as CSV, one row each; page rows have an empty id.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "file,id,words,sentences,syllables,polysyllables,reading_time_seconds,\
+             flesch_kincaid_grade,smog_grade,longest_sentence_words,longest_sentence,above_target\n",
+        );
+        for entry in self.pages.iter().chain(self.blocks.iter()) {
+            let grade = |grade: Option<f64>| grade.map(|g| format!("{:.1}", g)).unwrap_or_default();
+            let fields = [
+                entry.file.to_string_lossy().to_string(),
+                entry.id.clone().unwrap_or_default(),
+                entry.stats.words.to_string(),
+                entry.stats.sentences.to_string(),
+                entry.stats.syllables.to_string(),
+                entry.stats.polysyllables.to_string(),
+                format!("{:.0}", entry.reading_time_seconds),
+                grade(entry.flesch_kincaid_grade),
+                grade(entry.smog_grade),
+                entry.stats.longest_sentence_words.to_string(),
+                entry.stats.longest_sentence.clone(),
+                entry.above_target.to_string(),
+            ];
+            let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+            csv.push_str(&row.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Splits text after `.`, `!` and `?` followed by whitespace, so that decimals such as
+/// "2.5 mg" stay in one sentence. The period of an abbreviation such as "e.g." or "Dr."
+/// does not end a sentence.
+fn split_sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if !matches!(c, '.' | '!' | '?') || (c == '.' && is_abbreviation(&text[..=i])) {
+            continue;
+        }
+        // Include closing punctuation such as "?!" or quotes in the sentence
+        while let Some(&(_, next)) = chars.peek() {
+            if matches!(next, '.' | '!' | '?' | '"' | '\'' | ')' | '”' | '’') {
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        let end = chars.peek().map_or(text.len(), |&(i, _)| i);
+        if chars.peek().is_none_or(|&(_, next)| next.is_whitespace()) {
+            sentences.push(text[start..end].trim());
+            start = end;
+        }
+    }
+    if start < text.len() {
+        sentences.push(text[start..].trim());
+    }
+    sentences.into_iter().filter(|s| !s.is_empty()).collect()
+}
+
+/// Whether the last word of `text`, which ends with a period, is an abbreviation.
+fn is_abbreviation(text: &str) -> bool {
+    let word = text
+        .rsplit(char::is_whitespace)
+        .next()
+        .unwrap_or_default()
+        .trim_start_matches(|c: char| !c.is_alphanumeric());
+    ABBREVIATIONS.contains(&word.to_lowercase().as_str())
+}
+
+fn is_word(token: &str) -> bool {
+    token.chars().any(char::is_alphanumeric)
+}
+
+/// Estimates the syllables of an English word from its vowel groups.
+fn count_syllables(word: &str) -> usize {
+    let word: String = word
+        .chars()
+        .filter(|c| c.is_alphabetic())
+        .flat_map(char::to_lowercase)
+        .collect();
+    if word.is_empty() {
+        // Numbers and symbols
+        return 1;
+    }
+    let is_vowel = |c: char| matches!(c, 'a' | 'e' | 'i' | 'o' | 'u' | 'y');
+    let chars: Vec<char> = word.chars().collect();
+    let mut count = 0;
+    let mut previous_vowel = false;
+    for &c in chars.iter() {
+        let vowel = is_vowel(c);
+        if vowel && !previous_vowel {
+            count += 1;
+        }
+        previous_vowel = vowel;
+    }
+    // A final "e" is usually silent, as in "take", but not in "table"
+    let len = chars.len();
+    if count > 1
+        && chars[len - 1] == 'e'
+        && !is_vowel(chars[len - 2])
+        && !(chars[len - 2] == 'l' && len > 2 && !is_vowel(chars[len - 3]))
+    {
+        count -= 1;
+    }
+    count.max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_stats() {
+        assert_eq!(count_syllables("take"), 1);
+        assert_eq!(count_syllables("table"), 2);
+        assert_eq!(count_syllables("medication"), 4);
+        assert_eq!(count_syllables("2.5"), 1);
+
+        let stats =
+            TextStats::from_text("Take 2.5 mg daily. Do not stop taking this medication suddenly!");
+        assert_eq!(stats.words, 11);
+        assert_eq!(stats.sentences, 2);
+        assert_eq!(stats.polysyllables, 2);
+        assert_eq!(stats.longest_sentence_words, 7);
+        assert_eq!(
+            stats.longest_sentence,
+            "Do not stop taking this medication suddenly!"
+        );
+        let grade = stats.flesch_kincaid_grade().unwrap();
+        assert!((grade - 5.86).abs() < 0.1, "grade {}", grade);
+    }
+
+    #[test]
+    fn test_abbreviations_do_not_end_sentences() {
+        assert_eq!(
+            split_sentences(
+                "Ask Dr. Lee about it, e.g. at the next visit (approx. 2 weeks). Stop! Really?"
+            ),
+            [
+                "Ask Dr. Lee about it, e.g. at the next visit (approx. 2 weeks).",
+                "Stop!",
+                "Really?"
+            ]
+        );
+        assert_eq!(
+            split_sentences("Take it daily. Do not stop."),
+            ["Take it daily.", "Do not stop."]
+        );
+    }
+
+    #[test]
+    fn test_flags_blocks_above_target() {
+        let html = "<h1><rich-text id=\"h\">Contraindications</rich-text></h1>\
+            <p><rich-text id=\"easy\">Take one pill each day. Drink a glass of water.</rich-text></p>\
+            <p><rich-text id=\"hard\">Concomitant administration of monoamine oxidase inhibitors \
+            necessitates individualized therapeutic monitoring considerations.</rich-text></p>";
+        let options = ReadabilityOptions {
+            target_grade: Some(8.0),
+            min_words: 5,
+            ..Default::default()
+        };
+        let dom = RcDom::from_str(html);
+        let mut visitor = ReadabilityVisitor::new("page.html", &options);
+        visitor.traverse(dom.document);
+
+        let flagged: Vec<_> = visitor
+            .blocks()
+            .iter()
+            .filter(|block| block.above_target)
+            .map(|block| block.id.as_deref().unwrap())
+            .collect();
+        assert_eq!(flagged, ["hard"]);
+
+        let page = visitor.page();
+        assert_eq!(page.stats.words, 1 + 10 + 11);
+        assert!(page.above_target);
+
+        let report = ReadabilityReport {
+            pages: vec![page],
+            blocks: visitor.blocks().to_vec(),
+        };
+        let csv = report.to_csv();
+        assert_eq!(csv.lines().count(), 5);
+        assert!(csv.lines().nth(1).unwrap().starts_with("page.html,,22,4,"));
+    }
+}
@@ -1,14 +1,13 @@
 use crate::rc_dom::{Handle, NodeData};
+use crate::rich_text::RichTextMarkup;
 use html5ever::{Attribute, QualName};
 use log::{debug, info, trace};
 use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use tendril::StrTendril;
 use uuid::Uuid;
-use html5ever::namespace_url;
 
 use super::NodeVisitor;
-use crate::rc_dom::Node;
 
 /// A visitor that wraps text content in rich-text elements and maintains a map of their IDs to content.
 pub struct RichTextWrapperVisitor {
@@ -16,6 +15,8 @@ pub struct RichTextWrapperVisitor {
     content_map: HashMap<String, String>,
     /// Set of HTML elements considered as root elements for rich-text wrapping.
     root_elements: HashSet<String>,
+    /// The element and id attribute the rich-text elements are made of.
+    markup: RichTextMarkup,
 }
 
 impl Default for RichTextWrapperVisitor {
@@ -38,9 +39,16 @@ impl RichTextWrapperVisitor {
         Self {
             content_map: HashMap::new(),
             root_elements,
+            markup: RichTextMarkup::default(),
         }
     }
 
+    /// Sets the element and id attribute the rich-text elements are made of.
+    pub fn with_rich_text_markup(mut self, markup: RichTextMarkup) -> Self {
+        self.markup = markup;
+        self
+    }
+
     /// Returns a reference to the content map.
     pub fn content_map(&self) -> &HashMap<String, String> {
         &self.content_map
@@ -77,7 +85,7 @@ impl NodeVisitor for RichTextWrapperVisitor {
         trace!("Visiting element: {:?}", name);
 
         match name.local.as_ref() {
-            "rich-text" => {
+            _ if self.markup.is_rich_text(name) => {
                 debug!("Skipping existing rich-text element");
                 (None, false)
             }
@@ -86,11 +94,11 @@ impl NodeVisitor for RichTextWrapperVisitor {
                 let unique_id = Uuid::new_v4().to_string();
                 let children_content = self.extract_children_content(&handle.children.borrow());
 
-                let rich_text_node = create_element(
-                    "rich-text",
-                    vec![("id", unique_id.clone())],
-                    vec![handle.clone()],
-                );
+                let rich_text_node = self
+                    .markup
+                    .element(&unique_id)
+                    .child(handle.clone())
+                    .build();
 
                 self.content_map.insert(unique_id, children_content);
 
@@ -106,11 +114,11 @@ impl NodeVisitor for RichTextWrapperVisitor {
         _handle: &Handle,
     ) -> (Option<Handle>, bool) {
         // let text_content = contents.borrow().to_string();
-        // let sanitized_content = sanitize_text(&text_content);
+        // let sanitized_content = normalize_text(&text_content, &WhitespaceOptions::default());
         
         // if !sanitized_content.is_empty() {
         //     let unique_id = Uuid::new_v4().to_string();
-        //     let rich_text_node = create_rich_text_element(&unique_id, &sanitized_content);
+        //     let rich_text_node = create_rich_text_element(&self.markup, &unique_id, &sanitized_content);
             
         //     self.content_map.insert(unique_id, sanitized_content);
             
@@ -125,69 +133,18 @@ impl NodeVisitor for RichTextWrapperVisitor {
 
 
 /// Creates a new rich-text element with the given ID and text content.
-fn create_rich_text_element(id: &str, content: &str) -> Handle {
-    create_element(
-        "rich-text",
-        vec![("id", id.to_string())],
-        vec![create_text_node(content)],
-    )
-}
-
-/// Creates a new element with the given name, attributes, and children.
-fn create_element(name: &str, attrs: Vec<(&str, String)>, children: Vec<Handle>) -> Handle {
-    let attrs = attrs
-        .into_iter()
-        .map(|(name, value)| html5ever::Attribute {
-            name: html5ever::QualName::new(
-                None,
-                html5ever::ns!(),
-                html5ever::LocalName::from(name),
-            ),
-            value: value.into(),
-        })
-        .collect::<Vec<Attribute>>();
-
-    let element = NodeData::Element {
-        name: html5ever::QualName::new(
-            None,
-            html5ever::ns!(),
-            html5ever::LocalName::from(name),
-        ),
-        attrs: RefCell::new(attrs),
-        template_contents: RefCell::new(None),
-        mathml_annotation_xml_integration_point: false,
-    };
-    let handle = Node::new(element);
-
-    *handle.children.borrow_mut() = children;
-    handle
-}
-
-/// Creates a new text node with the given content.
-fn create_text_node(content: &str) -> Handle {
-    Node::new(NodeData::Text {
-        contents: RefCell::new(content.into()),
-    })
-}
-
-/// Sanitizes the given text by trimming lines, removing empty lines, and normalizing spaces.
-fn sanitize_text(text: &str) -> String {
-    text.lines()
-        .map(|line| line.trim())
-        .filter(|line| !line.is_empty())
-        .collect::<Vec<&str>>()
-        .join(" ")
-        .replace('\u{00A0}', " ") // Replace non-breaking spaces with regular spaces
-        .replace("  ", " ") // Replace double spaces with single spaces
+fn create_rich_text_element(markup: &RichTextMarkup, id: &str, content: &str) -> Handle {
+    markup.element(id).text(content).build()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::whitespace::{normalize_text, WhitespaceOptions};
     use html5ever::parse_fragment;
     use html5ever::tendril::TendrilSink;
     use crate::rc_dom::RcDom;
-    use markup5ever::ns;
+    use markup5ever::{namespace_url, ns};
 
     fn parse_html(html: &str) -> RcDom {
         let parser = parse_fragment(RcDom::default(), Default::default(), QualName::new(None, ns!(html), "div".into()), vec![]);
@@ -222,7 +179,7 @@ mod tests {
     fn test_sanitize_text() {
         let input = "  Hello,\n  world!  \n\n  How are you?  ";
         let expected = "Hello, world! How are you?";
-        assert_eq!(sanitize_text(input), expected);
+        assert_eq!(normalize_text(input, &WhitespaceOptions::default()), expected);
     }
 
     #[test]
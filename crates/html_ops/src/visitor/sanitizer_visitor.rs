@@ -0,0 +1,415 @@
+use super::NodeVisitor;
+use crate::{
+    rc_dom::{Handle, NodeData, RcDom, WeakHandle},
+    rich_text::{RichTextMarkup, RICH_TEXT_TAG},
+};
+use html5ever::{Attribute, QualName};
+use markup5ever::{namespace_url, ns};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet, VecDeque},
+};
+
+/// Elements allowed by default, with the attributes each may have besides
+/// [`SanitizerConfig::global_attributes`].
+const DEFAULT_TAGS: &[(&str, &[&str])] = &[
+    ("html", &[]),
+    ("head", &[]),
+    ("title", &[]),
+    ("body", &[]),
+    (RICH_TEXT_TAG, &[]),
+    ("a", &["href", "hreflang", "rel", "target"]),
+    ("abbr", &[]),
+    ("b", &[]),
+    ("bdi", &[]),
+    ("bdo", &[]),
+    ("cite", &[]),
+    ("code", &[]),
+    ("del", &["cite", "datetime"]),
+    ("dfn", &[]),
+    ("em", &[]),
+    ("i", &[]),
+    ("ins", &["cite", "datetime"]),
+    ("kbd", &[]),
+    ("mark", &[]),
+    ("q", &["cite"]),
+    ("s", &[]),
+    ("samp", &[]),
+    ("small", &[]),
+    ("span", &[]),
+    ("strong", &[]),
+    ("sub", &[]),
+    ("sup", &[]),
+    ("time", &["datetime"]),
+    ("u", &[]),
+    ("var", &[]),
+    ("br", &[]),
+    ("wbr", &[]),
+    ("p", &[]),
+    ("div", &[]),
+    ("blockquote", &["cite"]),
+    ("pre", &[]),
+    ("hr", &[]),
+    ("h1", &[]),
+    ("h2", &[]),
+    ("h3", &[]),
+    ("h4", &[]),
+    ("h5", &[]),
+    ("h6", &[]),
+    ("ul", &[]),
+    ("ol", &["start", "reversed", "type"]),
+    ("li", &["value"]),
+    ("dl", &[]),
+    ("dt", &[]),
+    ("dd", &[]),
+    ("article", &[]),
+    ("aside", &[]),
+    ("details", &["open"]),
+    ("summary", &[]),
+    ("footer", &[]),
+    ("header", &[]),
+    ("main", &[]),
+    ("nav", &[]),
+    ("section", &[]),
+    ("figure", &[]),
+    ("figcaption", &[]),
+    ("picture", &[]),
+    ("source", &["srcset", "sizes", "media", "type"]),
+    (
+        "img",
+        &[
+            "src", "srcset", "sizes", "alt", "width", "height", "loading",
+        ],
+    ),
+    ("table", &[]),
+    ("caption", &[]),
+    ("colgroup", &["span"]),
+    ("col", &["span"]),
+    ("thead", &[]),
+    ("tbody", &[]),
+    ("tfoot", &[]),
+    ("tr", &[]),
+    ("th", &["colspan", "rowspan", "headers", "scope", "abbr"]),
+    ("td", &["colspan", "rowspan", "headers"]),
+];
+
+const DEFAULT_GLOBAL_ATTRIBUTES: &[&str] = &["id", "class", "title", "lang", "dir", "style"];
+
+/// Elements removed along with their content, which is never meant to be shown as text.
+const DEFAULT_DROP_CONTENT: &[&str] = &[
+    "script", "style", "template", "iframe", "frame", "frameset", "object", "embed", "applet",
+    "noscript", "noembed", "noframes", "textarea", "select", "button",
+];
+
+/// Attributes holding a single URL.
+const URL_ATTRIBUTES: &[&str] = &[
+    "href",
+    "src",
+    "cite",
+    "poster",
+    "action",
+    "formaction",
+    "background",
+    "longdesc",
+];
+
+const DEFAULT_URL_SCHEMES: &[&str] = &["http", "https", "mailto", "tel"];
+
+const DEFAULT_STYLE_PROPERTIES: &[&str] = &[
+    "color",
+    "background-color",
+    "font-style",
+    "font-weight",
+    "text-align",
+    "text-decoration",
+    "text-transform",
+    "vertical-align",
+    "white-space",
+    "width",
+    "height",
+    "list-style-type",
+];
+
+/// Fragments of style values that can load resources or run script.
+const UNSAFE_STYLE_VALUES: &[&str] = &[
+    "url(",
+    "image(",
+    "image-set(",
+    "expression(",
+    "javascript:",
+    "vbscript:",
+    "@import",
+    "behavior",
+    "-moz-binding",
+    "\\",
+    "<",
+];
+
+/// The markup kept by a [`SanitizerVisitor`]. Anything not allowed is removed.
+#[derive(Debug, Clone)]
+pub struct SanitizerConfig {
+    /// Allowed elements, with the attributes each may have besides the global ones.
+    /// Other elements are unwrapped, keeping their content.
+    pub tags: HashMap<String, HashSet<String>>,
+    /// Attributes allowed on every allowed element.
+    pub global_attributes: HashSet<String>,
+    /// Allow `data-*` attributes on every allowed element.
+    pub allow_data_attributes: bool,
+    /// Elements removed together with their content.
+    pub drop_content: HashSet<String>,
+    /// Schemes allowed in URL attributes. Relative URLs are always allowed.
+    pub url_schemes: HashSet<String>,
+    /// CSS properties kept in `style` attributes.
+    pub style_properties: HashSet<String>,
+}
+
+impl Default for SanitizerConfig {
+    fn default() -> Self {
+        let strings = |values: &[&str]| values.iter().map(|value| value.to_string()).collect();
+        SanitizerConfig {
+            tags: DEFAULT_TAGS
+                .iter()
+                .map(|(tag, attrs)| (tag.to_string(), strings(attrs)))
+                .collect(),
+            global_attributes: strings(DEFAULT_GLOBAL_ATTRIBUTES),
+            allow_data_attributes: true,
+            drop_content: strings(DEFAULT_DROP_CONTENT),
+            url_schemes: strings(DEFAULT_URL_SCHEMES),
+            style_properties: strings(DEFAULT_STYLE_PROPERTIES),
+        }
+    }
+}
+
+impl SanitizerConfig {
+    /// Allows the elements of rich-text blocks marked up as `markup`, with their id and
+    /// extra attributes. The default markup is allowed already.
+    pub fn allow_rich_text(&mut self, markup: &RichTextMarkup) {
+        let attrs = self.tags.entry(markup.tag.clone()).or_default();
+        attrs.insert(markup.id_attribute.clone());
+        attrs.extend(markup.attributes.iter().map(|(name, _)| name.clone()));
+    }
+
+    fn allows_attribute(&self, tag: &str, attr: &str) -> bool {
+        self.global_attributes.contains(attr)
+            || self.tags.get(tag).is_some_and(|attrs| attrs.contains(attr))
+            || (self.allow_data_attributes && attr.starts_with("data-"))
+    }
+
+    /// Whether a URL is relative or uses an allowed scheme.
+    fn allows_url(&self, url: &str) -> bool {
+        // Browsers ignore tabs and line breaks anywhere in a URL, and surrounding
+        // control characters and spaces
+        let url: String = url
+            .chars()
+            .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+            .collect();
+        let url = url.trim_matches(|c: char| c <= ' ');
+        let Some((scheme, _)) = url.split_once(':') else {
+            return true;
+        };
+        let is_scheme = scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+        !is_scheme || self.url_schemes.contains(&scheme.to_ascii_lowercase())
+    }
+
+    fn allows_srcset(&self, srcset: &str) -> bool {
+        srcset.split(',').all(|candidate| {
+            candidate
+                .split_whitespace()
+                .next()
+                .is_none_or(|url| self.allows_url(url))
+        })
+    }
+
+    /// Keeps the declarations of allowed properties with safe values.
+    fn filter_style(&self, style: &str) -> String {
+        style
+            .split(';')
+            .filter_map(|declaration| {
+                let (property, value) = declaration.split_once(':')?;
+                let property = property.trim().to_ascii_lowercase();
+                let value = value.trim();
+                let lowercase = value.to_ascii_lowercase();
+                let is_safe = !value.is_empty()
+                    && !UNSAFE_STYLE_VALUES
+                        .iter()
+                        .any(|unsafe_value| lowercase.contains(unsafe_value));
+                (self.style_properties.contains(&property) && is_safe)
+                    .then(|| format!("{}: {}", property, value))
+            })
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+/// A visitor that removes the markup a [`SanitizerConfig`] does not allow: scripts,
+/// event handlers, unsafe URLs and styles, comments (including CDATA sections, which HTML
+/// parses as comments) and processing instructions.
+pub struct SanitizerVisitor<'a> {
+    config: &'a SanitizerConfig,
+    removed: usize,
+}
+
+impl<'a> SanitizerVisitor<'a> {
+    pub fn new(config: &'a SanitizerConfig) -> Self {
+        SanitizerVisitor { config, removed: 0 }
+    }
+
+    /// The number of nodes and attributes removed so far.
+    pub fn removed(&self) -> usize {
+        self.removed
+    }
+
+    fn sanitize_attributes(&mut self, tag: &str, attrs: &RefCell<Vec<Attribute>>) {
+        let config = self.config;
+        let mut attrs = attrs.borrow_mut();
+        let count = attrs.len();
+        attrs.retain_mut(|attr| {
+            let name = attr.name.local.as_ref();
+            if attr.name.ns != ns!() || !config.allows_attribute(tag, name) {
+                log::debug!("Removing attribute {} from <{}>", name, tag);
+                return false;
+            }
+            if URL_ATTRIBUTES.contains(&name) && !config.allows_url(&attr.value) {
+                log::debug!("Removing unsafe URL {:?} from <{}>", attr.value, tag);
+                return false;
+            }
+            if name == "srcset" && !config.allows_srcset(&attr.value) {
+                log::debug!("Removing unsafe srcset {:?} from <{}>", attr.value, tag);
+                return false;
+            }
+            if name == "style" {
+                let style = config.filter_style(&attr.value);
+                if style.is_empty() {
+                    return false;
+                }
+                attr.value = style.into();
+            }
+            true
+        });
+        self.removed += count - attrs.len();
+    }
+
+    /// Removes disallowed children of `parent`, and replaces disallowed elements by their
+    /// own children.
+    fn sanitize_children(&mut self, parent: &Handle) {
+        let mut pending: VecDeque<Handle> =
+            std::mem::take(&mut *parent.children.borrow_mut()).into();
+        let mut children = Vec::with_capacity(pending.len());
+
+        while let Some(child) = pending.pop_front() {
+            match child.data {
+                NodeData::Text { .. } => children.push(child),
+                NodeData::Doctype { .. } if matches!(parent.data, NodeData::Document) => {
+                    children.push(child)
+                }
+                NodeData::Element { ref name, .. } => {
+                    let tag = name.local.as_ref();
+                    if self.config.drop_content.contains(tag) {
+                        log::debug!("Removing <{}> and its content", tag);
+                        self.removed += 1;
+                    } else if self.config.tags.contains_key(tag) {
+                        children.push(child);
+                    } else {
+                        log::debug!("Unwrapping <{}>", tag);
+                        self.removed += 1;
+                        let grandchildren = std::mem::take(&mut *child.children.borrow_mut());
+                        for grandchild in grandchildren.into_iter().rev() {
+                            pending.push_front(grandchild);
+                        }
+                    }
+                }
+                _ => {
+                    log::debug!("Removing {:?}", child);
+                    self.removed += 1;
+                }
+            }
+        }
+
+        for child in children.iter() {
+            child.parent.set(Some(WeakHandle::from(parent)));
+        }
+        *parent.children.borrow_mut() = children;
+    }
+}
+
+impl NodeVisitor for SanitizerVisitor<'_> {
+    fn visit_document(&mut self, handle: &Handle) -> (Option<Handle>, bool) {
+        self.sanitize_children(handle);
+        (None, true)
+    }
+
+    fn visit_element(
+        &mut self,
+        name: &QualName,
+        attrs: &RefCell<Vec<Attribute>>,
+        _template_contents: &RefCell<Option<Handle>>,
+        _mathml_annotation_xml_integration_point: bool,
+        handle: &Handle,
+    ) -> (Option<Handle>, bool) {
+        self.sanitize_attributes(name.local.as_ref(), attrs);
+        self.sanitize_children(handle);
+        (None, true)
+    }
+
+    // Script and event handlers inside SVG are as live as anywhere else.
+    fn visits_foreign_content(&self) -> bool {
+        true
+    }
+}
+
+/// Sanitizes an HTML fragment, such as the content of a rich-text block.
+pub fn sanitize_html(html: &str, config: &SanitizerConfig) -> String {
+    let dom = RcDom::from_fragment_str(html);
+    let mut visitor = SanitizerVisitor::new(config);
+    let (root, _) = visitor.traverse(dom.fragment_root());
+    if visitor.removed() > 0 {
+        log::info!("Removed {} unsafe nodes and attributes", visitor.removed());
+    }
+    root.inner_html()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sanitize(html: &str) -> String {
+        sanitize_html(html, &SanitizerConfig::default())
+    }
+
+    #[test]
+    fn test_removes_scripts_and_handlers() {
+        assert_eq!(
+            sanitize(
+                r#"<p onclick="steal()">Hi<script>alert(1)</script> <a href=" java&#9;script:alert(1)" target="_blank">x</a> <a href="/ok">y</a></p>"#
+            ),
+            r#"<p>Hi <a target="_blank">x</a> <a href="/ok">y</a></p>"#
+        );
+        assert_eq!(
+            sanitize(
+                r#"<img src="data:image/svg+xml,x" srcset="a.png 1x, javascript:x 2x" alt="A">"#
+            ),
+            r#"<img alt="A">"#
+        );
+    }
+
+    #[test]
+    fn test_strips_comments_cdata_and_unknown_elements() {
+        assert_eq!(
+            sanitize("a<!-- hidden --><![CDATA[hidden]]><font color=red><b data-ref=\"x\">b</b></font><svg><style>*{}</style><desc>c</desc></svg>"),
+            "a<b data-ref=\"x\">b</b>c"
+        );
+    }
+
+    #[test]
+    fn test_filters_styles() {
+        assert_eq!(
+            sanitize(
+                r#"<span style="color: red; position: fixed; background-color: url(x); FONT-WEIGHT:bold">x</span><em style="position: absolute">y</em>"#
+            ),
+            r#"<span style="color: red; font-weight: bold">x</span><em>y</em>"#
+        );
+    }
+}
@@ -0,0 +1,381 @@
+use super::NodeVisitor;
+use crate::{
+    rc_dom::{builder::attribute, Handle, NodeData},
+    util::fnv1a,
+};
+use html5ever::{Attribute, QualName};
+use markup5ever::{namespace_url, ns};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+};
+
+/// Properties removed by default: fonts, which the site stylesheet sets, and the `mso-*`
+/// properties Word writes for its own round trips.
+const DEFAULT_DENYLIST: &[&str] = &["font", "font-family", "font-size", "mso-*"];
+
+const DEFAULT_CLASS_PREFIX: &str = "u-";
+
+/// How a [`StyleClassVisitor`] cleans up inline styles.
+#[derive(Debug, Clone)]
+pub struct StyleClassOptions {
+    /// Properties removed from every `style` attribute. A trailing `*` matches any suffix,
+    /// as in `mso-*`.
+    pub denylist: Vec<String>,
+    /// The prefix of the generated class names.
+    pub class_prefix: String,
+    /// How many elements of a document must share a declaration for it to become a class.
+    /// Rarer declarations stay inline.
+    pub min_occurrences: usize,
+}
+
+impl Default for StyleClassOptions {
+    fn default() -> Self {
+        StyleClassOptions {
+            denylist: DEFAULT_DENYLIST.iter().map(|p| p.to_string()).collect(),
+            class_prefix: DEFAULT_CLASS_PREFIX.to_string(),
+            min_occurrences: 2,
+        }
+    }
+}
+
+impl StyleClassOptions {
+    fn denies(&self, property: &str) -> bool {
+        self.denylist
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => property.starts_with(prefix),
+                None => property == pattern,
+            })
+    }
+
+    /// The class of a declaration. Values made of letters, digits and hyphens are spelled
+    /// out, as in `u-text-align-center`; others are hashed, so the same declaration gets the
+    /// same class in every document.
+    fn class_name(&self, (property, value): &Declaration) -> String {
+        let is_plain = value
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if is_plain {
+            format!("{}{}-{}", self.class_prefix, property, value)
+        } else {
+            let hash = fnv1a(format!("{}: {}", property, value).as_bytes()) as u32;
+            format!("{}{}-{:08x}", self.class_prefix, property, hash)
+        }
+    }
+}
+
+/// A property, lowercased, and its value, with whitespace collapsed.
+pub type Declaration = (String, String);
+
+/// Parses the declarations of a `style` attribute, skipping comments and malformed ones.
+/// Semicolons inside quotes and parentheses, as in `url("a;b")`, do not end a declaration.
+pub fn parse_style(style: &str) -> Vec<Declaration> {
+    let mut declarations = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+    let mut depth = 0usize;
+    let mut chars = style.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth = depth.saturating_sub(1),
+            (None, '/') if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+                current.push(' ');
+                continue;
+            }
+            (None, ';') if depth == 0 => {
+                declarations.extend(parse_declaration(&current));
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    declarations.extend(parse_declaration(&current));
+    declarations
+}
+
+fn parse_declaration(declaration: &str) -> Option<Declaration> {
+    let (property, value) = declaration.split_once(':')?;
+    let property = property.trim().to_ascii_lowercase();
+    let value = value.split_whitespace().collect::<Vec<_>>().join(" ");
+    (!property.is_empty() && !value.is_empty()).then_some((property, value))
+}
+
+/// Whether two properties set the same thing, as a shorthand and one of its longhands do:
+/// `margin` and `margin-left`, or `border-color` and `border-top-color`.
+fn overlaps(a: &str, b: &str) -> bool {
+    let is_longhand = |long: &str, short: &str| {
+        long.strip_prefix(short)
+            .is_some_and(|rest| rest.starts_with('-'))
+    };
+    let is_border = |property: &str| property == "border" || property.starts_with("border-");
+    is_longhand(a, b) || is_longhand(b, a) || (is_border(a) && is_border(b))
+}
+
+fn format_style(declarations: &[&Declaration]) -> String {
+    declarations
+        .iter()
+        .map(|(property, value)| format!("{}: {}", property, value))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// A visitor that cleans up inline styles, as exported by Word and most CMSs.
+///
+/// While traversing, it removes the declarations on the
+/// [`denylist`](StyleClassOptions::denylist). [`StyleClassVisitor::extract_classes`] then
+/// moves the styles made only of declarations shared by enough elements into utility
+/// classes, one per declaration, whose rules [`StyleClassVisitor::stylesheet`] writes.
+///
+/// An element keeps its whole style inline unless all of it can be hoisted, so inline
+/// declarations never override its classes. Nor is a style holding both a shorthand and
+/// one of its longhands hoisted, since the order of their rules would decide which wins.
+pub struct StyleClassVisitor {
+    options: StyleClassOptions,
+    /// The styled elements, with their remaining declarations.
+    styled: Vec<(Handle, Vec<Declaration>)>,
+    /// The declaration of each class extracted so far.
+    classes: BTreeMap<String, Declaration>,
+    removed: usize,
+}
+
+impl StyleClassVisitor {
+    pub fn new() -> Self {
+        StyleClassVisitor::with_options(StyleClassOptions::default())
+    }
+
+    pub fn with_options(options: StyleClassOptions) -> Self {
+        StyleClassVisitor {
+            options,
+            styled: Vec::new(),
+            classes: BTreeMap::new(),
+            removed: 0,
+        }
+    }
+
+    /// The number of denied declarations removed so far.
+    pub fn removed(&self) -> usize {
+        self.removed
+    }
+
+    /// The classes extracted so far, with their declarations.
+    pub fn classes(&self) -> &BTreeMap<String, Declaration> {
+        &self.classes
+    }
+
+    /// Replaces the styles found during traversal whose every declaration is shared by at
+    /// least [`min_occurrences`](StyleClassOptions::min_occurrences) such styles by
+    /// classes, removing their `style` attributes. Returns the number of classes added.
+    pub fn extract_classes(&mut self) -> usize {
+        let mut hoisted: Vec<&(Handle, Vec<Declaration>)> =
+            self.styled
+                .iter()
+                .filter(|(_, declarations)| {
+                    !declarations.iter().enumerate().any(|(i, (a, _))| {
+                        declarations[i + 1..].iter().any(|(b, _)| overlaps(a, b))
+                    })
+                })
+                .collect();
+        // Leaving a style inline can make its declarations too rare for other styles
+        loop {
+            let mut counts: HashMap<&Declaration, usize> = HashMap::new();
+            for (_, declarations) in hoisted.iter() {
+                for declaration in declarations {
+                    *counts.entry(declaration).or_default() += 1;
+                }
+            }
+            let before = hoisted.len();
+            hoisted.retain(|(_, declarations)| {
+                declarations
+                    .iter()
+                    .all(|declaration| counts[declaration] >= self.options.min_occurrences)
+            });
+            if hoisted.len() == before {
+                break;
+            }
+        }
+
+        let before = self.classes.len();
+        for (handle, declarations) in hoisted {
+            let classes: Vec<String> = declarations
+                .iter()
+                .map(|declaration| {
+                    let class = self.options.class_name(declaration);
+                    self.classes.insert(class.clone(), declaration.clone());
+                    class
+                })
+                .collect();
+            set_style(handle, "", &classes);
+        }
+        self.styled.clear();
+        self.classes.len() - before
+    }
+
+    /// The rules of the extracted classes, in order of class name. No element has two
+    /// classes setting the same property, so the order does not change the result.
+    pub fn stylesheet(&self) -> String {
+        to_stylesheet(&self.classes)
+    }
+}
+
+impl Default for StyleClassVisitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NodeVisitor for StyleClassVisitor {
+    fn visit_element(
+        &mut self,
+        _name: &QualName,
+        attrs: &RefCell<Vec<Attribute>>,
+        _template_contents: &RefCell<Option<Handle>>,
+        _mathml_annotation_xml_integration_point: bool,
+        handle: &Handle,
+    ) -> (Option<Handle>, bool) {
+        let style = attrs
+            .borrow()
+            .iter()
+            .find(|attr| attr.name.ns == ns!() && attr.name.local.as_ref() == "style")
+            .map(|attr| attr.value.to_string());
+        if let Some(style) = style {
+            let mut declarations = parse_style(&style);
+            let count = declarations.len();
+            declarations.retain(|(property, _)| !self.options.denies(property));
+            self.removed += count - declarations.len();
+            // A later declaration of a property overrides earlier ones
+            let mut seen = Vec::new();
+            for (property, value) in declarations.into_iter().rev() {
+                if !seen.iter().any(|(seen, _): &Declaration| *seen == property) {
+                    seen.push((property, value));
+                }
+            }
+            seen.reverse();
+            set_style(handle, &format_style(&seen.iter().collect::<Vec<_>>()), &[]);
+            if !seen.is_empty() {
+                self.styled.push((handle.clone(), seen));
+            }
+        }
+        (None, true)
+    }
+}
+
+/// Writes the `style` attribute of an element, removing it if empty, and appends classes
+/// to its `class` attribute.
+fn set_style(handle: &Handle, style: &str, classes: &[String]) {
+    let NodeData::Element { ref attrs, .. } = handle.data else {
+        return;
+    };
+    let mut attrs = attrs.borrow_mut();
+    let is_named =
+        |attr: &Attribute, name: &str| attr.name.ns == ns!() && attr.name.local.as_ref() == name;
+    if style.is_empty() {
+        attrs.retain(|attr| !is_named(attr, "style"));
+    } else if let Some(attr) = attrs.iter_mut().find(|attr| is_named(attr, "style")) {
+        attr.value = style.into();
+    }
+    if classes.is_empty() {
+        return;
+    }
+    match attrs.iter_mut().find(|attr| is_named(attr, "class")) {
+        Some(attr) => {
+            let mut names: Vec<&str> = attr.value.split_whitespace().collect();
+            for class in classes {
+                if !names.contains(&class.as_str()) {
+                    names.push(class);
+                }
+            }
+            attr.value = names.join(" ").into();
+        }
+        None => attrs.push(attribute("class", &classes.join(" "))),
+    }
+}
+
+/// The rules of utility classes, one per line.
+pub fn to_stylesheet(classes: &BTreeMap<String, Declaration>) -> String {
+    classes
+        .iter()
+        .map(|(class, (property, value))| format!(".{} {{ {}: {}; }}\n", class, property, value))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rc_dom::RcDom;
+
+    fn extract(html: &str) -> (String, StyleClassVisitor) {
+        let dom = RcDom::from_fragment_str(html);
+        let mut visitor = StyleClassVisitor::new();
+        let (root, _) = visitor.traverse(dom.fragment_root());
+        visitor.extract_classes();
+        (root.inner_html(), visitor)
+    }
+
+    #[test]
+    fn test_parses_declarations() {
+        assert_eq!(
+            parse_style(
+                "COLOR : Red;;background: url(\"a;b.png\") /* ; */ no-repeat; bogus; margin:"
+            ),
+            [
+                ("color".to_string(), "Red".to_string()),
+                (
+                    "background".to_string(),
+                    "url(\"a;b.png\") no-repeat".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hoists_repeated_declarations() {
+        let (html, visitor) = extract(
+            r#"<p class="MsoNormal" style="mso-margin-top-alt:auto; text-align:center;font-family:Calibri">a</p><p style="text-align: center; color: #1F497D">b</p><p style="color:#1F497D;font-size:11pt">c</p><span style="font-size:11pt">d</span>"#,
+        );
+        assert_eq!(
+            html,
+            r#"<p class="MsoNormal u-text-align-center">a</p><p class="u-text-align-center u-color-08603204">b</p><p class="u-color-08603204">c</p><span>d</span>"#
+        );
+        assert_eq!(visitor.removed(), 4);
+        assert_eq!(
+            visitor.stylesheet(),
+            ".u-color-08603204 { color: #1F497D; }\n.u-text-align-center { text-align: center; }\n"
+        );
+    }
+
+    #[test]
+    fn test_keeps_partly_shared_and_overlapping_styles_inline() {
+        let (html, visitor) = extract(
+            r#"<p style="color:red; width:50%">a</p><p style="color:red">b</p><p style="margin:0;margin-left:4px">c</p><p style="margin:0;margin-left:4px">d</p><p style="border-color:red;border-top-color:blue">e</p><p style="border-color:red;border-top-color:blue">f</p>"#,
+        );
+        assert_eq!(
+            html,
+            r#"<p style="color: red; width: 50%">a</p><p style="color: red">b</p><p style="margin: 0; margin-left: 4px">c</p><p style="margin: 0; margin-left: 4px">d</p><p style="border-color: red; border-top-color: blue">e</p><p style="border-color: red; border-top-color: blue">f</p>"#
+        );
+        assert!(visitor.classes().is_empty());
+    }
+
+    #[test]
+    fn test_overlapping_properties() {
+        assert!(overlaps("margin", "margin-left"));
+        assert!(overlaps("font-size", "font"));
+        assert!(overlaps("border-color", "border-top-color"));
+        assert!(!overlaps("margin", "marginal"));
+        assert!(!overlaps("text-align", "text-indent"));
+    }
+}
@@ -0,0 +1,150 @@
+use super::NodeVisitor;
+use crate::{
+    rc_dom::{Handle, RcDom, WeakHandle},
+    rich_text::RichTextMarkup,
+    translation::Translations,
+};
+use html5ever::{Attribute, QualName};
+use std::cell::RefCell;
+use uuid::Uuid;
+
+/// A visitor that replaces the content of each rich-text element with its translation,
+/// producing the page for one locale.
+///
+/// Rich-text elements without a translation keep their source content.
+pub struct TranslationVisitor<'a> {
+    translations: &'a Translations,
+    translated: usize,
+    untranslated: Vec<Uuid>,
+    markup: RichTextMarkup,
+}
+
+impl<'a> TranslationVisitor<'a> {
+    pub fn new(translations: &'a Translations) -> Self {
+        TranslationVisitor {
+            translations,
+            translated: 0,
+            untranslated: Vec::new(),
+            markup: RichTextMarkup::default(),
+        }
+    }
+
+    /// Sets the element and id attribute of the rich-text blocks to translate.
+    pub fn with_rich_text_markup(mut self, markup: RichTextMarkup) -> Self {
+        self.markup = markup;
+        self
+    }
+
+    /// The number of rich-text elements whose content was replaced.
+    pub fn translated(&self) -> usize {
+        self.translated
+    }
+
+    /// Rich-text elements left in the source language.
+    pub fn untranslated(&self) -> &[Uuid] {
+        &self.untranslated
+    }
+}
+
+impl<'a> NodeVisitor for TranslationVisitor<'a> {
+    fn visit_element(
+        &mut self,
+        name: &QualName,
+        attrs: &RefCell<Vec<Attribute>>,
+        _template_contents: &RefCell<Option<Handle>>,
+        _mathml_annotation_xml_integration_point: bool,
+        handle: &Handle,
+    ) -> (Option<Handle>, bool) {
+        if !self.markup.is_rich_text(name) {
+            return (None, true);
+        }
+
+        let Some(id) = self.markup.uuid(&attrs.borrow()) else {
+            return (None, false);
+        };
+
+        match self.translations.units.get(&id) {
+            Some(html) => {
+                let fragment = RcDom::from_fragment_str(html);
+                let children = fragment.fragment_root().children.take();
+                for child in children.iter() {
+                    child.parent.set(Some(WeakHandle::from(handle)));
+                }
+                *handle.children.borrow_mut() = children;
+                self.translated += 1;
+            }
+            None => {
+                log::debug!("No {} translation for {}", self.translations.locale, id);
+                self.untranslated.push(id);
+            }
+        }
+        (None, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const TRANSLATED: &str = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+    const UNTRANSLATED: &str = "1b4e28ba-2fa1-11d2-883f-0016d3cca427";
+
+    fn translations() -> Translations {
+        Translations {
+            locale: "fr".to_string(),
+            units: HashMap::from([(
+                Uuid::parse_str(TRANSLATED).unwrap(),
+                "Prendre <b>10&nbsp;mg</b> &amp; boire".to_string(),
+            )]),
+        }
+    }
+
+    #[test]
+    fn test_applies_translations() {
+        let dom = RcDom::from_fragment_str(&format!(
+            r#"<rich-text id="{}">Take <b>10 mg</b> &amp; drink</rich-text><rich-text id="{}">Rest</rich-text><rich-text>Other</rich-text>"#,
+            TRANSLATED, UNTRANSLATED
+        ));
+        let translations = translations();
+        let mut visitor = TranslationVisitor::new(&translations);
+        let (root, _) = visitor.traverse(dom.fragment_root());
+        assert_eq!(
+            root.inner_html(),
+            format!(
+                r#"<rich-text id="{}">Prendre <b>10&nbsp;mg</b> &amp; boire</rich-text><rich-text id="{}">Rest</rich-text><rich-text>Other</rich-text>"#,
+                TRANSLATED, UNTRANSLATED
+            )
+        );
+        assert_eq!(visitor.translated(), 1);
+        assert_eq!(
+            visitor.untranslated(),
+            [Uuid::parse_str(UNTRANSLATED).unwrap()]
+        );
+
+        let rich_text = root.children.borrow()[0].clone();
+        let bold = rich_text.children.borrow()[1].clone();
+        let parent = bold.parent.take().and_then(|parent| parent.upgrade());
+        assert!(parent.is_some_and(|parent| std::ptr::eq(&*parent, &*rich_text)));
+    }
+
+    #[test]
+    fn test_applies_translations_with_markup() {
+        let dom = RcDom::from_fragment_str(&format!(
+            r#"<rt-block data-rt-id="{0}">Take</rt-block><rich-text id="{0}">Take</rich-text>"#,
+            TRANSLATED
+        ));
+        let translations = translations();
+        let mut visitor = TranslationVisitor::new(&translations)
+            .with_rich_text_markup(RichTextMarkup::new("rt-block", "data-rt-id"));
+        let (root, _) = visitor.traverse(dom.fragment_root());
+        assert_eq!(
+            root.inner_html(),
+            format!(
+                r#"<rt-block data-rt-id="{0}">Prendre <b>10&nbsp;mg</b> &amp; boire</rt-block><rich-text id="{0}">Take</rich-text>"#,
+                TRANSLATED
+            )
+        );
+        assert_eq!(visitor.translated(), 1);
+    }
+}
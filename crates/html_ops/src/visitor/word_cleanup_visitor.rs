@@ -0,0 +1,674 @@
+use super::{style_class_visitor::parse_style, NodeVisitor};
+use crate::rc_dom::{
+    builder::{element, set_children, ElementBuilder},
+    Handle, NodeData, RcDom,
+};
+use html5ever::{Attribute, QualName};
+use std::{cell::RefCell, collections::VecDeque};
+use tendril::StrTendril;
+
+/// Inline elements merged with an identical element next to them. Only presentational
+/// ones are: two `<sup>` footnote markers or two `<code>` spans stay apart.
+const MERGED_INLINE: &[&str] = &["b", "strong", "i", "em", "u", "s", "span", "font"];
+
+/// Elements a paragraph can hold and still be blank, when they hold nothing but whitespace.
+const BLANK_ELEMENTS: &[&str] = &[
+    "span", "b", "strong", "i", "em", "u", "s", "strike", "small", "font", "a", "br",
+];
+
+/// Elements whose leading and trailing `&nbsp;` padding is removed.
+const PADDED_BLOCKS: &[&str] = &[
+    "p", "li", "h1", "h2", "h3", "h4", "h5", "h6", "td", "th", "dt", "dd", "caption",
+];
+
+/// Office-namespaced elements removed with their content: VML drawings, which Word follows
+/// with an `<img>` fallback, and Word's own settings.
+const DROPPED_PREFIXES: &[&str] = &["v:", "w:"];
+
+/// A visitor that cleans up the HTML Word writes with "Save as HTML", to run before
+/// extracting rich text.
+///
+/// It removes conditional comments, `<xml>` data islands and Office-namespaced elements and
+/// attributes, unwrapping those that hold text such as `<o:p>` and smart tags. It drops
+/// `Mso*` classes and `mso-*` style declarations, unwraps spans left without attributes,
+/// removes blank paragraphs and `&nbsp;` padding, rebuilds lists from `MsoListParagraph`
+/// paragraphs, and merges adjacent identical inline elements.
+///
+/// Spans also lose their `lang` attribute, which Word writes on nearly every run of text
+/// when the document language differs from the editor's.
+pub struct WordCleanupVisitor {
+    removed: usize,
+    lists: usize,
+}
+
+impl WordCleanupVisitor {
+    pub fn new() -> Self {
+        WordCleanupVisitor {
+            removed: 0,
+            lists: 0,
+        }
+    }
+
+    /// The number of nodes and attributes removed or unwrapped so far.
+    pub fn removed(&self) -> usize {
+        self.removed
+    }
+
+    /// The number of lists rebuilt from list paragraphs so far.
+    pub fn lists(&self) -> usize {
+        self.lists
+    }
+
+    /// Removes the Office attributes, classes and style declarations of an element.
+    fn clean_attributes(&mut self, tag: &str, attrs: &RefCell<Vec<Attribute>>) {
+        let mut attrs = attrs.borrow_mut();
+        let count = attrs.len();
+        attrs.retain_mut(|attr| {
+            let name = attr.name.local.as_ref();
+            if attr.name.prefix.is_some() || name.contains(':') || (tag == "span" && name == "lang")
+            {
+                return false;
+            }
+            match name {
+                "class" => {
+                    let classes: Vec<&str> = attr
+                        .value
+                        .split_whitespace()
+                        .filter(|class| !is_office_class(class))
+                        .collect();
+                    attr.value = classes.join(" ").into();
+                }
+                "style" => {
+                    let declarations: Vec<String> = parse_style(&attr.value)
+                        .into_iter()
+                        .filter(|(property, _)| !property.starts_with("mso-"))
+                        .map(|(property, value)| format!("{}: {}", property, value))
+                        .collect();
+                    attr.value = declarations.join("; ").into();
+                }
+                _ => return true,
+            }
+            !attr.value.is_empty()
+        });
+        self.removed += count - attrs.len();
+    }
+
+    /// Cleans the children of `parent`, replacing unwrapped elements by their own children
+    /// and runs of list paragraphs by lists.
+    fn clean_children(&mut self, parent: &Handle) {
+        let mut pending: VecDeque<Handle> =
+            std::mem::take(&mut *parent.children.borrow_mut()).into();
+        let mut children = Vec::with_capacity(pending.len());
+        let mut items: Vec<ListItem> = Vec::new();
+
+        while let Some(child) = pending.pop_front() {
+            let item = match child.data {
+                NodeData::Comment { ref contents } if is_conditional(contents) => {
+                    self.removed += 1;
+                    continue;
+                }
+                NodeData::Text { ref contents } if !items.is_empty() => {
+                    // Whitespace between list paragraphs would split the list
+                    if contents.borrow().trim().is_empty() {
+                        continue;
+                    }
+                    None
+                }
+                NodeData::Element {
+                    ref name,
+                    ref attrs,
+                    ..
+                } => {
+                    let tag = name.local.as_ref();
+                    if tag == "xml" || DROPPED_PREFIXES.iter().any(|p| tag.starts_with(p)) {
+                        self.removed += 1;
+                        continue;
+                    }
+                    if tag.contains(':') {
+                        self.removed += 1;
+                        unwrap_into(&child, &mut pending);
+                        continue;
+                    }
+                    let item = (tag == "p")
+                        .then(|| ListItem::parse(&attrs.borrow()))
+                        .flatten();
+                    self.clean_attributes(tag, attrs);
+                    if tag == "span" && attrs.borrow().is_empty() {
+                        self.removed += 1;
+                        unwrap_into(&child, &mut pending);
+                        continue;
+                    }
+                    if tag == "span" && child.children.borrow().is_empty() {
+                        self.removed += 1;
+                        continue;
+                    }
+                    if tag == "p" && is_blank(&child) {
+                        self.removed += 1;
+                        continue;
+                    }
+                    item
+                }
+                _ => None,
+            };
+            match item {
+                Some(item) => items.push(item.with_content(&child)),
+                None => {
+                    self.flush_list(&mut items, &mut children);
+                    children.push(child);
+                }
+            }
+        }
+        self.flush_list(&mut items, &mut children);
+
+        set_children(parent, merge_adjacent(children));
+    }
+
+    /// Builds the lists of the pending list items, nested by level, and adds them to
+    /// `children`.
+    fn flush_list(&mut self, items: &mut Vec<ListItem>, children: &mut Vec<Handle>) {
+        // The open lists, innermost last, with their level
+        let mut open: Vec<(u32, Handle)> = Vec::new();
+        let mut list_id = None;
+        for item in items.drain(..) {
+            if list_id.is_some_and(|id| id != item.list_id) && item.level <= 1 {
+                open.clear();
+            }
+            list_id = Some(item.list_id);
+            while open.last().is_some_and(|(level, _)| *level > item.level) {
+                open.pop();
+            }
+            if open.last().is_none_or(|(level, _)| *level < item.level) {
+                let list = item.marker.list().build();
+                match open.last() {
+                    Some((_, outer)) => append(&last_item(outer), list.clone()),
+                    None => {
+                        self.lists += 1;
+                        children.push(list.clone());
+                    }
+                }
+                open.push((item.level, list));
+            }
+            let (_, list) = open.last().expect("a list is open");
+            append(list, item.li);
+        }
+    }
+}
+
+impl Default for WordCleanupVisitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NodeVisitor for WordCleanupVisitor {
+    fn visit_document(&mut self, handle: &Handle) -> (Option<Handle>, bool) {
+        self.clean_children(handle);
+        (None, true)
+    }
+
+    fn visit_element(
+        &mut self,
+        name: &QualName,
+        attrs: &RefCell<Vec<Attribute>>,
+        _template_contents: &RefCell<Option<Handle>>,
+        _mathml_annotation_xml_integration_point: bool,
+        handle: &Handle,
+    ) -> (Option<Handle>, bool) {
+        // The parent has cleaned the attributes already, unless this is the root
+        self.clean_attributes(name.local.as_ref(), attrs);
+        self.clean_children(handle);
+        if PADDED_BLOCKS.contains(&name.local.as_ref()) {
+            trim_padding(handle);
+        }
+        (None, true)
+    }
+
+    fn visit_text(
+        &mut self,
+        contents: &RefCell<StrTendril>,
+        _handle: &Handle,
+    ) -> (Option<Handle>, bool) {
+        let collapsed = collapse_padding(&contents.borrow());
+        if let Some(collapsed) = collapsed {
+            *contents.borrow_mut() = collapsed.into();
+        }
+        (None, false)
+    }
+}
+
+/// Cleans up an HTML fragment saved from Word.
+pub fn clean_word_html(html: &str) -> String {
+    let dom = RcDom::from_fragment_str(html);
+    let mut visitor = WordCleanupVisitor::new();
+    let (root, _) = visitor.traverse(dom.fragment_root());
+    root.inner_html()
+}
+
+/// A paragraph Word wrote as a list item, as
+/// `<p class=MsoListParagraphCxSpFirst style='mso-list:l0 level1 lfo1'>`.
+struct ListItem {
+    /// The `l0` of `mso-list`, which tells lists apart.
+    list_id: String,
+    level: u32,
+    marker: Marker,
+    li: Handle,
+}
+
+impl ListItem {
+    fn parse(attrs: &[Attribute]) -> Option<ListItem> {
+        let attr = |name: &str| {
+            attrs
+                .iter()
+                .find(|attr| attr.name.local.as_ref() == name)
+                .map(|attr| attr.value.to_string())
+        };
+        let style = attr("style").unwrap_or_default();
+        let (_, mso_list) = parse_style(&style)
+            .into_iter()
+            .find(|(property, _)| property == "mso-list")?;
+        // `mso-list: none` marks a paragraph styled as an item that is not one
+        if mso_list.eq_ignore_ascii_case("none") {
+            return None;
+        }
+        let mut list_id = String::new();
+        let mut level = 1;
+        for token in mso_list.split_whitespace() {
+            if let Some(number) = token.strip_prefix("level") {
+                level = number.parse().unwrap_or(1);
+            } else if token.strip_prefix('l').is_some_and(|number| {
+                !number.is_empty() && number.bytes().all(|b| b.is_ascii_digit())
+            }) {
+                list_id = token.to_string();
+            }
+        }
+        let is_item = !list_id.is_empty()
+            || attr("class").is_some_and(|class| class.starts_with("MsoListParagraph"));
+        is_item.then(|| ListItem {
+            list_id,
+            level,
+            marker: Marker::Bullet,
+            li: element("li").build(),
+        })
+    }
+
+    /// Moves the content of the paragraph into the list item, without its marker.
+    fn with_content(mut self, paragraph: &Handle) -> Self {
+        if let Some(marker) = remove_marker(paragraph) {
+            self.marker = Marker::parse(&marker);
+        }
+        let attrs = match paragraph.data {
+            NodeData::Element { ref attrs, .. } => attrs.borrow().clone(),
+            _ => Vec::new(),
+        };
+        let children = std::mem::take(&mut *paragraph.children.borrow_mut());
+        // Indentation is the list's job now
+        self.li = element("li")
+            .attrs(
+                attrs
+                    .into_iter()
+                    .filter(|attr| !matches!(attr.name.local.as_ref(), "class" | "style")),
+            )
+            .child(children)
+            .build();
+        self
+    }
+}
+
+/// The marker Word wrote before the text of a list item.
+#[derive(Debug, PartialEq)]
+enum Marker {
+    Bullet,
+    /// An `<ol>` with its `type`, unless decimal, and `start`, unless 1.
+    Numbered(Option<char>, Option<u32>),
+}
+
+impl Marker {
+    fn parse(marker: &str) -> Marker {
+        let marker = marker.trim_matches(|c: char| c.is_whitespace() || c == '\u{a0}');
+        let Some(label) = marker
+            .strip_suffix('.')
+            .or_else(|| marker.strip_suffix(')'))
+            .map(|label| label.trim_start_matches('('))
+        else {
+            return Marker::Bullet;
+        };
+        let is_roman = |c: char| "ivxlcdm".contains(c.to_ascii_lowercase());
+        let mut chars = label.chars();
+        match (chars.next(), chars.next()) {
+            _ if !label.is_empty() && label.chars().all(|c| c.is_ascii_digit()) => {
+                let start = label.parse().ok().filter(|start| *start != 1);
+                Marker::Numbered(None, start)
+            }
+            (Some(c), None) if c.is_ascii_alphabetic() && !matches!(c, 'i' | 'I') => {
+                let kind = if c.is_ascii_lowercase() { 'a' } else { 'A' };
+                let start = (c.to_ascii_lowercase() as u32 - 'a' as u32 + 1).max(1);
+                Marker::Numbered(Some(kind), (start != 1).then_some(start))
+            }
+            (Some(c), _) if label.chars().all(is_roman) => {
+                let kind = if c.is_ascii_lowercase() { 'i' } else { 'I' };
+                Marker::Numbered(Some(kind), None)
+            }
+            _ => Marker::Bullet,
+        }
+    }
+
+    /// Starts the list this marker belongs to.
+    fn list(&self) -> ElementBuilder {
+        match self {
+            Marker::Bullet => element("ul"),
+            Marker::Numbered(kind, start) => element("ol")
+                .attr_opt("type", kind.map(String::from))
+                .attr_opt("start", start.map(|start| start.to_string())),
+        }
+    }
+}
+
+/// Removes the marker of a list paragraph, a span styled `mso-list: Ignore`, and returns
+/// its text.
+fn remove_marker(node: &Handle) -> Option<String> {
+    let mut children = node.children.borrow_mut();
+    for (index, child) in children.iter().enumerate() {
+        if let NodeData::Element { ref attrs, .. } = child.data {
+            let is_marker = attrs.borrow().iter().any(|attr| {
+                attr.name.local.as_ref() == "style"
+                    && parse_style(&attr.value).iter().any(|(property, value)| {
+                        property == "mso-list" && value.eq_ignore_ascii_case("ignore")
+                    })
+            });
+            if is_marker {
+                let marker = child.text_content();
+                children.remove(index);
+                return Some(marker);
+            }
+            if let Some(marker) = remove_marker(child) {
+                return Some(marker);
+            }
+        }
+    }
+    None
+}
+
+/// Whether a comment is a conditional comment, or one half of a downlevel-revealed one
+/// such as `<![if !supportLists]>`, which HTML parses as a comment.
+fn is_conditional(contents: &StrTendril) -> bool {
+    let contents = contents.trim_start().to_ascii_lowercase();
+    contents.starts_with("[if") || contents.starts_with("[endif")
+}
+
+fn is_office_class(class: &str) -> bool {
+    class.starts_with("Mso") || class.starts_with("WordSection")
+}
+
+/// Whether an element holds nothing but whitespace, `&nbsp;` and empty formatting.
+fn is_blank(node: &Handle) -> bool {
+    node.children.borrow().iter().all(|child| match child.data {
+        NodeData::Text { ref contents } => is_padding(&contents.borrow()),
+        NodeData::Element { ref name, .. } => {
+            let tag = name.local.as_ref();
+            (BLANK_ELEMENTS.contains(&tag) || tag.contains(':')) && is_blank(child)
+        }
+        NodeData::Comment { .. } => true,
+        _ => false,
+    })
+}
+
+fn is_padding(text: &str) -> bool {
+    text.chars().all(|c| c.is_whitespace() || c == '\u{a0}')
+}
+
+/// Replaces runs of spaces holding more than one `&nbsp;`, Word's way of padding text, by
+/// a single space. Returns `None` if there are none.
+fn collapse_padding(text: &str) -> Option<String> {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut run = String::new();
+    let mut changed = false;
+    let mut flush = |run: &mut String, collapsed: &mut String| {
+        if run.chars().filter(|c| *c == '\u{a0}').count() > 1 {
+            collapsed.push(' ');
+            changed = true;
+        } else {
+            collapsed.push_str(run);
+        }
+        run.clear();
+    };
+    for c in text.chars() {
+        if c == ' ' || c == '\u{a0}' {
+            run.push(c);
+        } else {
+            flush(&mut run, &mut collapsed);
+            collapsed.push(c);
+        }
+    }
+    flush(&mut run, &mut collapsed);
+    changed.then_some(collapsed)
+}
+
+/// Removes the whitespace and `&nbsp;` at the start and end of the text of a block.
+fn trim_padding(block: &Handle) {
+    let mut texts = Vec::new();
+    collect_texts(block, &mut texts);
+    let trim = |text: &Handle, trim_text: fn(&str) -> &str| -> bool {
+        let NodeData::Text { ref contents } = text.data else {
+            return false;
+        };
+        let trimmed = trim_text(&contents.borrow()).to_string();
+        let is_empty = trimmed.is_empty();
+        *contents.borrow_mut() = trimmed.into();
+        !is_empty
+    };
+    for text in texts.iter() {
+        if trim(text, |s| {
+            s.trim_start_matches(|c: char| c.is_whitespace() || c == '\u{a0}')
+        }) {
+            break;
+        }
+    }
+    for text in texts.iter().rev() {
+        if trim(text, |s| {
+            s.trim_end_matches(|c: char| c.is_whitespace() || c == '\u{a0}')
+        }) {
+            break;
+        }
+    }
+}
+
+/// The text nodes inside a block, in document order, stopping at line breaks and nested
+/// blocks, whose padding is their own.
+fn collect_texts(node: &Handle, texts: &mut Vec<Handle>) {
+    for child in node.children.borrow().iter() {
+        match child.data {
+            NodeData::Text { .. } => texts.push(child.clone()),
+            NodeData::Element { ref name, .. }
+                if !PADDED_BLOCKS.contains(&name.local.as_ref())
+                    && !matches!(name.local.as_ref(), "ul" | "ol" | "br") =>
+            {
+                collect_texts(child, texts)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Merges adjacent text nodes, and adjacent inline elements with the same name and
+/// attributes, such as the `<b>` runs Word writes for every change of language. Whitespace
+/// between two such elements moves inside the merged one.
+fn merge_adjacent(children: Vec<Handle>) -> Vec<Handle> {
+    let mut merged: Vec<Handle> = Vec::with_capacity(children.len());
+    for child in children {
+        if let Some(previous) = merged.last() {
+            if let (
+                NodeData::Text {
+                    contents: ref previous,
+                },
+                NodeData::Text { ref contents },
+            ) = (&previous.data, &child.data)
+            {
+                previous.borrow_mut().push_tendril(&contents.borrow());
+                continue;
+            }
+        }
+        if is_mergeable(&child) {
+            // The element before, with the whitespace since
+            let whitespace = merged
+                .iter()
+                .rev()
+                .take_while(|node| match node.data {
+                    NodeData::Text { ref contents } => contents.borrow().trim().is_empty(),
+                    _ => false,
+                })
+                .count();
+            let before = merged.len().checked_sub(whitespace + 1);
+            if let Some(index) = before.filter(|index| same_element(&merged[*index], &child)) {
+                let target = merged[index].clone();
+                let mut moved: Vec<Handle> = merged.drain(index + 1..).collect();
+                moved.append(&mut child.children.borrow_mut());
+                for node in moved {
+                    append(&target, node);
+                }
+                let children = std::mem::take(&mut *target.children.borrow_mut());
+                set_children(&target, merge_adjacent(children));
+                continue;
+            }
+        }
+        merged.push(child);
+    }
+    merged
+}
+
+fn is_mergeable(node: &Handle) -> bool {
+    matches!(node.data, NodeData::Element { ref name, .. } if MERGED_INLINE.contains(&name.local.as_ref()))
+}
+
+fn same_element(a: &Handle, b: &Handle) -> bool {
+    match (&a.data, &b.data) {
+        (
+            NodeData::Element {
+                name: a_name,
+                attrs: a_attrs,
+                ..
+            },
+            NodeData::Element {
+                name: b_name,
+                attrs: b_attrs,
+                ..
+            },
+        ) => {
+            let sorted = |attrs: &RefCell<Vec<Attribute>>| {
+                let mut attrs: Vec<(String, String)> = attrs
+                    .borrow()
+                    .iter()
+                    .map(|attr| (attr.name.local.to_string(), attr.value.to_string()))
+                    .collect();
+                attrs.sort();
+                attrs
+            };
+            a_name == b_name && sorted(a_attrs) == sorted(b_attrs)
+        }
+        _ => false,
+    }
+}
+
+/// Replaces an element by its children at the front of the pending nodes.
+fn unwrap_into(node: &Handle, pending: &mut VecDeque<Handle>) {
+    let children = std::mem::take(&mut *node.children.borrow_mut());
+    for child in children.into_iter().rev() {
+        pending.push_front(child);
+    }
+}
+
+fn append(parent: &Handle, child: Handle) {
+    let mut children = std::mem::take(&mut *parent.children.borrow_mut());
+    children.push(child);
+    set_children(parent, children);
+}
+
+/// The last item of a list, added if it has none, to hold a nested list.
+fn last_item(list: &Handle) -> Handle {
+    let last = list.children.borrow().last().cloned();
+    match last {
+        Some(item) => item,
+        None => {
+            let item = element("li").build();
+            append(list, item.clone());
+            item
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_removes_office_markup() {
+        assert_eq!(
+            clean_word_html(
+                "<!--[if gte mso 9]><xml><w:WordDocument></w:WordDocument></xml><![endif]-->\
+                 <div class=WordSection1><p class=MsoNormal style='mso-margin-top-alt:auto;text-align:center'>\
+                 <span lang=EN-GB style='mso-bidi-font-weight:bold'>Take&nbsp;&nbsp;&nbsp; <st1:place>two</st1:place>\
+                 </span><span lang=EN-GB><o:p></o:p></span> &nbsp;</p>\
+                 <p class=MsoNormal><o:p>&nbsp;</o:p></p>\
+                 <p class=MsoNormal><b><span style='color:red'>Keep</span></b><b> <span style='color:red'>cool</span></b></p></div>"
+            ),
+            r#"<div><p style="text-align: center">Take two</p><p><b><span style="color: red">Keep cool</span></b></p></div>"#
+        );
+    }
+
+    #[test]
+    fn test_merges_formatting_only() {
+        assert_eq!(
+            clean_word_html(
+                "<p><i>a</i><i>b</i> c<sup>1</sup><sup>2</sup> <code>x</code><code>y</code></p>"
+            ),
+            "<p><i>ab</i> c<sup>1</sup><sup>2</sup> <code>x</code><code>y</code></p>"
+        );
+    }
+
+    #[test]
+    fn test_rebuilds_lists() {
+        let item = |class: &str, level: u32, marker: &str, text: &str| {
+            format!(
+                "<p class=MsoListParagraph{} style='text-indent:-18.0pt;mso-list:l0 level{} lfo1'>\
+                 <![if !supportLists]><span style='font-family:Symbol'><span style='mso-list:Ignore'>{}\
+                 <span style='font:7.0pt \"Times New Roman\"'>&nbsp;&nbsp;&nbsp; </span></span></span><![endif]>{}<o:p></o:p></p>\n",
+                class, level, marker, text
+            )
+        };
+        let html = [
+            "<p class=MsoNormal>Doses:</p>\n".to_string(),
+            item("CxSpFirst", 1, "a.", "Morning"),
+            item("CxSpMiddle", 2, "·", "With food"),
+            item("CxSpMiddle", 2, "·", "With water"),
+            item("CxSpLast", 1, "b.", "Evening"),
+            "<p class=MsoNormal>Done.</p>".to_string(),
+        ]
+        .concat();
+        assert_eq!(
+            clean_word_html(&html),
+            "<p>Doses:</p>\n<ol type=\"a\"><li>Morning<ul><li>With food</li><li>With water</li></ul></li>\
+             <li>Evening</li></ol><p>Done.</p>"
+        );
+    }
+
+    #[test]
+    fn test_tells_lists_apart() {
+        assert_eq!(
+            clean_word_html(
+                "<p class=MsoListParagraph style='mso-list:l0 level1 lfo1'>One</p>\
+                 <p class=MsoListParagraph style='mso-list:l1 level1 lfo1'>Other</p>"
+            ),
+            "<ul><li>One</li></ul><ul><li>Other</li></ul>"
+        );
+    }
+
+    #[test]
+    fn test_keeps_paragraphs_without_list() {
+        assert_eq!(
+            clean_word_html(
+                "<p class=MsoListParagraph style='mso-list:l0 level1 lfo1'>One</p>\n\
+                 <p class=MsoListParagraph style='margin-left:36.0pt;mso-list:none'>Still one</p>"
+            ),
+            "<ul><li>One</li></ul><p style=\"margin-left: 36.0pt\">Still one</p>"
+        );
+    }
+}
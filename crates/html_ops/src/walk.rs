@@ -1,17 +1,50 @@
+use std::future::Future;
 use std::io;
-use std::path::Path;
+use std::num::NonZeroUsize;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use tokio::task::{self, JoinSet};
 use walkdir::WalkDir;
 
-pub fn process_html_files<P, F>(directory: P, mut operation: F) -> Result<(), io::Error>
+/// Calls `operation` with the path of every `.html` file under `directory`, and its path
+/// relative to `directory`, in file-name order.
+pub fn process_html_files<P, F>(directory: P, operation: F) -> Result<(), io::Error>
+where
+    P: AsRef<Path>,
+    F: FnMut(&Path, &Path) -> Result<(), io::Error>,
+{
+    process_files_with_extensions(directory, &["html"], operation)
+}
+
+/// Like [`process_html_files`], for `.md` and `.markdown` files.
+pub fn process_markdown_files<P, F>(directory: P, operation: F) -> Result<(), io::Error>
+where
+    P: AsRef<Path>,
+    F: FnMut(&Path, &Path) -> Result<(), io::Error>,
+{
+    process_files_with_extensions(directory, &["md", "markdown"], operation)
+}
+
+fn process_files_with_extensions<P, F>(
+    directory: P,
+    extensions: &[&str],
+    mut operation: F,
+) -> Result<(), io::Error>
 where
     P: AsRef<Path>,
     F: FnMut(&Path, &Path) -> Result<(), io::Error>,
 {
     let base_path = directory.as_ref().to_path_buf();
-    for entry in WalkDir::new(&base_path).into_iter() {
+    for entry in WalkDir::new(&base_path).sort_by_file_name() {
         let entry = entry.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
         let path = entry.path();
-        if path.is_file() && path.extension().map_or(false, |ext| ext == "html") {
+        let has_extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map_or(false, |ext| extensions.contains(&ext));
+        if path.is_file() && has_extension {
             let relative_path = path
                 .strip_prefix(&base_path)
                 .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
@@ -21,23 +54,210 @@ where
     Ok(())
 }
 
+/// Like [`process_html_files`], with the files fanned out over one worker thread per
+/// available core. Documents parsed inside `operation` stay on their worker thread; use
+/// [`RcDom::freeze`](crate::rc_dom::RcDom::freeze) to hand one to another thread.
+///
+/// After the first error no further files are started, and that error is returned.
+pub fn process_html_files_parallel<P, F>(directory: P, operation: F) -> Result<(), io::Error>
+where
+    P: AsRef<Path>,
+    F: Fn(&Path, &Path) -> Result<(), io::Error> + Sync,
+{
+    let files = html_files(directory)?;
+    let workers = thread::available_parallelism()
+        .map_or(1, NonZeroUsize::get)
+        .min(files.len());
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let worker = || -> Result<(), io::Error> {
+        while !failed.load(Ordering::Relaxed) {
+            let Some((path, relative_path)) = files.get(next.fetch_add(1, Ordering::Relaxed))
+            else {
+                break;
+            };
+            if let Err(error) = operation(path, relative_path) {
+                failed.store(true, Ordering::Relaxed);
+                return Err(error);
+            }
+        }
+        Ok(())
+    };
+
+    thread::scope(|scope| {
+        let handles: Vec<_> = (0..workers).map(|_| scope.spawn(worker)).collect();
+        handles.into_iter().try_for_each(|handle| {
+            handle
+                .join()
+                .unwrap_or_else(|panic| panic::resume_unwind(panic))
+        })
+    })
+}
 
-pub async fn process_html_files_async<P, F, Fut>(directory: P, operation: F) -> Result<(), io::Error>
+/// Runs `operation` for every HTML file as a separate tokio task, with at most `limit`
+/// tasks running at once.
+///
+/// Parsing and visiting are CPU-bound, so do them inside the future with
+/// [`spawn_blocking`](tokio::task::spawn_blocking) rather than on the runtime's workers.
+/// The futures must be `Send`, which an `RcDom` is not: freeze the document with
+/// [`RcDom::freeze`](crate::rc_dom::RcDom::freeze) to hand it back from the blocking task.
+///
+/// After the first error no further files are started, the running tasks are aborted, and
+/// that error is returned.
+pub async fn process_html_files_async<P, F, Fut>(
+    directory: P,
+    limit: usize,
+    operation: F,
+) -> Result<(), io::Error>
 where
     P: AsRef<Path>,
-    F: Fn(&Path, &Path) -> Fut,
-    Fut: std::future::Future<Output = Result<(), io::Error>>,
+    F: Fn(PathBuf, PathBuf) -> Fut,
+    Fut: Future<Output = Result<(), io::Error>> + Send + 'static,
 {
-    let base_path = directory.as_ref().to_path_buf();
-    for entry in WalkDir::new(&base_path).into_iter() {
-        let entry = entry.map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-        let path = entry.path();
-        if path.is_file() && path.extension().map_or(false, |ext| ext == "html") {
-            let relative_path = path
-                .strip_prefix(&base_path)
-                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-            operation(path, relative_path).await?;
+    let directory = directory.as_ref().to_path_buf();
+    let files = task::spawn_blocking(move || html_files(directory))
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))??;
+
+    let mut tasks = JoinSet::new();
+    for (path, relative_path) in files {
+        if tasks.len() >= limit.max(1) {
+            join_next(&mut tasks).await?;
         }
+        tasks.spawn(operation(path, relative_path));
     }
+    while join_next(&mut tasks).await? {}
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Waits for one task of `tasks`, returning whether there was one.
+async fn join_next(tasks: &mut JoinSet<Result<(), io::Error>>) -> Result<bool, io::Error> {
+    match tasks.join_next().await {
+        Some(result) => result
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
+            .map(|()| true),
+        None => Ok(false),
+    }
+}
+
+/// The paths of every HTML file under `directory`, with their paths relative to it.
+fn html_files<P: AsRef<Path>>(directory: P) -> Result<Vec<(PathBuf, PathBuf)>, io::Error> {
+    let mut files = Vec::new();
+    process_html_files(directory, |path, relative_path| {
+        files.push((path.to_path_buf(), relative_path.to_path_buf()));
+        Ok(())
+    })?;
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rc_dom::RcDom;
+    use std::{
+        fs,
+        sync::{Arc, Mutex},
+    };
+
+    #[test]
+    fn test_parallel_visits_every_file() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.html", "b.html", "nested/c.html", "notes.txt"] {
+            let path = dir.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "<p>text</p>").unwrap();
+        }
+
+        let seen = Mutex::new(Vec::new());
+        process_html_files_parallel(dir.path(), |_, relative_path| {
+            seen.lock().unwrap().push(relative_path.to_path_buf());
+            Ok(())
+        })
+        .unwrap();
+
+        let mut seen = seen.into_inner().unwrap();
+        seen.sort();
+        let expected: Vec<PathBuf> = ["a.html", "b.html", "nested/c.html"]
+            .iter()
+            .map(PathBuf::from)
+            .collect();
+        assert_eq!(seen, expected);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_async_visits_every_file() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.html", "nested/b.html", "notes.txt"] {
+            let path = dir.path().join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, format!("<p>{}</p>", name)).unwrap();
+        }
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        process_html_files_async(dir.path(), 2, |path, relative_path| {
+            let seen = seen.clone();
+            async move {
+                let frozen =
+                    task::spawn_blocking(move || RcDom::from_file(&path).map(|dom| dom.freeze()))
+                        .await
+                        .unwrap()?;
+                tokio::task::yield_now().await;
+                let html = frozen.thaw().document.to_html_string();
+                seen.lock().unwrap().push((relative_path, html));
+                Ok(())
+            }
+        })
+        .await
+        .unwrap();
+
+        let mut seen = seen.lock().unwrap().clone();
+        seen.sort();
+        assert_eq!(seen.len(), 2);
+        assert_eq!(seen[0].0, PathBuf::from("a.html"));
+        assert!(seen[0].1.contains("<p>a.html</p>"));
+        assert_eq!(seen[1].0, PathBuf::from("nested/b.html"));
+        assert!(seen[1].1.contains("<p>nested/b.html</p>"));
+    }
+
+    #[tokio::test]
+    async fn test_async_returns_first_error() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["a.html", "b.html"] {
+            fs::write(dir.path().join(name), "<p>text</p>").unwrap();
+        }
+
+        let result = process_html_files_async(dir.path(), 4, |_, relative_path| async move {
+            if relative_path == Path::new("b.html") {
+                Err(io::Error::new(io::ErrorKind::InvalidData, "bad page"))
+            } else {
+                Ok(())
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap_err().to_string(), "bad page");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_async_bounds_running_tasks() {
+        let dir = tempfile::tempdir().unwrap();
+        for index in 0..8 {
+            fs::write(dir.path().join(format!("{}.html", index)), "<p>text</p>").unwrap();
+        }
+
+        let running = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        process_html_files_async(dir.path(), 3, |_, _| {
+            let (running, most) = (running.clone(), most.clone());
+            async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+                Ok(())
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(most.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,403 @@
+//! Whitespace normalization following the CSS `white-space: normal` rules.
+//!
+//! Runs of spaces, tabs and line breaks collapse to a single space, including across
+//! inline element boundaries, and spaces at the start and end of a line are removed.
+//! Preformatted content, such as `<pre>`, `<textarea>` and `<code>`, is left untouched.
+
+use crate::{
+    rc_dom::{Handle, Node, NodeData},
+    util::{element_name, parent},
+};
+use std::collections::HashMap;
+
+/// Elements whose content is preformatted or not rendered as text.
+const PREFORMATTED_ELEMENTS: &[&str] = &[
+    "pre",
+    "textarea",
+    "code",
+    "listing",
+    "plaintext",
+    "xmp",
+    "script",
+    "style",
+    "template",
+];
+
+/// Elements that start a new line, so that whitespace around them is not rendered.
+const BLOCK_ELEMENTS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "br",
+    "caption",
+    "dd",
+    "details",
+    "dialog",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hgroup",
+    "hr",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "tr",
+    "ul",
+];
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WhitespaceOptions {
+    /// Keep non-breaking spaces, as in "10&nbsp;mg", instead of turning them into
+    /// ordinary, collapsible spaces.
+    pub preserve_nbsp: bool,
+}
+
+/// Collapses each run of whitespace in `text` to a single space.
+pub fn collapse_whitespace(text: &str, options: &WhitespaceOptions) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut in_whitespace = false;
+    for c in text.chars() {
+        let is_space = matches!(c, ' ' | '\t' | '\n' | '\r' | '\u{000C}')
+            || (c == '\u{00A0}' && !options.preserve_nbsp);
+        if is_space {
+            if !in_whitespace {
+                collapsed.push(' ');
+            }
+            in_whitespace = true;
+        } else {
+            collapsed.push(c);
+            in_whitespace = false;
+        }
+    }
+    collapsed
+}
+
+/// Collapses the whitespace of a standalone piece of text, and trims it.
+pub fn normalize_text(text: &str, options: &WhitespaceOptions) -> String {
+    collapse_whitespace(text, options)
+        .trim_matches(' ')
+        .to_string()
+}
+
+/// Whether whitespace in the element must be kept as is, either because of the element
+/// itself or because of a `white-space` declaration in its `style` attribute.
+fn is_preformatted(node: &Handle) -> bool {
+    let NodeData::Element {
+        ref name,
+        ref attrs,
+        ..
+    } = node.data
+    else {
+        return false;
+    };
+    if PREFORMATTED_ELEMENTS.contains(&name.local.as_ref()) {
+        return true;
+    }
+    attrs
+        .borrow()
+        .iter()
+        .filter(|attr| attr.name.local.as_ref() == "style")
+        .any(|attr| {
+            attr.value
+                .split(';')
+                .filter_map(|declaration| declaration.split_once(':'))
+                .any(|(property, value)| {
+                    let value = value.trim();
+                    property.trim().eq_ignore_ascii_case("white-space")
+                        && !value.eq_ignore_ascii_case("normal")
+                        && !value.eq_ignore_ascii_case("nowrap")
+                })
+        })
+}
+
+fn is_block(node: &Handle) -> bool {
+    element_name(node).is_some_and(|name| BLOCK_ELEMENTS.contains(&name))
+}
+
+/// Normalizes the whitespace of the text inside `element`, in place, as a browser would
+/// render it. Text nodes left empty are removed.
+pub fn normalize_whitespace(element: &Handle, options: &WhitespaceOptions) {
+    if is_preformatted(element) || ancestors(element).any(|ancestor| is_preformatted(&ancestor)) {
+        return;
+    }
+    let mut state = LineState::default();
+    state.normalize_children(element, options);
+    state.end_line();
+    for (text, normalized) in state.texts {
+        if let NodeData::Text { ref contents } = text.data {
+            *contents.borrow_mut() = normalized.into();
+        }
+    }
+    remove_empty_text(element);
+}
+
+/// Normalizes a single text node as [`normalize_whitespace`] would normalize it in its
+/// block, without changing the tree: whitespace is trimmed where the text starts or ends
+/// a line, and kept next to the inline content rendered before and after it.
+///
+/// This lays out the whole block; use a [`TextNormalizer`] for many nodes of a document.
+pub fn normalize_text_node(text: &Handle, options: &WhitespaceOptions) -> String {
+    TextNormalizer::new(*options).normalize(text)
+}
+
+/// Normalizes text nodes as [`normalize_text_node`] does, laying out each block once for
+/// all of its text nodes rather than once per node.
+///
+/// Results are computed when a block is first reached, so the text nodes of a block
+/// should be normalized before the block is changed.
+#[derive(Default)]
+pub struct TextNormalizer {
+    options: WhitespaceOptions,
+    /// The normalized text of the text nodes laid out but not asked for yet. The handles
+    /// keep the nodes, and so the keys, alive.
+    texts: HashMap<*const Node, (Handle, String)>,
+}
+
+impl TextNormalizer {
+    pub fn new(options: WhitespaceOptions) -> Self {
+        TextNormalizer {
+            options,
+            texts: HashMap::new(),
+        }
+    }
+
+    /// The normalized text of a text node, as [`normalize_text_node`] returns it.
+    pub fn normalize(&mut self, text: &Handle) -> String {
+        let NodeData::Text { ref contents } = text.data else {
+            return String::new();
+        };
+        if let Some((_, normalized)) = self.texts.remove(&(&**text as *const Node)) {
+            return normalized;
+        }
+        if ancestors(text).any(|ancestor| is_preformatted(&ancestor)) {
+            return contents.borrow().to_string();
+        }
+        // Lines are laid out by the nearest block
+        let Some(container) = ancestors(text)
+            .find(is_block)
+            .or_else(|| ancestors(text).last())
+        else {
+            return normalize_text(&contents.borrow(), &self.options);
+        };
+
+        let mut state = LineState::default();
+        state.normalize_children(&container, &self.options);
+        state.end_line();
+        for (node, normalized) in state.texts {
+            self.texts.insert(&*node as *const Node, (node, normalized));
+        }
+        self.texts
+            .remove(&(&**text as *const Node))
+            .map(|(_, normalized)| normalized)
+            .unwrap_or_default()
+    }
+}
+
+/// The ancestors of a node, nearest first.
+fn ancestors(node: &Handle) -> impl Iterator<Item = Handle> {
+    std::iter::successors(parent(node), parent)
+}
+
+/// Tracks the rendered line while walking text in document order.
+#[derive(Default)]
+struct LineState {
+    /// Whether content has been rendered on the current line.
+    in_line: bool,
+    /// The index in `texts` of the text ending with a collapsible space, if the line ends
+    /// with one.
+    trailing_space: Option<usize>,
+    /// The text nodes walked so far, with their normalized text.
+    texts: Vec<(Handle, String)>,
+}
+
+impl LineState {
+    fn normalize_children(&mut self, parent: &Handle, options: &WhitespaceOptions) {
+        for child in parent.children.borrow().iter() {
+            match child.data {
+                NodeData::Text { ref contents } => {
+                    let mut text = collapse_whitespace(&contents.borrow(), options);
+                    if !self.in_line || self.trailing_space.is_some() {
+                        text = text.trim_start_matches(' ').to_string();
+                    }
+                    if !text.is_empty() {
+                        self.in_line = true;
+                        self.trailing_space = text.ends_with(' ').then_some(self.texts.len());
+                    }
+                    self.texts.push((child.clone(), text));
+                }
+                NodeData::Element { .. } => {
+                    let is_block = is_block(child);
+                    if is_block {
+                        self.end_line();
+                    }
+                    if is_preformatted(child) {
+                        self.in_line = true;
+                        self.trailing_space = None;
+                    } else {
+                        if !is_block && child.children.borrow().is_empty() {
+                            // Replaced content, such as an image
+                            self.in_line = true;
+                            self.trailing_space = None;
+                        }
+                        self.normalize_children(child, options);
+                    }
+                    if is_block {
+                        self.end_line();
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Removes the space at the end of the current line, and starts a new one.
+    fn end_line(&mut self) {
+        if let Some(index) = self.trailing_space.take() {
+            let text = &mut self.texts[index].1;
+            text.truncate(text.trim_end_matches(' ').len());
+        }
+        self.in_line = false;
+    }
+}
+
+fn remove_empty_text(element: &Handle) {
+    if is_preformatted(element) {
+        return;
+    }
+    element
+        .children
+        .borrow_mut()
+        .retain(|child| match child.data {
+            NodeData::Text { ref contents } => !contents.borrow().is_empty(),
+            _ => true,
+        });
+    for child in element.children.borrow().iter() {
+        remove_empty_text(child);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rc_dom::RcDom;
+
+    fn normalize(html: &str, options: &WhitespaceOptions) -> String {
+        let dom = RcDom::from_fragment_str(html);
+        let root = dom.fragment_root();
+        normalize_whitespace(&root, options);
+        root.inner_html()
+    }
+
+    #[test]
+    fn test_keeps_inter_element_spaces() {
+        let options = WhitespaceOptions::default();
+        assert_eq!(
+            normalize(
+                "<p>\n  Hello<b> world</b> <i>again</i>\n  and\n</p>",
+                &options
+            ),
+            "<p>Hello<b> world</b> <i>again</i> and</p>"
+        );
+        assert_eq!(
+            normalize("<p>Hello <b> world </b> !<br>\n next</p>", &options),
+            "<p>Hello <b>world </b>!<br>next</p>"
+        );
+    }
+
+    #[test]
+    fn test_keeps_preformatted_content() {
+        let options = WhitespaceOptions::default();
+        assert_eq!(
+            normalize(
+                "<div>Run  <code>a  b</code>:<pre>\n x\n   y</pre><span style=\"white-space: pre-wrap\"> z </span></div>",
+                &options
+            ),
+            "<div>Run <code>a  b</code>:<pre> x\n   y</pre><span style=\"white-space: pre-wrap\"> z </span></div>"
+        );
+    }
+
+    #[test]
+    fn test_keeps_preformatted_inline_content() {
+        let options = WhitespaceOptions::default();
+        assert_eq!(
+            normalize("<pre><b>  x    y  </b></pre>", &options),
+            "<pre><b>  x    y  </b></pre>"
+        );
+        let dom = RcDom::from_fragment_str(
+            "<pre><b>  x    y  </b></pre><div style=\"white-space: pre\"><i> a  b </i></div>",
+        );
+        let root = dom.fragment_root();
+        let text = |path: &[usize]| {
+            let mut node = root.clone();
+            for index in path {
+                let child = node.children.borrow()[*index].clone();
+                node = child;
+            }
+            normalize_text_node(&node, &options)
+        };
+        assert_eq!(text(&[0, 0, 0]), "  x    y  ");
+        assert_eq!(text(&[1, 0, 0]), " a  b ");
+        normalize_whitespace(&root.children.borrow()[0].children.borrow()[0], &options);
+        assert_eq!(
+            root.inner_html().split("</pre>").next(),
+            Some("<pre><b>  x    y  </b>")
+        );
+    }
+
+    #[test]
+    fn test_normalizes_text_node_in_line() {
+        let options = WhitespaceOptions::default();
+        let dom = RcDom::from_fragment_str("<div>Hello<b> world</b> again <i> </i></div>");
+        let div = dom.fragment_root().children.borrow()[0].clone();
+        let children = div.children.borrow().clone();
+        let b_text = children[1].children.borrow()[0].clone();
+        assert_eq!(normalize_text_node(&children[0], &options), "Hello");
+        assert_eq!(normalize_text_node(&b_text, &options), " world");
+        assert_eq!(normalize_text_node(&children[2], &options), " again");
+        assert_eq!(div.inner_html(), "Hello<b> world</b> again <i> </i>");
+
+        let mut normalizer = TextNormalizer::new(options);
+        assert_eq!(normalizer.normalize(&children[0]), "Hello");
+        assert_eq!(normalizer.texts.len(), 3);
+        assert_eq!(normalizer.normalize(&b_text), " world");
+        assert_eq!(normalizer.normalize(&children[2]), " again");
+        assert_eq!(normalizer.texts.len(), 1);
+    }
+
+    #[test]
+    fn test_nbsp() {
+        assert_eq!(
+            normalize_text(" 10\u{00A0} mg ", &WhitespaceOptions::default()),
+            "10 mg"
+        );
+        let options = WhitespaceOptions {
+            preserve_nbsp: true,
+        };
+        assert_eq!(normalize_text(" 10\u{00A0}mg ", &options), "10\u{00A0}mg");
+    }
+}
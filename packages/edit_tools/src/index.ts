@@ -1,2 +1,3 @@
 export { RichText } from './lib/rich_text_2';
-
+export { DEFAULT_RICH_TEXT_MARKUP, defineRichText } from './lib/markup';
+export type { RichTextMarkup, RichTextElementClass } from './lib/markup';
@@ -0,0 +1,34 @@
+// The element wrapping each rich-text block, and the attribute holding its id.
+// Mirrors `RichTextMarkup` in the html_ops crate: pages generated with
+// `--rich-text-tag` or `--rich-text-id-attribute` must be hydrated with the same values.
+export interface RichTextMarkup {
+  tag: string;
+  idAttribute: string;
+}
+
+export const DEFAULT_RICH_TEXT_MARKUP: RichTextMarkup = {
+  tag: 'rich-text',
+  idAttribute: 'id',
+};
+
+// A rich-text element class, which reads its block id from `markup.idAttribute`.
+export type RichTextElementClass = CustomElementConstructor & { markup: RichTextMarkup };
+
+// Registers `element` under the configured tag, unless that tag is already defined.
+// Each tag gets its own subclass, so that one page can host several configurations.
+export function defineRichText(
+  element: RichTextElementClass,
+  markup: Partial<RichTextMarkup> = {},
+): RichTextMarkup {
+  const resolved: RichTextMarkup = {
+    tag: (markup.tag || DEFAULT_RICH_TEXT_MARKUP.tag).toLowerCase(),
+    idAttribute: (markup.idAttribute || DEFAULT_RICH_TEXT_MARKUP.idAttribute).toLowerCase(),
+  };
+  if (!customElements.get(resolved.tag)) {
+    const base = element as unknown as { new (): HTMLElement; markup: RichTextMarkup };
+    customElements.define(resolved.tag, class extends base {
+      static markup = resolved;
+    });
+  }
+  return resolved;
+}
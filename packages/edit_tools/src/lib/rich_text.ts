@@ -7,9 +7,10 @@ import { $isAtNodeEnd } from "@lexical/selection";
 import { mergeRegister } from '@lexical/utils';
 import { $createParagraphNode, $createTextNode, $getRoot, $getSelection, $isElementNode, $isRangeSelection, CAN_REDO_COMMAND, CAN_UNDO_COMMAND, COMMAND_PRIORITY_EDITOR, COMMAND_PRIORITY_LOW, createEditor, ElementFormatType, FORMAT_ELEMENT_COMMAND, FORMAT_TEXT_COMMAND, LexicalEditor, LineBreakNode, ParagraphNode, RangeSelection, REDO_COMMAND, TextFormatType, TextNode, UNDO_COMMAND } from 'lexical';
 import { css, html, LitElement } from 'lit';
-import { customElement, property, state } from 'lit/decorators.js';
+import { property, state } from 'lit/decorators.js';
 import { createRef, ref, Ref } from 'lit/directives/ref.js';
 import { styleMap } from 'lit/directives/style-map.js';
+import { DEFAULT_RICH_TEXT_MARKUP, RichTextMarkup } from './markup';
 import { getRichText, postRichText } from './requests';
 
 // Define a simple Result type
@@ -55,10 +56,22 @@ export default function $prepopulatedRichText() {
 
 
 
-@customElement('rich-text')
+// Registered with `defineRichText`, which sets the tag and id attribute.
 export class RichText extends LitElement {
+  static markup: RichTextMarkup = DEFAULT_RICH_TEXT_MARKUP;
+
   @property({ type: Boolean, reflect: true }) editable = true;
-  @property({ type: String, reflect: true }) id = "";
+
+  // The id of the block, kept in the configured id attribute
+  get blockId(): string {
+    const { markup } = this.constructor as typeof RichText;
+    return this.getAttribute(markup.idAttribute) ?? '';
+  }
+
+  set blockId(id: string) {
+    const { markup } = this.constructor as typeof RichText;
+    this.setAttribute(markup.idAttribute, id);
+  }
   @state() private _editor: LexicalEditor;
   @state() private canUndo = false;
   @state() private canRedo = false;
@@ -437,11 +450,11 @@ export class RichText extends LitElement {
       });
     });
 
-    const result = await RichTextRequest.post(this.id, htmlString);
+    const result = await RichTextRequest.post(this.blockId, htmlString);
     if (result.ok) {
       console.log('Content saved successfully. New ID:', result.value);
       // Optionally update the id property if a new one is returned
-      this.id = result.value;
+      this.blockId = result.value;
     } else {
       console.error('Failed to save content:', result.error);
       // Handle the error (e.g., show an error message to the user)
@@ -449,12 +462,12 @@ export class RichText extends LitElement {
   }
 
   async loadContent(): Promise<boolean> {
-    if (!this.id) {
+    if (!this.blockId) {
       console.warn('No ID provided for loading content');
       return false;
     }
 
-    const result = await RichTextRequest.get(this.id);
+    const result = await RichTextRequest.get(this.blockId);
     if (result.ok) {
       const htmlContent = result.value; // Assuming this is now an HTML string
       this._editor.update(() => {
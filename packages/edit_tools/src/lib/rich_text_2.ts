@@ -1,17 +1,23 @@
 import { ListItemNode, ListNode } from '@lexical/list';
 import { css, html, LitElement } from 'lit';
-import { customElement, property, state } from 'lit/decorators.js';
+import { state } from 'lit/decorators.js';
 
 import { $generateHtmlFromNodes, $generateNodesFromDOM } from '@lexical/html';
 import { LinkNode } from '@lexical/link';
 import { HeadingNode, QuoteNode } from '@lexical/rich-text';
 import { $createParagraphNode, $createTextNode, $getRoot, createEditor, LexicalEditor, LineBreakNode, ParagraphNode, TextNode } from 'lexical';
 import { createRef, ref, Ref } from 'lit/directives/ref.js';
+import { DEFAULT_RICH_TEXT_MARKUP, RichTextMarkup } from './markup';
 
-@customElement('rich-text')
+// Registered with `defineRichText`, which sets the tag and id attribute.
 export class RichText extends LitElement {
-  @property({ type: String })
-  id!: string;
+  static markup: RichTextMarkup = DEFAULT_RICH_TEXT_MARKUP;
+
+  // The id of the block, read from the configured id attribute
+  get blockId(): string {
+    const { markup } = this.constructor as typeof RichText;
+    return this.getAttribute(markup.idAttribute) ?? '';
+  }
 
 
 
@@ -118,21 +124,12 @@ export class RichText extends LitElement {
     }
   `;
 
-  // async updated(changedProperties: Map<string, any>) {
-  //   if (changedProperties.has('id')) {
-  //     const nodes = await this.fetchRichText();
-  //     if(nodes){
-  //       this.richTextNodes = nodes;
-  //     }
-  //   }
-  // }
-
   async fetchRichText() {
     this.loading = true;
     this.error = null;
 
     try {
-      const response = await fetch(`http://127.0.0.1:3001/rich-text/${this.id}`);
+      const response = await fetch(`http://127.0.0.1:3001/rich-text/${this.blockId}`);
       if (!response.ok) {
         throw new Error(response.status === 400
           ? 'Bad request. Please check the rich text ID.'
@@ -170,7 +167,7 @@ export class RichText extends LitElement {
           'Content-Type': 'application/json',
         },
         body: JSON.stringify({
-          id: this.id,
+          id: this.blockId,
           rich_text: html
         }),
       });
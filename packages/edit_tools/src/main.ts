@@ -1,14 +1,12 @@
-import { RichText } from './index';
+import { defineRichText, RichText } from './index';
 import './style.css';
 import page from '../../../assets/output/index.html?raw';
 
-
-// Define the custom element if it's not already defined
-if (!customElements.get('rich-text')) {
-  customElements.define('rich-text', RichText);
-}
+// Use the same tag and id attribute as the CLI run that generated the page
+defineRichText(RichText, {
+  tag: import.meta.env.VITE_RICH_TEXT_TAG,
+  idAttribute: import.meta.env.VITE_RICH_TEXT_ID_ATTRIBUTE,
+});
 
 const root = document.querySelector<HTMLDivElement>('#app')!;
-
-
 root.innerHTML = page;
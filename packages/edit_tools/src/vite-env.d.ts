@@ -1 +1,6 @@
 /// <reference types="vite/client" />
+
+interface ImportMetaEnv {
+  readonly VITE_RICH_TEXT_TAG?: string;
+  readonly VITE_RICH_TEXT_ID_ATTRIBUTE?: string;
+}